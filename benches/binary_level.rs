@@ -0,0 +1,59 @@
+#![feature(test)]
+
+extern crate cgmath;
+extern crate simple_physics;
+extern crate test;
+
+use cgmath::vec2;
+use simple_physics::binary_level::{self, StaticEntity};
+use simple_physics::{AxisAlignedRect, Shape};
+use test::Bencher;
+
+const GRID_SIZE: u32 = 100;
+const TILE_SIZE: f64 = 16.;
+
+fn tile_level() -> Vec<StaticEntity> {
+    let mut entities = Vec::new();
+    for y in 0..GRID_SIZE {
+        for x in 0..GRID_SIZE {
+            let shade = ((x / 7 + y) % 3) as f32 / 2.;
+            entities.push(StaticEntity {
+                position: vec2(x as f64, y as f64) * TILE_SIZE,
+                shape: Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(
+                    TILE_SIZE, TILE_SIZE,
+                ))),
+                colour: [shade, 1., 1.],
+            });
+        }
+    }
+    entities
+}
+
+#[bench]
+fn decode_binary_tile_level(b: &mut Bencher) {
+    let bytes = binary_level::encode(&tile_level());
+    b.iter(|| binary_level::decode(&bytes).unwrap());
+}
+
+#[cfg(feature = "ron_level")]
+#[bench]
+fn parse_ron_tile_level(b: &mut Bencher) {
+    use simple_physics::ron_level;
+    let mut ron = String::from("(entities: [\n");
+    for entity in tile_level().iter() {
+        ron.push_str(&format!(
+            "(kind: Static, position: (x: {:?}, y: {:?}), \
+             shape: Some(Rect(dimensions: (x: {:?}, y: {:?}))), \
+             colour: ({:?}, {:?}, {:?})),\n",
+            entity.position.x,
+            entity.position.y,
+            TILE_SIZE,
+            TILE_SIZE,
+            entity.colour[0],
+            entity.colour[1],
+            entity.colour[2],
+        ));
+    }
+    ron.push_str("])\n");
+    b.iter(|| ron_level::parse(&ron).unwrap());
+}
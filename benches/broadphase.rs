@@ -0,0 +1,65 @@
+#![feature(test)]
+
+extern crate cgmath;
+extern crate simple_physics;
+extern crate test;
+
+use cgmath::vec2;
+use simple_physics::loose_quad_tree::LooseQuadTree;
+use simple_physics::Aabb;
+use test::Bencher;
+
+const NUM_TILES: u32 = 5000;
+const NUM_MOVERS: u32 = 50;
+
+// 5000 static tiles and 50 small movers, as in a large tile level.
+fn tiles_and_movers() -> (Vec<Aabb>, Vec<Aabb>) {
+    let tiles = (0..NUM_TILES)
+        .map(|i| {
+            let position = vec2((i % 100) as f64 * 40., 400. + (i / 100) as f64 * 70.);
+            Aabb::new(position, vec2(32., 32.))
+        })
+        .collect();
+    let movers = (0..NUM_MOVERS)
+        .map(|i| Aabb::new(vec2(i as f64 * 70., 10.), vec2(20., 20.)))
+        .collect();
+    (tiles, movers)
+}
+
+fn populated_tree(tiles: &[Aabb], movers: &[Aabb]) -> LooseQuadTree<u32> {
+    let mut quad_tree = LooseQuadTree::new(vec2(4000., 4000.));
+    for (id, &aabb) in tiles.iter().chain(movers.iter()).enumerate() {
+        quad_tree.insert(aabb, id as u32);
+    }
+    quad_tree
+}
+
+// Moves each mover back and forth, relocating only the movers.
+#[bench]
+fn incremental_update(b: &mut Bencher) {
+    let (tiles, movers) = tiles_and_movers();
+    let mut quad_tree = populated_tree(&tiles, &movers);
+    let mut current = movers.clone();
+    let mut step = vec2(0., 1.);
+    b.iter(|| {
+        for (i, aabb) in current.iter_mut().enumerate() {
+            let moved = Aabb::new(aabb.top_left() + step, aabb.size());
+            quad_tree.update(*aabb, moved, NUM_TILES + i as u32);
+            *aabb = moved;
+        }
+        step = -step;
+    });
+}
+
+// Clears the tree and reinserts every entity, as each rebuild used to.
+#[bench]
+fn full_rebuild(b: &mut Bencher) {
+    let (tiles, movers) = tiles_and_movers();
+    let mut quad_tree = populated_tree(&tiles, &movers);
+    b.iter(|| {
+        quad_tree.clear();
+        for (id, &aabb) in tiles.iter().chain(movers.iter()).enumerate() {
+            quad_tree.insert(aabb, id as u32);
+        }
+    });
+}
@@ -1,6 +1,7 @@
 use cgmath::{vec2, Vector2};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct Aabb {
     top_left: Vector2<f64>,
     size: Vector2<f64>,
@@ -25,7 +26,10 @@ impl Aabb {
         let size = half_size * 2.;
         Self::new(top_left, size)
     }
-    fn bottom_right_coord(&self) -> Vector2<f64> {
+    pub fn top_left(&self) -> Vector2<f64> {
+        self.top_left
+    }
+    pub fn bottom_right_coord(&self) -> Vector2<f64> {
         self.top_left + self.size
     }
     pub fn from_union(a: &Aabb, b: &Aabb) -> Self {
@@ -48,15 +52,58 @@ impl Aabb {
     pub fn size(&self) -> Vector2<f64> {
         self.size
     }
+    pub fn corners(&self) -> [Vector2<f64>; 4] {
+        let bottom_right = self.bottom_right_coord();
+        [
+            self.top_left,
+            vec2(bottom_right.x, self.top_left.y),
+            bottom_right,
+            vec2(self.top_left.x, bottom_right.y),
+        ]
+    }
     pub fn is_intersecting(&self, other: &Aabb) -> bool {
         self.top_left.x + self.size.x >= other.top_left.x
             && other.top_left.x + other.size.x >= self.top_left.x
             && self.top_left.y + self.size.y >= other.top_left.y
             && other.top_left.y + other.size.y >= self.top_left.y
     }
+    pub fn is_overlapping(&self, other: &Aabb) -> bool {
+        self.top_left.x + self.size.x > other.top_left.x
+            && other.top_left.x + other.size.x > self.top_left.x
+            && self.top_left.y + self.size.y > other.top_left.y
+            && other.top_left.y + other.size.y > self.top_left.y
+    }
+    pub fn inset(&self, amount: f64) -> Self {
+        let offset = vec2(amount, amount);
+        Self::new(self.top_left + offset, self.size - offset * 2.)
+    }
+    pub fn offset_to_contain(&self, inner: &Aabb) -> Vector2<f64> {
+        let inner_bottom_right = inner.bottom_right_coord();
+        let bottom_right = self.bottom_right_coord();
+        let axis = |inner_min: f64, inner_max: f64, min: f64, max: f64| {
+            if inner_min < min {
+                min - inner_min
+            } else if inner_max > max {
+                max - inner_max
+            } else {
+                0.
+            }
+        };
+        vec2(
+            axis(inner.top_left.x, inner_bottom_right.x, self.top_left.x, bottom_right.x),
+            axis(inner.top_left.y, inner_bottom_right.y, self.top_left.y, bottom_right.y),
+        )
+    }
     pub fn centre(&self) -> Vector2<f64> {
         self.top_left + self.size / 2.
     }
+    pub fn closest_point(&self, point: Vector2<f64>) -> Vector2<f64> {
+        let bottom_right = self.bottom_right_coord();
+        vec2(
+            point.x.max(self.top_left.x).min(bottom_right.x),
+            point.y.max(self.top_left.y).min(bottom_right.y),
+        )
+    }
     pub fn split_four(&self) -> AabbSplitFour {
         let size = self.size / 2.;
         AabbSplitFour {
@@ -2,40 +2,99 @@ use aabb::Aabb;
 use cgmath::{vec2, Vector2};
 use collide::{channels, flags, Collide, Edge};
 use left_solid_edge::EPSILON;
+use std::ops::BitOr;
 
-#[derive(Debug, Clone, Copy)]
-enum Metadata {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct EdgeFlags(u8);
+
+impl EdgeFlags {
+    pub const NONE: Self = EdgeFlags(0);
+    pub const TOP: Self = EdgeFlags(1 << 0);
+    pub const BOTTOM: Self = EdgeFlags(1 << 1);
+    pub const LEFT: Self = EdgeFlags(1 << 2);
+    pub const RIGHT: Self = EdgeFlags(1 << 3);
+    pub const ALL: Self = EdgeFlags(0xf);
+
+    pub fn from_bits(bits: u8) -> Self {
+        EdgeFlags(bits & Self::ALL.0)
+    }
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for EdgeFlags {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        EdgeFlags(self.0 | other.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum Metadata {
     Main,
     Character,
     FloorOnly,
+    CeilingOnly,
+    WallLeftOnly,
+    WallRightOnly,
+    Sensor,
 }
 
 impl Metadata {
+    fn is_one_way(self) -> bool {
+        match self {
+            Metadata::Main | Metadata::Character | Metadata::Sensor => false,
+            _ => true,
+        }
+    }
+    pub fn default_solid_edges(self) -> EdgeFlags {
+        match self {
+            Metadata::Main | Metadata::Character | Metadata::Sensor => EdgeFlags::ALL,
+            Metadata::FloorOnly => EdgeFlags::TOP,
+            Metadata::CeilingOnly => EdgeFlags::BOTTOM,
+            Metadata::WallLeftOnly => EdgeFlags::LEFT,
+            Metadata::WallRightOnly => EdgeFlags::RIGHT,
+        }
+    }
     fn top_channel(self) -> u32 {
         match self {
             Metadata::FloorOnly => channels::FLOOR,
-            Metadata::Character => channels::MAIN,
+            Metadata::CeilingOnly | Metadata::WallLeftOnly | Metadata::WallRightOnly => 0,
+            Metadata::Character => channels::MAIN | channels::CEILING,
+            Metadata::Sensor => channels::SENSOR,
             _ => channels::MAIN,
         }
     }
     fn bottom_channel(self) -> u32 {
         match self {
-            Metadata::FloorOnly => 0,
+            Metadata::CeilingOnly => channels::CEILING,
+            Metadata::FloorOnly | Metadata::WallLeftOnly | Metadata::WallRightOnly => 0,
             Metadata::Character => channels::MAIN | channels::FLOOR,
+            Metadata::Sensor => channels::SENSOR,
             _ => channels::MAIN,
         }
     }
     fn left_channel(self) -> u32 {
         match self {
-            Metadata::FloorOnly => 0,
-            Metadata::Character => channels::MAIN,
+            Metadata::WallLeftOnly => channels::WALL,
+            Metadata::FloorOnly | Metadata::CeilingOnly | Metadata::WallRightOnly => 0,
+            Metadata::Character => channels::MAIN | channels::WALL,
+            Metadata::Sensor => channels::SENSOR,
             _ => channels::MAIN,
         }
     }
     fn right_channel(self) -> u32 {
         match self {
-            Metadata::FloorOnly => 0,
-            Metadata::Character => channels::MAIN,
+            Metadata::WallRightOnly => channels::WALL,
+            Metadata::FloorOnly | Metadata::CeilingOnly | Metadata::WallLeftOnly => 0,
+            Metadata::Character => channels::MAIN | channels::WALL,
+            Metadata::Sensor => channels::SENSOR,
             _ => channels::MAIN,
         }
     }
@@ -63,29 +122,59 @@ impl Metadata {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct AxisAlignedRect {
     dimensions: Vector2<f64>,
     metadata: Metadata,
+    solid_edges: EdgeFlags,
 }
 
 impl AxisAlignedRect {
     pub fn new(dimensions: Vector2<f64>) -> Self {
+        Self::with_solid_edges(dimensions, EdgeFlags::ALL)
+    }
+    pub fn new_character(dimensions: Vector2<f64>) -> Self {
+        Self::with_metadata(dimensions, Metadata::Character)
+    }
+    pub fn new_floor_only(dimensions: Vector2<f64>) -> Self {
+        Self::with_metadata(dimensions, Metadata::FloorOnly)
+    }
+    pub fn new_ceiling_only(dimensions: Vector2<f64>) -> Self {
+        Self::with_metadata(dimensions, Metadata::CeilingOnly)
+    }
+    pub fn new_wall_left_only(dimensions: Vector2<f64>) -> Self {
+        Self::with_metadata(dimensions, Metadata::WallLeftOnly)
+    }
+    pub fn new_wall_right_only(dimensions: Vector2<f64>) -> Self {
+        Self::with_metadata(dimensions, Metadata::WallRightOnly)
+    }
+    pub fn new_sensor(dimensions: Vector2<f64>) -> Self {
+        Self::with_metadata(dimensions, Metadata::Sensor)
+    }
+    pub fn with_solid_edges(dimensions: Vector2<f64>, solid_edges: EdgeFlags) -> Self {
         Self {
             dimensions,
             metadata: Metadata::Main,
+            solid_edges,
         }
     }
-    pub fn new_character(dimensions: Vector2<f64>) -> Self {
+    pub fn with_metadata(dimensions: Vector2<f64>, metadata: Metadata) -> Self {
         Self {
             dimensions,
-            metadata: Metadata::Character,
+            metadata,
+            solid_edges: metadata.default_solid_edges(),
         }
     }
-    pub fn new_floor_only(dimensions: Vector2<f64>) -> Self {
+    pub fn with_metadata_and_solid_edges(
+        dimensions: Vector2<f64>,
+        metadata: Metadata,
+        solid_edges: EdgeFlags,
+    ) -> Self {
         Self {
             dimensions,
-            metadata: Metadata::FloorOnly,
+            metadata,
+            solid_edges,
         }
     }
     fn top_left(&self) -> Vector2<f64> {
@@ -116,6 +205,18 @@ impl AxisAlignedRect {
     pub fn dimensions(&self) -> Vector2<f64> {
         self.dimensions
     }
+    pub fn metadata(&self) -> Metadata {
+        self.metadata
+    }
+    pub fn solid_edges(&self) -> EdgeFlags {
+        self.solid_edges
+    }
+    pub fn is_one_way(&self) -> bool {
+        self.metadata.is_one_way() || self.solid_edges != EdgeFlags::ALL
+    }
+    pub fn is_sensor(&self) -> bool {
+        self.metadata == Metadata::Sensor
+    }
 }
 
 impl Collide for AxisAlignedRect {
@@ -127,22 +228,22 @@ impl Collide for AxisAlignedRect {
         direction: Vector2<f64>,
         mut f: F,
     ) {
-        if direction.y > -EPSILON {
+        if direction.y > -EPSILON && self.solid_edges.contains(EdgeFlags::BOTTOM) {
             f(self.bottom()
                 .with_channels(self.metadata.bottom_channel())
                 .with_flags(self.metadata.bottom_flags()))
         }
-        if direction.y < EPSILON {
+        if direction.y < EPSILON && self.solid_edges.contains(EdgeFlags::TOP) {
             f(self.top()
                 .with_channels(self.metadata.top_channel())
                 .with_flags(self.metadata.top_flags()))
         }
-        if direction.x > -EPSILON {
+        if direction.x > -EPSILON && self.solid_edges.contains(EdgeFlags::RIGHT) {
             f(self.right()
                 .with_channels(self.metadata.right_channel())
                 .with_flags(self.metadata.right_flags()))
         }
-        if direction.x < EPSILON {
+        if direction.x < EPSILON && self.solid_edges.contains(EdgeFlags::LEFT) {
             f(self.left()
                 .with_channels(self.metadata.left_channel())
                 .with_flags(self.metadata.left_flags()))
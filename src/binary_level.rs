@@ -0,0 +1,501 @@
+use axis_aligned_rect::{AxisAlignedRect, EdgeFlags, Metadata};
+use capsule::Capsule;
+use cgmath::{vec2, Vector2};
+use circle::{self, Circle};
+use convex_polygon::ConvexPolygon;
+use line_segment::{LineSegment, Solidity};
+use shape::Shape;
+use std::cmp::Ordering;
+
+const MAGIC: &[u8] = b"SPSL";
+const VERSION: u8 = 1;
+
+const RECORD_SINGLE: u8 = 0;
+const RECORD_RUN: u8 = 1;
+
+// Counts in the file aren't trusted, so a malformed level can't ask for an
+// arbitrarily large allocation.
+const MAX_ENTITIES: u32 = 1 << 20;
+
+const SHAPE_AXIS_ALIGNED_RECT: u8 = 0;
+const SHAPE_LINE_SEGMENT: u8 = 1;
+const SHAPE_CIRCLE: u8 = 2;
+const SHAPE_CONVEX_POLYGON: u8 = 3;
+const SHAPE_CAPSULE: u8 = 4;
+
+const METADATA_MAIN: u8 = 0;
+const METADATA_CHARACTER: u8 = 1;
+const METADATA_FLOOR_ONLY: u8 = 2;
+const METADATA_CEILING_ONLY: u8 = 3;
+const METADATA_WALL_LEFT_ONLY: u8 = 4;
+const METADATA_WALL_RIGHT_ONLY: u8 = 5;
+const METADATA_SENSOR: u8 = 6;
+const METADATA_MASK: u8 = 0x7f;
+const HAS_SOLID_EDGES: u8 = 1 << 7;
+
+const SOLIDITY_BOTH: u8 = 0;
+const SOLIDITY_LEFT: u8 = 1;
+const SOLIDITY_RIGHT: u8 = 2;
+const SOLIDITY_MASK: u8 = 0x0f;
+const HAS_PREVIOUS: u8 = 1 << 4;
+const HAS_NEXT: u8 = 1 << 5;
+
+#[derive(Debug, Clone)]
+pub struct StaticEntity {
+    pub position: Vector2<f64>,
+    pub shape: Shape,
+    pub colour: [f32; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnexpectedEnd,
+    InvalidRecordTag(u8),
+    InvalidShapeTag(u8),
+    InvalidMetadata(u8),
+    InvalidSolidity(u8),
+    InvalidPolygon,
+    InvalidCircle,
+    TooManyEntities,
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+    fn u32(&mut self, value: u32) {
+        for i in 0..4 {
+            self.bytes.push((value >> (i * 8)) as u8);
+        }
+    }
+    fn u64(&mut self, value: u64) {
+        for i in 0..8 {
+            self.bytes.push((value >> (i * 8)) as u8);
+        }
+    }
+    fn f32(&mut self, value: f32) {
+        self.u32(value.to_bits());
+    }
+    fn f64(&mut self, value: f64) {
+        self.u64(value.to_bits());
+    }
+    fn vector2(&mut self, value: Vector2<f64>) {
+        self.f64(value.x);
+        self.f64(value.y);
+    }
+    fn colour(&mut self, colour: [f32; 3]) {
+        for &c in colour.iter() {
+            self.f32(c);
+        }
+    }
+    fn shape(&mut self, shape: &Shape) {
+        match shape {
+            Shape::AxisAlignedRect(rect) => {
+                self.u8(SHAPE_AXIS_ALIGNED_RECT);
+                self.vector2(rect.dimensions());
+                let metadata = match rect.metadata() {
+                    Metadata::Main => METADATA_MAIN,
+                    Metadata::Character => METADATA_CHARACTER,
+                    Metadata::FloorOnly => METADATA_FLOOR_ONLY,
+                    Metadata::CeilingOnly => METADATA_CEILING_ONLY,
+                    Metadata::WallLeftOnly => METADATA_WALL_LEFT_ONLY,
+                    Metadata::WallRightOnly => METADATA_WALL_RIGHT_ONLY,
+                    Metadata::Sensor => METADATA_SENSOR,
+                };
+                // Solid edges are only stored when they differ from the
+                // metadata's default, so older levels still decode.
+                if rect.solid_edges() == rect.metadata().default_solid_edges() {
+                    self.u8(metadata);
+                } else {
+                    self.u8(metadata | HAS_SOLID_EDGES);
+                    self.u8(rect.solid_edges().bits());
+                }
+            }
+            Shape::LineSegment(line_segment) => {
+                self.u8(SHAPE_LINE_SEGMENT);
+                self.vector2(line_segment.start);
+                self.vector2(line_segment.end);
+                let solidity = match line_segment.solidity() {
+                    Solidity::Both => SOLIDITY_BOTH,
+                    Solidity::Left => SOLIDITY_LEFT,
+                    Solidity::Right => SOLIDITY_RIGHT,
+                };
+                let (previous, next) = line_segment.neighbours();
+                let has_previous = if previous.is_some() { HAS_PREVIOUS } else { 0 };
+                let has_next = if next.is_some() { HAS_NEXT } else { 0 };
+                self.u8(solidity | has_previous | has_next);
+                if let Some(previous) = previous {
+                    self.vector2(previous);
+                }
+                if let Some(next) = next {
+                    self.vector2(next);
+                }
+            }
+            Shape::Circle(circle) => {
+                self.u8(SHAPE_CIRCLE);
+                self.f64(circle.radius());
+                self.u32(circle.num_edges());
+            }
+            Shape::ConvexPolygon(polygon) => {
+                self.u8(SHAPE_CONVEX_POLYGON);
+                self.u32(polygon.vertices().len() as u32);
+                for &vertex in polygon.vertices() {
+                    self.vector2(vertex);
+                }
+            }
+            Shape::Capsule(capsule) => {
+                self.u8(SHAPE_CAPSULE);
+                self.f64(capsule.half_height());
+                self.f64(capsule.radius());
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < n {
+            return Err(Error::UnexpectedEnd);
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(bytes
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, &b)| acc | (b as u32) << (i * 8)))
+    }
+    fn u64(&mut self) -> Result<u64, Error> {
+        let bytes = self.take(8)?;
+        Ok(bytes
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, &b)| acc | (b as u64) << (i * 8)))
+    }
+    fn f32(&mut self) -> Result<f32, Error> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+    fn f64(&mut self) -> Result<f64, Error> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+    fn vector2(&mut self) -> Result<Vector2<f64>, Error> {
+        let x = self.f64()?;
+        let y = self.f64()?;
+        Ok(vec2(x, y))
+    }
+    fn colour(&mut self) -> Result<[f32; 3], Error> {
+        Ok([self.f32()?, self.f32()?, self.f32()?])
+    }
+    fn shape(&mut self) -> Result<Shape, Error> {
+        match self.u8()? {
+            SHAPE_AXIS_ALIGNED_RECT => {
+                let dimensions = self.vector2()?;
+                let byte = self.u8()?;
+                let metadata = match byte & METADATA_MASK {
+                    METADATA_MAIN => Metadata::Main,
+                    METADATA_CHARACTER => Metadata::Character,
+                    METADATA_FLOOR_ONLY => Metadata::FloorOnly,
+                    METADATA_CEILING_ONLY => Metadata::CeilingOnly,
+                    METADATA_WALL_LEFT_ONLY => Metadata::WallLeftOnly,
+                    METADATA_WALL_RIGHT_ONLY => Metadata::WallRightOnly,
+                    METADATA_SENSOR => Metadata::Sensor,
+                    _ => return Err(Error::InvalidMetadata(byte)),
+                };
+                let solid_edges = if byte & HAS_SOLID_EDGES != 0 {
+                    EdgeFlags::from_bits(self.u8()?)
+                } else {
+                    metadata.default_solid_edges()
+                };
+                Ok(Shape::AxisAlignedRect(
+                    AxisAlignedRect::with_metadata_and_solid_edges(
+                        dimensions,
+                        metadata,
+                        solid_edges,
+                    ),
+                ))
+            }
+            SHAPE_LINE_SEGMENT => {
+                let start = self.vector2()?;
+                let end = self.vector2()?;
+                let byte = self.u8()?;
+                let solidity = match byte & SOLIDITY_MASK {
+                    SOLIDITY_BOTH => Solidity::Both,
+                    SOLIDITY_LEFT => Solidity::Left,
+                    SOLIDITY_RIGHT => Solidity::Right,
+                    _ => return Err(Error::InvalidSolidity(byte)),
+                };
+                let previous = if byte & HAS_PREVIOUS != 0 {
+                    Some(self.vector2()?)
+                } else {
+                    None
+                };
+                let next = if byte & HAS_NEXT != 0 {
+                    Some(self.vector2()?)
+                } else {
+                    None
+                };
+                let line_segment = LineSegment::with_solidity(start, end, solidity)
+                    .with_neighbours(previous, next);
+                Ok(Shape::LineSegment(line_segment))
+            }
+            SHAPE_CIRCLE => {
+                let radius = self.f64()?;
+                let num_edges = self.u32()?;
+                if num_edges > circle::MAX_NUM_EDGES {
+                    return Err(Error::InvalidCircle);
+                }
+                Ok(Shape::Circle(Circle::with_num_edges(radius, num_edges)))
+            }
+            SHAPE_CONVEX_POLYGON => {
+                let num_vertices = self.u32()?;
+                let vertices = (0..num_vertices)
+                    .map(|_| self.vector2())
+                    .collect::<Result<Vec<_>, _>>()?;
+                ConvexPolygon::from_vertices(vertices)
+                    .map(Shape::ConvexPolygon)
+                    .map_err(|_| Error::InvalidPolygon)
+            }
+            SHAPE_CAPSULE => {
+                let half_height = self.f64()?;
+                let radius = self.f64()?;
+                Ok(Shape::Capsule(Capsule::new(half_height, radius)))
+            }
+            other => Err(Error::InvalidShapeTag(other)),
+        }
+    }
+}
+
+// Shape and colour encoded together, used to decide whether neighbouring
+// entities can share a run record.
+fn appearance_bytes(entity: &StaticEntity) -> Vec<u8> {
+    let mut writer = Writer { bytes: Vec::new() };
+    writer.shape(&entity.shape);
+    writer.colour(entity.colour);
+    writer.bytes
+}
+
+fn run_position(start: Vector2<f64>, step: Vector2<f64>, i: u32) -> Vector2<f64> {
+    start + step * (i as f64)
+}
+
+fn run_length(entities: &[StaticEntity], appearance: &[u8]) -> u32 {
+    if entities.len() < 2 || appearance_bytes(&entities[1]) != appearance {
+        return 1;
+    }
+    let start = entities[0].position;
+    let step = entities[1].position - start;
+    let mut count = 2;
+    for entity in entities[2..].iter() {
+        if entity.position != run_position(start, step, count)
+            || appearance_bytes(entity) != appearance
+        {
+            break;
+        }
+        count += 1;
+    }
+    count
+}
+
+pub fn encode(entities: &[StaticEntity]) -> Vec<u8> {
+    let mut sorted = entities.to_vec();
+    sorted.sort_by(|a, b| {
+        a.position
+            .y
+            .partial_cmp(&b.position.y)
+            .unwrap_or(Ordering::Equal)
+            .then(
+                a.position
+                    .x
+                    .partial_cmp(&b.position.x)
+                    .unwrap_or(Ordering::Equal),
+            )
+    });
+
+    let mut records = Writer { bytes: Vec::new() };
+    let mut num_records = 0;
+    let mut remaining = &sorted[..];
+    while let Some(first) = remaining.first() {
+        let appearance = appearance_bytes(first);
+        let count = run_length(remaining, &appearance);
+        if count == 1 {
+            records.u8(RECORD_SINGLE);
+            records.vector2(first.position);
+        } else {
+            records.u8(RECORD_RUN);
+            records.u32(count);
+            records.vector2(first.position);
+            records.vector2(remaining[1].position - first.position);
+        }
+        records.bytes.extend_from_slice(&appearance);
+        num_records += 1;
+        remaining = &remaining[count as usize..];
+    }
+
+    let mut writer = Writer {
+        bytes: Vec::with_capacity(MAGIC.len() + 5 + records.bytes.len()),
+    };
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.u8(VERSION);
+    writer.u32(num_records);
+    writer.bytes.extend_from_slice(&records.bytes);
+    writer.bytes
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<StaticEntity>, Error> {
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len()).map_err(|_| Error::BadMagic)? != MAGIC {
+        return Err(Error::BadMagic);
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    let num_records = reader.u32()?;
+    let mut entities = Vec::new();
+    for _ in 0..num_records {
+        match reader.u8()? {
+            RECORD_SINGLE => {
+                if entities.len() as u32 == MAX_ENTITIES {
+                    return Err(Error::TooManyEntities);
+                }
+                let position = reader.vector2()?;
+                let shape = reader.shape()?;
+                let colour = reader.colour()?;
+                entities.push(StaticEntity {
+                    position,
+                    shape,
+                    colour,
+                });
+            }
+            RECORD_RUN => {
+                let count = reader.u32()?;
+                let start = reader.vector2()?;
+                let step = reader.vector2()?;
+                let shape = reader.shape()?;
+                let colour = reader.colour()?;
+                if count > MAX_ENTITIES - entities.len() as u32 {
+                    return Err(Error::TooManyEntities);
+                }
+                for i in 0..count {
+                    entities.push(StaticEntity {
+                        position: run_position(start, step, i),
+                        shape: shape.clone(),
+                        colour,
+                    });
+                }
+            }
+            other => return Err(Error::InvalidRecordTag(other)),
+        }
+    }
+    Ok(entities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID_SIZE: u32 = 100;
+    const TILE_SIZE: f64 = 16.;
+
+    fn tile_level() -> Vec<StaticEntity> {
+        let mut entities = Vec::new();
+        for y in 0..GRID_SIZE {
+            for x in 0..GRID_SIZE {
+                // Varying the colour breaks rows into several runs.
+                let shade = ((x / 7 + y) % 3) as f32 / 2.;
+                entities.push(StaticEntity {
+                    position: vec2(x as f64, y as f64) * TILE_SIZE,
+                    shape: Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(
+                        TILE_SIZE, TILE_SIZE,
+                    ))),
+                    colour: [shade, 1., 1.],
+                });
+            }
+        }
+        entities
+    }
+
+    #[test]
+    fn round_trip_large_tile_level() {
+        let entities = tile_level();
+        let bytes = encode(&entities);
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.len(), entities.len());
+        // Entities are decoded in row order, which is the order they were
+        // generated in.
+        for (a, b) in entities.iter().zip(decoded.iter()) {
+            assert_eq!(a.position, b.position);
+            assert_eq!(a.shape, b.shape);
+            assert_eq!(a.colour, b.colour);
+        }
+        #[cfg(feature = "ron_level")]
+        {
+            use ron_level;
+            let mut ron = String::from("(entities: [\n");
+            for entity in entities.iter() {
+                ron.push_str(&format!(
+                    "(kind: Static, position: (x: {:?}, y: {:?}), \
+                     shape: Some(Rect(dimensions: (x: {:?}, y: {:?}))), \
+                     colour: ({:?}, {:?}, {:?})),\n",
+                    entity.position.x,
+                    entity.position.y,
+                    TILE_SIZE,
+                    TILE_SIZE,
+                    entity.colour[0],
+                    entity.colour[1],
+                    entity.colour[2],
+                ));
+            }
+            ron.push_str("])\n");
+            let parsed = ron_level::parse(&ron).unwrap();
+            assert_eq!(parsed.len(), entities.len());
+            assert!(bytes.len() < ron.len());
+        }
+    }
+
+    #[test]
+    fn huge_run_count_is_rejected() {
+        let mut writer = Writer { bytes: Vec::new() };
+        writer.bytes.extend_from_slice(MAGIC);
+        writer.u8(VERSION);
+        writer.u32(1);
+        writer.u8(RECORD_RUN);
+        writer.u32(::std::u32::MAX);
+        writer.vector2(vec2(0., 0.));
+        writer.vector2(vec2(1., 0.));
+        writer.shape(&Shape::Circle(Circle::new(1.)));
+        writer.colour([1., 1., 1.]);
+        assert_eq!(decode(&writer.bytes).unwrap_err(), Error::TooManyEntities);
+    }
+
+    #[test]
+    fn huge_circle_edge_count_is_rejected() {
+        let mut writer = Writer { bytes: Vec::new() };
+        writer.bytes.extend_from_slice(MAGIC);
+        writer.u8(VERSION);
+        writer.u32(1);
+        writer.u8(RECORD_SINGLE);
+        writer.vector2(vec2(0., 0.));
+        writer.u8(SHAPE_CIRCLE);
+        writer.f64(1.);
+        writer.u32(::std::u32::MAX);
+        writer.colour([1., 1., 1.]);
+        assert_eq!(decode(&writer.bytes).unwrap_err(), Error::InvalidCircle);
+    }
+}
@@ -1,4 +1,4 @@
-use cgmath::{InnerSpace, Vector2};
+use cgmath::{vec2, InnerSpace, Vector2};
 use collide::{flags, Collision};
 use left_solid_edge::StartOrEnd;
 use movement::ClosestCollisions;
@@ -30,13 +30,33 @@ pub fn max_bump(closest_collisions: ClosestCollisions) -> Option<Bump> {
         })
 }
 
+// A lone vertex touching the moving edge, with the stationary edge leading
+// away from it out in front of the moving edge at no more than 45 degrees, is
+// the foot of a slope. The moving shape steps up onto it as it would onto the
+// top of a step. Steeper edges are walls, and block as usual.
+fn is_foot_of_slope(collision_info: &Collision, vertex: StartOrEnd) -> bool {
+    if collision_info.left_solid_edge_collision.moving_edge_collision_count() != 1 {
+        return false;
+    }
+    let moving = collision_info.moving_edge_vector.vector;
+    let outward = vec2(moving.y, -moving.x);
+    let away = match vertex {
+        StartOrEnd::Start => collision_info.stationary_edge_vector.vector,
+        StartOrEnd::End => -collision_info.stationary_edge_vector.vector,
+    };
+    away.dot(outward) > EPSILON && away.dot(outward) >= away.dot(moving).abs()
+}
+
 fn bump(collision_info: &Collision) -> Option<Bump> {
     if collision_info.moving_edge_vector.flags & flags::BUMP_START != 0 {
         if let Some(edge_collision_position) = collision_info
             .left_solid_edge_collision
             .moving_edge_min_collision_position()
         {
-            if edge_collision_position.which_part_of_other_edge == StartOrEnd::Start {
+            let which_part = edge_collision_position.which_part_of_other_edge;
+            if which_part == StartOrEnd::Start
+                || is_foot_of_slope(collision_info, which_part)
+            {
                 let multiplier = edge_collision_position.how_far_along_this_edge;
                 let distance2 = collision_info.moving_edge_vector.vector.magnitude2()
                     * multiplier * multiplier;
@@ -54,7 +74,10 @@ fn bump(collision_info: &Collision) -> Option<Bump> {
             .left_solid_edge_collision
             .moving_edge_max_collision_position()
         {
-            if edge_collision_position.which_part_of_other_edge == StartOrEnd::End {
+            let which_part = edge_collision_position.which_part_of_other_edge;
+            if which_part == StartOrEnd::End
+                || is_foot_of_slope(collision_info, which_part)
+            {
                 let multiplier = 1. - edge_collision_position.how_far_along_this_edge;
                 let distance2 = collision_info.moving_edge_vector.vector.magnitude2()
                     * multiplier * multiplier;
@@ -0,0 +1,115 @@
+use aabb::Aabb;
+use cgmath::{vec2, InnerSpace, Vector2};
+use collide::{Collide, Edge};
+use left_solid_edge::EPSILON;
+use std::f64::consts::PI;
+
+// Number of edges the two caps would have if they were joined into a circle.
+// A multiple of 4 so the capsule has flat sides, top and bottom.
+const NUM_CAP_EDGES: u32 = 16;
+
+// A vertical rectangle with a semicircular cap on the top and bottom.
+// `half_height` is half the length of the straight sides, so the capsule is
+// `2 * (half_height + radius)` tall.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Capsule {
+    half_height: f64,
+    radius: f64,
+}
+
+impl Capsule {
+    pub fn new(half_height: f64, radius: f64) -> Self {
+        Self {
+            half_height,
+            radius,
+        }
+    }
+    pub fn half_height(&self) -> f64 {
+        self.half_height
+    }
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+    pub fn centre(&self, top_left: Vector2<f64>) -> Vector2<f64> {
+        top_left + vec2(self.radius, self.radius + self.half_height)
+    }
+    fn dimensions(&self) -> Vector2<f64> {
+        vec2(self.radius, self.radius + self.half_height) * 2.
+    }
+    // Collisions treat the caps as halves of a polygon circumscribing a
+    // circle, as with `Circle`, pulled apart to make room for the straight
+    // sides. The first half of the vertices make up the bottom cap.
+    fn vertex(&self, i: u32) -> Vector2<f64> {
+        let step = 2. * PI / NUM_CAP_EDGES as f64;
+        let angle = step * (i as f64 + 0.5);
+        let distance = self.radius / (step / 2.).cos();
+        let offset = vec2(angle.cos(), angle.sin()) * distance;
+        let cap_centre_y = if (i % NUM_CAP_EDGES) < NUM_CAP_EDGES / 2 {
+            self.radius + self.half_height * 2.
+        } else {
+            self.radius
+        };
+        vec2(self.radius, cap_centre_y) + offset
+    }
+    pub fn vertices_at(&self, top_left: Vector2<f64>) -> Vec<Vector2<f64>> {
+        (0..NUM_CAP_EDGES)
+            .map(|i| top_left + self.vertex(i))
+            .collect()
+    }
+}
+
+impl Collide for Capsule {
+    fn aabb(&self, top_left: Vector2<f64>) -> Aabb {
+        Aabb::new(top_left, self.dimensions())
+    }
+    fn for_each_left_solid_edge_facing<F: FnMut(Edge)>(
+        &self,
+        direction: Vector2<f64>,
+        mut f: F,
+    ) {
+        for i in 0..NUM_CAP_EDGES {
+            let edge = Edge::new(self.vertex(i), self.vertex(i + 1));
+            let vector = edge.vector();
+            let normal = vec2(vector.y, -vector.x).normalize();
+            if normal.dot(direction) > -EPSILON {
+                f(edge);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axis_aligned_rect::AxisAlignedRect;
+    use movement::resolve_movement;
+    use shape::{Shape, ShapePosition};
+
+    #[test]
+    fn capsule_slides_over_the_seam_between_two_rects() {
+        let rect = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(100., 20.)));
+        let obstacles = [
+            ShapePosition {
+                entity_id: 0,
+                position: vec2(0., 100.),
+                shape: &rect,
+            },
+            ShapePosition {
+                entity_id: 1,
+                position: vec2(100., 100.),
+                shape: &rect,
+            },
+        ];
+        let capsule = Shape::Capsule(Capsule::new(10., 10.));
+        // Resting on the left rect, and pressed down onto the floor each step
+        // as gravity would, it crosses the seam at x = 100 at full speed.
+        let mut position = vec2(40., 60.);
+        for _ in 0..30 {
+            let next = resolve_movement(&capsule, position, vec2(4., 1.), &obstacles);
+            assert_eq!(next.position, position + vec2(4., 0.));
+            position = next.position;
+        }
+        assert_eq!(position, vec2(160., 60.));
+    }
+}
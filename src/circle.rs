@@ -0,0 +1,76 @@
+use aabb::Aabb;
+use cgmath::{vec2, InnerSpace, Vector2};
+use collide::{Collide, Edge};
+use left_solid_edge::EPSILON;
+use std::f64::consts::PI;
+
+const DEFAULT_NUM_EDGES: u32 = 16;
+pub const MAX_NUM_EDGES: u32 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Circle {
+    radius: f64,
+    num_edges: u32,
+}
+
+impl Circle {
+    pub fn new(radius: f64) -> Self {
+        Self::with_num_edges(radius, DEFAULT_NUM_EDGES)
+    }
+    // Collisions treat the circle as a polygon circumscribing it. The number
+    // of edges is rounded up to a multiple of 4 so the polygon has flat top,
+    // bottom and sides and shares the circle's bounding box. It's also capped
+    // at `MAX_NUM_EDGES`, which is already a multiple of 4.
+    pub fn with_num_edges(radius: f64, num_edges: u32) -> Self {
+        Self {
+            radius,
+            num_edges: ((num_edges.max(4).min(MAX_NUM_EDGES) + 3) / 4) * 4,
+        }
+    }
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+    pub fn num_edges(&self) -> u32 {
+        self.num_edges
+    }
+    pub fn centre(&self, top_left: Vector2<f64>) -> Vector2<f64> {
+        top_left + vec2(self.radius, self.radius)
+    }
+    fn vertex(&self, i: u32) -> Vector2<f64> {
+        let step = 2. * PI / self.num_edges as f64;
+        let angle = step * (i as f64 + 0.5);
+        let distance = self.radius / (step / 2.).cos();
+        self.centre(vec2(0., 0.)) + vec2(angle.cos(), angle.sin()) * distance
+    }
+    pub fn is_intersecting_aabb(&self, top_left: Vector2<f64>, aabb: &Aabb) -> bool {
+        let centre = self.centre(top_left);
+        let min = aabb.top_left();
+        let max = aabb.bottom_right_coord();
+        let closest = vec2(
+            centre.x.max(min.x).min(max.x),
+            centre.y.max(min.y).min(max.y),
+        );
+        (centre - closest).magnitude2() <= self.radius * self.radius
+    }
+}
+
+impl Collide for Circle {
+    fn aabb(&self, top_left: Vector2<f64>) -> Aabb {
+        Aabb::new(top_left, vec2(self.radius, self.radius) * 2.)
+    }
+    fn for_each_left_solid_edge_facing<F: FnMut(Edge)>(
+        &self,
+        direction: Vector2<f64>,
+        mut f: F,
+    ) {
+        for i in 0..self.num_edges {
+            let edge = Edge::new(self.vertex(i), self.vertex(i + 1));
+            let vector = edge.vector();
+            let normal = vec2(vector.y, -vector.x).normalize();
+            if normal.dot(direction) > -EPSILON {
+                f(edge);
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use aabb::Aabb;
 use best::BestMultiSet;
-use cgmath::Vector2;
+use cgmath::{vec2, InnerSpace, Vector2};
 use left_solid_edge::{LeftSolidEdge, LeftSolidEdgeCollision};
 use movement::EntityId;
 use std::cmp::Ordering;
@@ -14,6 +14,38 @@ pub struct Collision {
     pub stationary_edge_vector: EdgeVector,
     pub moving_entity_id: EntityId,
     pub stationary_entity_id: EntityId,
+    pub contact: (Vector2<f64>, Vector2<f64>),
+}
+
+impl Collision {
+    pub fn normal(&self) -> Vector2<f64> {
+        let vector = self.stationary_edge_vector.vector;
+        vec2(vector.y, -vector.x).normalize()
+    }
+}
+
+// The part of the stationary edge touched by the moving edge once it has
+// travelled up to the point of collision.
+fn contact_segment(
+    moving_edge: &LeftSolidEdge,
+    stationary_edge: &LeftSolidEdge,
+) -> (Vector2<f64>, Vector2<f64>) {
+    let vector = stationary_edge.vector();
+    let length2 = vector.magnitude2();
+    if length2 == 0. {
+        return (stationary_edge.start, stationary_edge.start);
+    }
+    let along = |point: Vector2<f64>| {
+        ((point - stationary_edge.start).dot(vector) / length2)
+            .max(0.)
+            .min(1.)
+    };
+    let a = along(moving_edge.start);
+    let b = along(moving_edge.end);
+    (
+        stationary_edge.start + vector * a.min(b),
+        stationary_edge.start + vector * a.max(b),
+    )
 }
 
 pub type Flags = u32;
@@ -23,6 +55,12 @@ pub mod channels {
     use super::*;
     pub const MAIN: Channels = 1 << 0;
     pub const FLOOR: Channels = 1 << 1;
+    pub const CEILING: Channels = 1 << 2;
+    pub const WALL: Channels = 1 << 3;
+    // Sensor edges never block movement. Overlaps with sensors are found with
+    // `MovementContext::overlapping_sensors` instead.
+    pub const SENSOR: Channels = 1 << 4;
+    pub const ALL: Channels = !0;
 }
 
 pub mod flags {
@@ -88,6 +126,7 @@ impl<'a, C: Collide> CollidePosition<'a, C> {
         &self,
         stationary: CollidePosition<Stationary>,
         movement: Vector2<f64>,
+        enabled_channels: Channels,
         mut f: F,
     ) where
         Stationary: Collide,
@@ -101,7 +140,11 @@ impl<'a, C: Collide> CollidePosition<'a, C> {
                 stationary.collide.for_each_left_solid_edge_facing(
                     -movement,
                     |stationary_rel_edge| {
-                        if moving_rel_edge.channels & stationary_rel_edge.channels == 0 {
+                        let channels = moving_rel_edge.channels
+                            & stationary_rel_edge.channels
+                            & enabled_channels
+                            & !channels::SENSOR;
+                        if channels == 0 {
                             return;
                         }
                         let stationary_edge = stationary_rel_edge
@@ -110,12 +153,20 @@ impl<'a, C: Collide> CollidePosition<'a, C> {
                         if let Some(left_solid_edge_collision) = moving_edge
                             .collide_with_stationary_edge(&stationary_edge, movement)
                         {
+                            let moving_edge_at_collision = moving_edge.add_vector(
+                                left_solid_edge_collision.movement_to_collision(movement),
+                            );
+                            let contact = contact_segment(
+                                &moving_edge_at_collision,
+                                &stationary_edge,
+                            );
                             let collision_info = Collision {
                                 left_solid_edge_collision,
                                 moving_edge_vector: moving_rel_edge.edge_vector(),
                                 stationary_edge_vector: stationary_rel_edge.edge_vector(),
                                 moving_entity_id: self.entity_id,
                                 stationary_entity_id: stationary.entity_id,
+                                contact,
                             };
                             f(collision_info);
                         }
@@ -128,23 +179,29 @@ impl<'a, C: Collide> CollidePosition<'a, C> {
         &self,
         stationary: CollidePosition<Stationary>,
         movement: Vector2<f64>,
+        enabled_channels: Channels,
         closest_collisions: &mut BestMultiSet<Collision>,
     ) where
         Stationary: Collide,
     {
-        self.for_each_movement_collision(stationary, movement, |collision| {
-            closest_collisions.insert_lt_by(collision, |a, b| {
-                let delta = a.left_solid_edge_collision.movement_multiplier()
-                    - b.left_solid_edge_collision.movement_multiplier();
-                if delta.abs() < EPSILON {
-                    Ordering::Equal
-                } else if delta > 0. {
-                    Ordering::Greater
-                } else {
-                    Ordering::Less
-                }
-            });
-        });
+        self.for_each_movement_collision(
+            stationary,
+            movement,
+            enabled_channels,
+            |collision| {
+                closest_collisions.insert_lt_by(collision, |a, b| {
+                    let delta = a.left_solid_edge_collision.movement_multiplier()
+                        - b.left_solid_edge_collision.movement_multiplier();
+                    if delta.abs() < EPSILON {
+                        Ordering::Equal
+                    } else if delta > 0. {
+                        Ordering::Greater
+                    } else {
+                        Ordering::Less
+                    }
+                });
+            },
+        );
     }
 }
 
@@ -0,0 +1,177 @@
+use aabb::Aabb;
+use cgmath::{vec2, InnerSpace, Vector2};
+use collide::{Collide, Edge};
+use left_solid_edge::EPSILON;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    TooFewVertices,
+    NotConvex,
+    AntiClockwise,
+}
+
+fn cross(a: Vector2<f64>, b: Vector2<f64>) -> f64 {
+    a.x * b.y - a.y * b.x
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ConvexPolygon {
+    vertices: Vec<Vector2<f64>>,
+}
+
+impl ConvexPolygon {
+    // Vertices are relative to the polygon's position and must go clockwise
+    // on screen (with y pointing down), so that the solid side of each edge is
+    // on its left.
+    pub fn from_vertices(vertices: Vec<Vector2<f64>>) -> Result<Self, Error> {
+        if vertices.len() < 3 {
+            return Err(Error::TooFewVertices);
+        }
+        let num_vertices = vertices.len();
+        let mut clockwise = 0;
+        let mut anti_clockwise = 0;
+        for i in 0..num_vertices {
+            let a = vertices[i];
+            let b = vertices[(i + 1) % num_vertices];
+            let c = vertices[(i + 2) % num_vertices];
+            let turn = cross(b - a, c - b);
+            if turn > 0. {
+                clockwise += 1;
+            } else if turn < 0. {
+                anti_clockwise += 1;
+            } else {
+                return Err(Error::NotConvex);
+            }
+        }
+        if clockwise > 0 && anti_clockwise > 0 {
+            return Err(Error::NotConvex);
+        }
+        if anti_clockwise > 0 {
+            return Err(Error::AntiClockwise);
+        }
+        // Turning the same way at every vertex still allows a polygon that
+        // winds around more than once.
+        let total_angle = (0..num_vertices).fold(0., |total, i| {
+            let a = vertices[(i + 1) % num_vertices] - vertices[i];
+            let b = vertices[(i + 2) % num_vertices] - vertices[(i + 1) % num_vertices];
+            total + cross(a, b).atan2(a.dot(b))
+        });
+        if total_angle > 2. * ::std::f64::consts::PI + EPSILON {
+            return Err(Error::NotConvex);
+        }
+        Ok(Self { vertices })
+    }
+    pub fn vertices(&self) -> &[Vector2<f64>] {
+        &self.vertices
+    }
+    pub fn vertices_at(&self, top_left: Vector2<f64>) -> Vec<Vector2<f64>> {
+        self.vertices
+            .iter()
+            .map(|&vertex| top_left + vertex)
+            .collect()
+    }
+    fn edges<'a>(&'a self) -> impl Iterator<Item = Edge> + 'a {
+        let num_vertices = self.vertices.len();
+        (0..num_vertices).map(move |i| {
+            Edge::new(self.vertices[i], self.vertices[(i + 1) % num_vertices])
+        })
+    }
+}
+
+impl Collide for ConvexPolygon {
+    fn aabb(&self, top_left: Vector2<f64>) -> Aabb {
+        let first = self.vertices[0];
+        let (min, max) = self.vertices.iter().fold((first, first), |(min, max), v| {
+            (
+                vec2(min.x.min(v.x), min.y.min(v.y)),
+                vec2(max.x.max(v.x), max.y.max(v.y)),
+            )
+        });
+        Aabb::new(top_left + min, max - min)
+    }
+    fn for_each_left_solid_edge_facing<F: FnMut(Edge)>(
+        &self,
+        direction: Vector2<f64>,
+        mut f: F,
+    ) {
+        for edge in self.edges() {
+            let vector = edge.vector();
+            let normal = vec2(vector.y, -vector.x).normalize();
+            if normal.dot(direction) > -EPSILON {
+                f(edge);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    // Regular polygon about (50, 50), visiting every `step`th vertex.
+    fn star(num_points: usize, step: usize) -> Vec<Vector2<f64>> {
+        (0..num_points)
+            .map(|i| {
+                let index = (i * step) % num_points;
+                let angle = index as f64 * 2. * PI / num_points as f64;
+                vec2(50., 50.) + vec2(angle.cos(), angle.sin()) * 50.
+            })
+            .collect()
+    }
+
+    #[test]
+    fn triangle_ramp() {
+        let ramp = vec![vec2(0., 100.), vec2(200., 50.), vec2(200., 100.)];
+        let polygon = ConvexPolygon::from_vertices(ramp.clone()).unwrap();
+        let aabb = polygon.aabb(vec2(10., 20.));
+        assert_eq!(aabb.top_left(), vec2(10., 70.));
+        assert_eq!(aabb.bottom_right_coord(), vec2(210., 120.));
+        let mut facing_up = Vec::new();
+        polygon.for_each_left_solid_edge_facing(vec2(0., -1.), |edge| {
+            facing_up.push((edge.start(), edge.end()))
+        });
+        // The vertical side is perpendicular to the movement so it is included.
+        assert_eq!(facing_up, vec![(ramp[0], ramp[1]), (ramp[1], ramp[2])]);
+        let reversed = ramp.into_iter().rev().collect();
+        assert_eq!(
+            ConvexPolygon::from_vertices(reversed),
+            Err(Error::AntiClockwise)
+        );
+    }
+
+    #[test]
+    fn pentagon() {
+        let polygon = ConvexPolygon::from_vertices(star(5, 1)).unwrap();
+        let aabb = polygon.aabb(vec2(0., 0.));
+        assert!((aabb.top_left().x - (50. + 50. * (0.8 * PI).cos())).abs() < EPSILON);
+        assert!((aabb.top_left().y - (50. - 50. * (0.4 * PI).sin())).abs() < EPSILON);
+        assert!((aabb.bottom_right_coord().x - 100.).abs() < EPSILON);
+        let bottom = 50. + 50. * (0.4 * PI).sin();
+        assert!((aabb.bottom_right_coord().y - bottom).abs() < EPSILON);
+        let mut num_facing_right = 0;
+        polygon.for_each_left_solid_edge_facing(vec2(1., 0.), |_| num_facing_right += 1);
+        assert_eq!(num_facing_right, 2);
+        assert_eq!(
+            ConvexPolygon::from_vertices(star(5, 2)),
+            Err(Error::NotConvex)
+        );
+    }
+
+    #[test]
+    fn invalid_vertices() {
+        assert_eq!(
+            ConvexPolygon::from_vertices(vec![vec2(0., 0.), vec2(10., 0.)]),
+            Err(Error::TooFewVertices)
+        );
+        let concave = vec![
+            vec2(0., 0.),
+            vec2(100., 0.),
+            vec2(50., 20.),
+            vec2(100., 100.),
+            vec2(0., 100.),
+        ];
+        assert_eq!(ConvexPolygon::from_vertices(concave), Err(Error::NotConvex));
+    }
+}
@@ -1,12 +1,32 @@
 use aabb::Aabb;
 use axis_aligned_rect::AxisAlignedRect;
-use cgmath::{vec2, ElementWise, InnerSpace, Vector2};
+use binary_level::{self, StaticEntity};
+use cgmath::{vec2, InnerSpace, Vector2};
+use collide::{channels, Channels};
 use fnv::{FnvHashMap, FnvHashSet};
+use integrator::{Integrator, SemiImplicitEuler};
 use line_segment::LineSegment;
 use loose_quad_tree::LooseQuadTree;
-use movement::{Displacement, EntityId, ForEachShapePosition, MovementContext};
+use movement::{CollisionEvent, Displacement, EntityId, ForEachShapePosition,
+               MovementContext, Side, SweepHit};
+use polyline::Polyline;
+use raycast::RayHit;
+use rng::Rng;
+#[cfg(feature = "ron_level")]
+use ron_level::{self, EntityKind, LevelEntity, LevelError};
 use shape::{Shape, ShapePosition};
+use std::cmp::Ordering;
 use std::collections::HashMap;
+#[cfg(feature = "ron_level")]
+use std::fs::File;
+#[cfg(feature = "ron_level")]
+use std::io::Read;
+use std::mem;
+#[cfg(feature = "ron_level")]
+use std::path::Path;
+use svg;
+#[cfg(feature = "tiled")]
+use tiled;
 
 fn clamp(value: f64, min: f64, max: f64) -> f64 {
     value.max(min).min(max)
@@ -77,19 +97,163 @@ impl InputModel {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Sprite {
+    pub texture_id: u32,
+    pub uv_top_left: Vector2<f32>,
+    pub uv_size: Vector2<f32>,
+}
+
+impl Sprite {
+    pub fn from_atlas_pixels(
+        texture_id: u32,
+        atlas_size: Vector2<f32>,
+        top_left: Vector2<f32>,
+        size: Vector2<f32>,
+    ) -> Self {
+        Self {
+            texture_id,
+            uv_top_left: vec2(top_left.x / atlas_size.x, top_left.y / atlas_size.y),
+            uv_size: vec2(size.x / atlas_size.x, size.y / atlas_size.y),
+        }
+    }
+}
+
 pub struct RenderUpdate<'a> {
     pub position: Vector2<f64>,
     pub shape: &'a Shape,
     pub colour: [f32; 3],
+    pub sprite: Option<Sprite>,
+}
+
+pub struct EntityView<'a> {
+    pub position: Vector2<f64>,
+    pub velocity: Vector2<f64>,
+    pub shape: &'a Shape,
+    pub colour: [f32; 3],
+}
+
+fn jump_time_to_velocity(time: f64, config: &PhysicsConfig) -> Option<f64> {
+    if time >= config.jump_time {
+        None
+    } else {
+        Some((config.jump_time - time.floor()) * config.jump_multiplier)
+    }
+}
+
+// Speeds and accelerations are measured per step of this length, in seconds.
+pub const FIXED_DT: f64 = 1. / 60.;
+
+const PENETRATION_SLOP: f64 = 0.01;
+const GROUND_PROBE_DISTANCE: f64 = 1000.;
+const MIN_PUSH_STEP: f64 = 1.;
+const DIFF_TOLERANCE: f64 = 1e-6;
+const MAX_RESOLVE_ITERATIONS: usize = 8;
+const DEFAULT_FRICTION: f64 = 1.;
+const DEFAULT_LAYER: u32 = 1;
+const DEFAULT_MASK: u32 = !0;
+const REST_VELOCITY_THRESHOLD: f64 = 0.01;
+const PICK_RADIUS: f64 = 4.;
+
+fn approach(current: f64, target: f64, rate: f64) -> f64 {
+    if current < target {
+        (current + rate).min(target)
+    } else {
+        (current - rate).max(target)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum Broadphase {
+    QuadTree,
+    BruteForce,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct PhysicsConfig {
+    pub gravity: Vector2<f64>,
+    pub max_lateral_speed: f64,
+    // Rates at which lateral speed approaches the target set by the input.
+    // The defaults can reverse from full speed one way to full speed the
+    // other in a single step, so movement responds instantly.
+    pub lateral_acceleration: f64,
+    pub lateral_deceleration: f64,
+    // Jumping pushes upwards for `jump_time` steps, starting at
+    // `jump_time * jump_multiplier` and dropping by `jump_multiplier` each
+    // step.
+    pub jump_time: f64,
+    pub jump_multiplier: f64,
+    pub penetration_bias: f64,
+    pub world_bounds: Option<Aabb>,
+    pub kill_plane_y: Option<f64>,
+    pub max_kinematic_substeps: u32,
+    pub broadphase: Broadphase,
+    pub landing_velocity_threshold: f64,
+    pub auto_rebuild_broadphase: bool,
+    pub max_carry_speed: Option<f64>,
+    pub coyote_frames: u32,
+    pub jump_buffer_frames: u32,
+    pub wall_slide_max_speed: f64,
+    pub max_fall_speed: Option<f64>,
+    // How long the player ignores the floor channel after pressing down while
+    // standing on a floor-only platform.
+    pub drop_through_frames: u32,
+    // Speed away from the wall in x and upwards in y.
+    pub wall_jump_velocity: Vector2<f64>,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            gravity: vec2(0., 0.5),
+            max_lateral_speed: 4.,
+            lateral_acceleration: 8.,
+            lateral_deceleration: 8.,
+            jump_time: 6.,
+            jump_multiplier: 0.4,
+            penetration_bias: 0.,
+            world_bounds: None,
+            kill_plane_y: None,
+            max_kinematic_substeps: 8,
+            broadphase: Broadphase::QuadTree,
+            landing_velocity_threshold: 0.5,
+            auto_rebuild_broadphase: true,
+            max_carry_speed: None,
+            coyote_frames: 6,
+            jump_buffer_frames: 4,
+            wall_slide_max_speed: 2.,
+            max_fall_speed: None,
+            drop_through_frames: 8,
+            wall_jump_velocity: vec2(4., 6.),
+        }
+    }
+}
+
+// A body moving away from the surface below it faster than the threshold is
+// grazing past it rather than landing on it.
+fn is_leaving_surface(
+    velocity: Vector2<f64>,
+    surface_velocity: Option<Vector2<f64>>,
+    up: Vector2<f64>,
+    threshold: f64,
+) -> bool {
+    (velocity - surface_velocity.unwrap_or(vec2(0., 0.))).dot(up) > threshold
 }
 
-fn jump_frame_count_to_velocity(count: u64) -> Option<f64> {
-    const MAX_COUNT: u64 = 6;
-    const MULTIPLIER: f64 = 0.4;
-    if count >= MAX_COUNT {
+// Kinematic entities carry the player rather than lending it their velocity,
+// so they count as stationary surfaces when working out the player's velocity.
+fn surface_velocity(
+    velocity: &FnvHashMap<EntityId, Vector2<f64>>,
+    kinematic: &FnvHashSet<EntityId>,
+    id: EntityId,
+) -> Option<Vector2<f64>> {
+    if kinematic.contains(&id) {
         None
     } else {
-        Some(((MAX_COUNT - count) as f64) * MULTIPLIER)
+        velocity.get(&id).cloned()
     }
 }
 
@@ -97,48 +261,66 @@ fn update_player_velocity(
     current_velocity: Vector2<f64>,
     input_model: &InputModel,
     max_platform_velocity: Option<Vector2<f64>>,
+    surface_friction: Option<f64>,
+    wall: Option<Side>,
     jump: &JumpStateMachine,
+    config: &PhysicsConfig,
+    gravity: Vector2<f64>,
+    up: Vector2<f64>,
+    dt: f64,
 ) -> Vector2<f64> {
-    const MULTIPLIER: Vector2<f64> = Vector2 { x: 4., y: 0.5 };
-    const GRAVITY: Vector2<f64> = Vector2 { x: 0., y: 0.5 };
-    const MAX_LATERAL: f64 = 10.;
-    const DECAY: Vector2<f64> = Vector2 { x: 0.0, y: 1. };
-
-    let (current_velocity_relative, platform_velocity) =
-        if let Some(max_platform_velocity) = max_platform_velocity {
-            let current_velocity_relative =
-                (current_velocity - max_platform_velocity).mul_element_wise(DECAY);
-            (current_velocity_relative, max_platform_velocity)
-        } else {
-            (current_velocity.mul_element_wise(DECAY), vec2(0., 0.))
-        };
-
-    let input_movement = input_model.movement().mul_element_wise(MULTIPLIER);
+    let platform_velocity = max_platform_velocity.unwrap_or(vec2(0., 0.));
+    let current_velocity_relative = current_velocity - platform_velocity;
+    let tangent = vec2(-up.y, up.x);
+    let current_lateral = current_velocity_relative.dot(tangent);
+    let current_vertical = current_velocity_relative.dot(up);
 
-    let horizontal_velocity_relative = clamp(
-        current_velocity_relative.x + input_movement.x,
-        -MAX_LATERAL,
-        MAX_LATERAL,
-    );
+    let input_horizontal = input_model.movement().x;
+    let target_horizontal = input_horizontal * config.max_lateral_speed;
+    let accelerating = input_horizontal != 0.
+        && input_horizontal * current_lateral >= 0.
+        && target_horizontal.abs() >= current_lateral.abs();
+    let rate = if accelerating {
+        config.lateral_acceleration
+    } else {
+        config.lateral_deceleration
+    } * surface_friction.unwrap_or(DEFAULT_FRICTION);
+    let horizontal_velocity_relative =
+        approach(current_lateral, target_horizontal, rate * dt);
 
     let vertical_delta = match jump {
-        JumpStateMachine::NotJumping => GRAVITY,
-        JumpStateMachine::JumpingForFrames(n) => match jump_frame_count_to_velocity(*n) {
-            Some(y) => vec2(0., -y),
-            None => GRAVITY,
-        },
+        JumpStateMachine::NotJumping => gravity,
+        JumpStateMachine::JumpingForFrames(n) => {
+            match jump_time_to_velocity((*n as f64) * dt, config) {
+                Some(y) => up * y,
+                None => gravity,
+            }
+        }
+    };
+    let vertical_velocity_relative = current_vertical + vertical_delta.dot(up) * dt;
+    let pressing_into_wall = match wall {
+        Some(Side::Left) => input_horizontal < 0.,
+        Some(Side::Right) => input_horizontal > 0.,
+        None => false,
+    };
+    let vertical_velocity_relative = if pressing_into_wall {
+        vertical_velocity_relative.max(-config.wall_slide_max_speed)
+    } else {
+        vertical_velocity_relative
+    };
+    let vertical_velocity_relative = match config.max_fall_speed {
+        Some(max_fall_speed) => vertical_velocity_relative.max(-max_fall_speed),
+        None => vertical_velocity_relative,
     };
-    let vertical_velocity_relative = current_velocity_relative.y + vertical_delta.y;
 
-    let velocity_relative = vec2(
-        horizontal_velocity_relative,
-        vertical_velocity_relative,
-    );
+    let velocity_relative =
+        tangent * horizontal_velocity_relative + up * vertical_velocity_relative;
 
     platform_velocity + velocity_relative
 }
 
 #[derive(Default)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 struct EntityIdAllocator {
     next: u32,
 }
@@ -155,6 +337,7 @@ impl EntityIdAllocator {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 struct EntityCommon {
     position: Vector2<f64>,
     shape: Shape,
@@ -181,7 +364,64 @@ pub struct GameStateChanges {
     displacements: Vec<(EntityId, Displacement)>,
 }
 
-enum JumpStateMachine {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerMotionState {
+    Grounded,
+    Rising,
+    Falling,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntityDiff {
+    OnlyInSelf(EntityId),
+    OnlyInOther(EntityId),
+    Position {
+        entity_id: EntityId,
+        ours: Vector2<f64>,
+        theirs: Vector2<f64>,
+    },
+    Velocity {
+        entity_id: EntityId,
+        ours: Vector2<f64>,
+        theirs: Vector2<f64>,
+    },
+    Shape(EntityId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum SensorEventKind {
+    Enter,
+    Exit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct SensorEvent {
+    pub entity_id: EntityId,
+    pub sensor_id: EntityId,
+    pub kind: SensorEventKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum ContactKind {
+    Begin,
+    End,
+}
+
+// `a` is always the lower of the two ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ContactEvent {
+    pub a: EntityId,
+    pub b: EntityId,
+    pub kind: ContactKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum JumpStateMachine {
     NotJumping,
     JumpingForFrames(u64),
 }
@@ -207,27 +447,156 @@ impl JumpStateMachine {
     }
 }
 
-pub struct GameState {
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+struct Parent {
+    id: EntityId,
+    local_offset: Vector2<f64>,
+    collides: bool,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum Level {
+    Demo,
+    Static(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct GravityZone {
+    pub region: Aabb,
+    pub up: Vector2<f64>,
+}
+
+pub type GlobalForce = Box<dyn Fn(u64, Vector2<f64>) -> Vector2<f64>>;
+
+// With the "serialize" feature, the whole simulation can be saved and
+// restored. The broadphase is rebuilt on the first update after loading (or
+// by `rebuild_broadphase`), and the global force and integrator aren't saved,
+// so must be set again after loading if they were customised. The format must
+// round-trip floats exactly for a restored state to step identically (e.g.
+// serde_json's "float_roundtrip" feature).
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct GameState<U = ()> {
     player_id: Option<EntityId>,
+    player_grounded: bool,
+    player_can_jump: bool,
+    player_frames_since_grounded: Option<u32>,
+    player_frames_since_jump_pressed: Option<u32>,
+    player_drop_through_frames: Option<u32>,
+    player_carrier: Option<EntityId>,
     moving_platform_ids: Vec<EntityId>,
     entity_id_allocator: EntityIdAllocator,
     common: FnvHashMap<EntityId, EntityCommon>,
     velocity: FnvHashMap<EntityId, Vector2<f64>>,
     dynamic_physics: FnvHashSet<EntityId>,
     static_physics: FnvHashSet<EntityId>,
+    world_bounds_exempt: FnvHashSet<EntityId>,
+    ghosts: FnvHashSet<EntityId>,
+    parents: FnvHashMap<EntityId, Parent>,
+    gravity_zones: Vec<GravityZone>,
+    surface_up: FnvHashMap<EntityId, Vector2<f64>>,
+    gravity_scales: FnvHashMap<EntityId, f64>,
+    restitution: FnvHashMap<EntityId, f64>,
+    friction: FnvHashMap<EntityId, f64>,
+    layers: FnvHashMap<EntityId, u32>,
+    masks: FnvHashMap<EntityId, u32>,
+    sprites: FnvHashMap<EntityId, Sprite>,
+    #[cfg_attr(feature = "serialize", serde(with = "quad_tree_size"))]
     quad_tree: LooseQuadTree<EntityId>,
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    quad_tree_aabbs: FnvHashMap<EntityId, Aabb>,
+    // Entities that have moved, or started or stopped colliding, since the
+    // quad tree was last rebuilt.
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    quad_tree_dirty: FnvHashSet<EntityId>,
+    // Set when the quad tree doesn't hold every entity, such as after a saved
+    // state is loaded, so the next rebuild reinserts all of them.
+    #[cfg_attr(feature = "serialize", serde(skip, default = "default_quad_tree_stale"))]
+    quad_tree_stale: bool,
+    previous_positions: FnvHashMap<EntityId, Vector2<f64>>,
+    // The direction out of the surface each dynamic body was left embedded in
+    // by the last update, while the penetration bias eases it out.
+    embedded_normals: FnvHashMap<EntityId, Vector2<f64>>,
     jump: FnvHashMap<EntityId, JumpStateMachine>,
     frame_count: u64,
+    time: f64,
+    time_scale: f64,
+    config: PhysicsConfig,
+    enabled_channels: Channels,
+    rng: Rng,
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    global_force: Option<GlobalForce>,
+    #[cfg_attr(feature = "serialize", serde(skip, default = "default_integrator"))]
+    integrator: Box<dyn Integrator>,
+    level: Option<Level>,
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    collision_events: Vec<CollisionEvent>,
+    sensor_overlaps: FnvHashSet<(EntityId, EntityId)>,
+    sensor_events: Vec<SensorEvent>,
+    contacts: FnvHashSet<(EntityId, EntityId)>,
+    contact_events: Vec<ContactEvent>,
+    killed: Vec<EntityId>,
+    player_killed: bool,
+    crushed: Vec<EntityId>,
+    user_data: FnvHashMap<EntityId, U>,
 }
 
-struct AllShapePositions<'a>(&'a GameState);
-struct DynamicPhysicsShapePositions<'a>(&'a GameState);
+fn default_integrator() -> Box<dyn Integrator> {
+    Box::new(SemiImplicitEuler)
+}
+
+#[cfg(feature = "serialize")]
+fn default_quad_tree_stale() -> bool {
+    true
+}
+
+// Only the size of the quad tree is saved, as its contents are rebuilt from
+// the entities.
+#[cfg(feature = "serialize")]
+mod quad_tree_size {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    pub fn serialize<S: Serializer>(
+        quad_tree: &LooseQuadTree<EntityId>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        quad_tree.covered_region().size().serialize(serializer)
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<LooseQuadTree<EntityId>, D::Error> {
+        Vector2::deserialize(deserializer).map(LooseQuadTree::new)
+    }
+}
+
+struct AllShapePositions<'a, U: 'a>(&'a GameState<U>);
+struct DynamicPhysicsShapePositions<'a, U: 'a>(&'a GameState<U>);
+struct StaticShapePositions<'a, U: 'a>(&'a GameState<U>);
+struct ShapePositionsExcept<'a, U: 'a>(&'a GameState<U>, EntityId);
 
-impl<'a> ForEachShapePosition for AllShapePositions<'a> {
+impl<'a, U> ForEachShapePosition for AllShapePositions<'a, U> {
     fn for_each<F: FnMut(ShapePosition)>(&self, aabb: Aabb, mut f: F) {
-        self.0
-            .quad_tree
-            .for_each_intersection(aabb, |_aabb, &entity_id| {
+        self.0.for_each_candidate(aabb, |entity_id| {
+            let common = self.0.common.get(&entity_id).unwrap();
+            let shape_position = ShapePosition {
+                entity_id,
+                shape: &common.shape,
+                position: common.position,
+            };
+            f(shape_position);
+        });
+    }
+    fn is_collision_enabled(&self, moving: EntityId, stationary: EntityId) -> bool {
+        self.0.layers_collide(moving, stationary)
+    }
+}
+
+impl<'a, U> ForEachShapePosition for DynamicPhysicsShapePositions<'a, U> {
+    fn for_each<F: FnMut(ShapePosition)>(&self, aabb: Aabb, mut f: F) {
+        self.0.for_each_candidate(aabb, |entity_id| {
+            if self.0.dynamic_physics.contains(&entity_id) {
                 let common = self.0.common.get(&entity_id).unwrap();
                 let shape_position = ShapePosition {
                     entity_id,
@@ -235,69 +604,571 @@ impl<'a> ForEachShapePosition for AllShapePositions<'a> {
                     position: common.position,
                 };
                 f(shape_position);
-            });
+            }
+        });
+    }
+    fn is_collision_enabled(&self, moving: EntityId, stationary: EntityId) -> bool {
+        self.0.layers_collide(moving, stationary)
     }
 }
 
-impl<'a> ForEachShapePosition for DynamicPhysicsShapePositions<'a> {
+impl<'a, U> ForEachShapePosition for StaticShapePositions<'a, U> {
     fn for_each<F: FnMut(ShapePosition)>(&self, aabb: Aabb, mut f: F) {
-        self.0
-            .quad_tree
-            .for_each_intersection(aabb, |_aabb, &entity_id| {
-                if self.0.dynamic_physics.contains(&entity_id) {
-                    let common = self.0.common.get(&entity_id).unwrap();
-                    let shape_position = ShapePosition {
-                        entity_id,
-                        shape: &common.shape,
-                        position: common.position,
-                    };
-                    f(shape_position);
-                }
-            });
+        self.0.for_each_candidate(aabb, |entity_id| {
+            if !self.0.dynamic_physics.contains(&entity_id) {
+                let common = self.0.common.get(&entity_id).unwrap();
+                let shape_position = ShapePosition {
+                    entity_id,
+                    shape: &common.shape,
+                    position: common.position,
+                };
+                f(shape_position);
+            }
+        });
+    }
+    fn is_collision_enabled(&self, moving: EntityId, stationary: EntityId) -> bool {
+        self.0.layers_collide(moving, stationary)
     }
 }
 
-impl GameState {
+impl<'a, U> ForEachShapePosition for ShapePositionsExcept<'a, U> {
+    fn for_each<F: FnMut(ShapePosition)>(&self, aabb: Aabb, mut f: F) {
+        self.0.for_each_candidate(aabb, |entity_id| {
+            if entity_id != self.1 {
+                let common = self.0.common.get(&entity_id).unwrap();
+                let shape_position = ShapePosition {
+                    entity_id,
+                    shape: &common.shape,
+                    position: common.position,
+                };
+                f(shape_position);
+            }
+        });
+    }
+    fn is_collision_enabled(&self, moving: EntityId, stationary: EntityId) -> bool {
+        self.0.layers_collide(moving, stationary)
+    }
+}
+
+impl<U> GameState<U> {
     pub fn new(size_hint: Vector2<f64>) -> Self {
+        Self::with_config(size_hint, Default::default())
+    }
+    pub fn with_config(size_hint: Vector2<f64>, config: PhysicsConfig) -> Self {
         Self {
             player_id: None,
+            player_grounded: false,
+            player_can_jump: false,
+            player_frames_since_grounded: None,
+            player_frames_since_jump_pressed: None,
+            player_drop_through_frames: None,
+            player_carrier: None,
             moving_platform_ids: Vec::new(),
             entity_id_allocator: Default::default(),
             common: Default::default(),
             velocity: Default::default(),
             dynamic_physics: Default::default(),
             static_physics: Default::default(),
+            world_bounds_exempt: Default::default(),
+            ghosts: Default::default(),
+            parents: Default::default(),
+            gravity_zones: Vec::new(),
+            surface_up: Default::default(),
+            gravity_scales: Default::default(),
+            restitution: Default::default(),
+            friction: Default::default(),
+            layers: Default::default(),
+            masks: Default::default(),
+            sprites: Default::default(),
             quad_tree: LooseQuadTree::new(size_hint),
+            quad_tree_aabbs: Default::default(),
+            quad_tree_dirty: Default::default(),
+            quad_tree_stale: false,
+            previous_positions: Default::default(),
+            embedded_normals: Default::default(),
             jump: Default::default(),
             frame_count: 0,
+            time: 0.,
+            time_scale: 1.,
+            config,
+            enabled_channels: channels::ALL,
+            rng: Default::default(),
+            global_force: None,
+            integrator: default_integrator(),
+            level: None,
+            collision_events: Vec::new(),
+            sensor_overlaps: Default::default(),
+            sensor_events: Vec::new(),
+            contacts: Default::default(),
+            contact_events: Vec::new(),
+            killed: Vec::new(),
+            player_killed: false,
+            crushed: Vec::new(),
+            user_data: Default::default(),
+        }
+    }
+    pub fn config(&self) -> &PhysicsConfig {
+        &self.config
+    }
+    pub fn config_mut(&mut self) -> &mut PhysicsConfig {
+        &mut self.config
+    }
+    // Disabling a channel turns off every edge pair that could only collide
+    // through it, on top of the channels assigned to each edge.
+    pub fn set_channel_enabled(&mut self, channel: Channels, enabled: bool) {
+        if enabled {
+            self.enabled_channels |= channel;
+        } else {
+            self.enabled_channels &= !channel;
+        }
+    }
+    pub fn is_channel_enabled(&self, channel: Channels) -> bool {
+        self.enabled_channels & channel == channel
+    }
+    // All randomness in the simulation should come from here so that runs
+    // with the same seed are reproducible.
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+    pub fn set_global_force(&mut self, global_force: GlobalForce) {
+        self.global_force = Some(global_force);
+    }
+    pub fn clear_global_force(&mut self) {
+        self.global_force = None;
+    }
+    pub fn set_kill_plane_y(&mut self, y: f64) {
+        self.config.kill_plane_y = Some(y);
+    }
+    pub fn clear_kill_plane(&mut self) {
+        self.config.kill_plane_y = None;
+    }
+    pub fn add_gravity_zone(&mut self, gravity_zone: GravityZone) {
+        self.gravity_zones.push(gravity_zone);
+    }
+    pub fn clear_gravity_zones(&mut self) {
+        self.gravity_zones.clear();
+    }
+    // A surface aligned entity's up vector follows the normal of the last
+    // surface it touched, so gravity pulls it onto walls and ceilings.
+    pub fn set_surface_aligned(&mut self, id: EntityId, aligned: bool) {
+        if aligned {
+            let up = self.local_up(id).unwrap_or(vec2(0., -1.));
+            self.surface_up.insert(id, up);
+        } else {
+            self.surface_up.remove(&id);
+        }
+    }
+    pub fn is_surface_aligned(&self, id: EntityId) -> bool {
+        self.surface_up.contains_key(&id)
+    }
+    fn local_up(&self, id: EntityId) -> Option<Vector2<f64>> {
+        if let Some(up) = self.surface_up.get(&id) {
+            return Some(*up);
+        }
+        let centre = self.common.get(&id)?.aabb().centre();
+        let point = Aabb::new(centre, vec2(0., 0.));
+        self.gravity_zones
+            .iter()
+            .find(|zone| zone.region.is_intersecting(&point))
+            .map(|zone| zone.up.normalize())
+    }
+    fn local_gravity(&self, up: Option<Vector2<f64>>) -> Vector2<f64> {
+        match up {
+            Some(up) => -up * self.config.gravity.magnitude(),
+            None => self.config.gravity,
+        }
+    }
+    pub fn set_gravity_scale(&mut self, id: EntityId, scale: f64) {
+        self.gravity_scales.insert(id, scale);
+    }
+    pub fn gravity_scale(&self, id: EntityId) -> f64 {
+        self.gravity_scales.get(&id).cloned().unwrap_or(1.)
+    }
+    pub fn set_restitution(&mut self, id: EntityId, restitution: f64) {
+        self.restitution.insert(id, clamp(restitution, 0., 1.));
+    }
+    pub fn restitution(&self, id: EntityId) -> f64 {
+        self.restitution.get(&id).cloned().unwrap_or(0.)
+    }
+    // Scales how quickly the player speeds up and slows down while standing
+    // on this entity. Surfaces default to 1, so ice is well below that.
+    pub fn set_friction(&mut self, id: EntityId, friction: f64) {
+        self.friction.insert(id, friction.max(0.));
+    }
+    pub fn friction(&self, id: EntityId) -> f64 {
+        self.friction.get(&id).cloned().unwrap_or(DEFAULT_FRICTION)
+    }
+    // Two entities only collide if each one's layer shares a bit with the
+    // other's mask. Entities default to layer 1 and a mask of every layer.
+    pub fn set_layer(&mut self, id: EntityId, layer: u32) {
+        self.layers.insert(id, layer);
+    }
+    pub fn layer(&self, id: EntityId) -> u32 {
+        self.layers.get(&id).cloned().unwrap_or(DEFAULT_LAYER)
+    }
+    pub fn set_mask(&mut self, id: EntityId, mask: u32) {
+        self.masks.insert(id, mask);
+    }
+    pub fn mask(&self, id: EntityId) -> u32 {
+        self.masks.get(&id).cloned().unwrap_or(DEFAULT_MASK)
+    }
+    fn layers_collide(&self, a: EntityId, b: EntityId) -> bool {
+        self.layer(a) & self.mask(b) != 0 && self.layer(b) & self.mask(a) != 0
+    }
+    fn entity_gravity(&self, id: EntityId) -> Vector2<f64> {
+        self.local_gravity(self.local_up(id)) * self.gravity_scale(id)
+    }
+    // Applies `dt` seconds of gravity and the global force to the velocity of
+    // every dynamic body without moving anything, for callers driving their
+    // own update loop. Like `update`, this is scaled by the time scale.
+    pub fn integrate_forces(&mut self, dt: f64) {
+        let dt = dt / FIXED_DT * self.time_scale;
+        for id in self.dynamic_physics.iter() {
+            let acceleration = match self.common.get(id) {
+                Some(common) => {
+                    let global_force = match self.global_force {
+                        Some(ref global_force) => {
+                            global_force(self.frame_count, common.position)
+                        }
+                        None => vec2(0., 0.),
+                    };
+                    self.entity_gravity(*id) + global_force
+                }
+                None => continue,
+            };
+            if let Some(velocity) = self.velocity.get_mut(id) {
+                *velocity += acceleration * dt;
+            }
+        }
+    }
+    pub fn set_world_bounds_exempt(&mut self, id: EntityId, exempt: bool) {
+        if exempt {
+            self.world_bounds_exempt.insert(id);
+        } else {
+            self.world_bounds_exempt.remove(&id);
+        }
+    }
+    // A body that doesn't resolve collisions moves freely through everything,
+    // still reporting what it passes through as collision events, and nothing
+    // collides with it.
+    pub fn set_resolve_collisions(&mut self, id: EntityId, resolve_collisions: bool) {
+        if resolve_collisions {
+            self.ghosts.remove(&id);
+        } else {
+            self.ghosts.insert(id);
+        }
+        self.quad_tree_dirty.insert(id);
+    }
+    pub fn resolves_collisions(&self, id: EntityId) -> bool {
+        !self.ghosts.contains(&id)
+    }
+    pub fn set_integrator(&mut self, integrator: Box<dyn Integrator>) {
+        self.integrator = integrator;
+    }
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        self.time_scale = time_scale.max(0.);
+    }
+    fn is_ancestor(&self, ancestor: EntityId, id: EntityId) -> bool {
+        let mut current = id;
+        while let Some(parent) = self.parents.get(&current) {
+            if parent.id == ancestor {
+                return true;
+            }
+            current = parent.id;
+        }
+        false
+    }
+    pub fn set_parent(
+        &mut self,
+        child: EntityId,
+        parent: EntityId,
+        local_offset: Vector2<f64>,
+        collides: bool,
+    ) -> bool {
+        if child == parent || self.is_ancestor(child, parent) {
+            return false;
+        }
+        self.parents.insert(
+            child,
+            Parent {
+                id: parent,
+                local_offset,
+                collides,
+            },
+        );
+        self.quad_tree_dirty.insert(child);
+        self.rebuild_quad_tree();
+        true
+    }
+    pub fn clear_parent(&mut self, child: EntityId) {
+        self.parents.remove(&child);
+        self.quad_tree_dirty.insert(child);
+        self.rebuild_quad_tree();
+    }
+    fn parented_position(&self, id: EntityId) -> Option<Vector2<f64>> {
+        match self.parents.get(&id) {
+            Some(parent) => self.parented_position(parent.id)
+                .map(|position| position + parent.local_offset),
+            None => self.position(id),
         }
     }
     fn clear(&mut self) {
         self.player_id = None;
+        self.player_grounded = false;
+        self.player_can_jump = false;
+        self.player_frames_since_grounded = None;
+        self.player_frames_since_jump_pressed = None;
+        self.player_drop_through_frames = None;
+        self.player_carrier = None;
+        self.moving_platform_ids.clear();
         self.entity_id_allocator.reset();
         self.common.clear();
         self.velocity.clear();
         self.dynamic_physics.clear();
         self.static_physics.clear();
+        self.world_bounds_exempt.clear();
+        self.ghosts.clear();
+        self.parents.clear();
+        self.surface_up.clear();
+        self.gravity_scales.clear();
+        self.restitution.clear();
+        self.friction.clear();
+        self.layers.clear();
+        self.masks.clear();
+        self.sprites.clear();
         self.quad_tree.clear();
+        self.quad_tree_aabbs.clear();
+        self.quad_tree_dirty.clear();
+        self.previous_positions.clear();
+        self.embedded_normals.clear();
         self.jump.clear();
+        self.collision_events.clear();
+        self.sensor_overlaps.clear();
+        self.sensor_events.clear();
+        self.contacts.clear();
+        self.contact_events.clear();
+        self.killed.clear();
+        self.player_killed = false;
+        self.crushed.clear();
+        self.user_data.clear();
         self.frame_count = 0;
+        self.time = 0.;
+    }
+    pub fn remove_entity(&mut self, id: EntityId) {
+        if self.player_id == Some(id) {
+            self.player_id = None;
+            self.player_grounded = false;
+            self.player_can_jump = false;
+            self.player_frames_since_grounded = None;
+            self.player_frames_since_jump_pressed = None;
+            self.player_drop_through_frames = None;
+            self.player_carrier = None;
+        }
+        if self.player_carrier == Some(id) {
+            self.player_carrier = None;
+        }
+        self.moving_platform_ids.retain(|&other| other != id);
+        self.common.remove(&id);
+        self.velocity.remove(&id);
+        self.dynamic_physics.remove(&id);
+        self.static_physics.remove(&id);
+        self.world_bounds_exempt.remove(&id);
+        self.ghosts.remove(&id);
+        self.parents.remove(&id);
+        {
+            let quad_tree_dirty = &mut self.quad_tree_dirty;
+            self.parents.retain(|&child, parent| {
+                let is_orphaned = parent.id == id;
+                if is_orphaned {
+                    quad_tree_dirty.insert(child);
+                }
+                !is_orphaned
+            });
+        }
+        self.surface_up.remove(&id);
+        self.gravity_scales.remove(&id);
+        self.restitution.remove(&id);
+        self.friction.remove(&id);
+        self.layers.remove(&id);
+        self.masks.remove(&id);
+        self.sprites.remove(&id);
+        self.jump.remove(&id);
+        self.user_data.remove(&id);
+        if let Some(aabb) = self.quad_tree_aabbs.remove(&id) {
+            self.quad_tree.remove_inserted(aabb, &id);
+        }
+        self.quad_tree_dirty.remove(&id);
+        self.previous_positions.remove(&id);
+        self.embedded_normals.remove(&id);
+    }
+    // The velocity a carrier moves the player at, limited by `max_carry_speed`.
+    fn carry_velocity(&self, carrier: Option<EntityId>) -> Vector2<f64> {
+        let velocity = carrier
+            .and_then(|id| self.velocity.get(&id).cloned())
+            .unwrap_or(vec2(0., 0.));
+        match self.config.max_carry_speed {
+            Some(max) if velocity.magnitude() > max => velocity.normalize_to(max),
+            _ => velocity,
+        }
     }
     fn add_static_solid(&mut self, common: EntityCommon) -> EntityId {
-        let id = self.entity_id_allocator.allocate();
-        self.quad_tree.insert(common.aabb(), id);
-        self.common.insert(id, common);
-        id
+        self.add_common(common)
     }
     fn add_common(&mut self, common: EntityCommon) -> EntityId {
         let id = self.entity_id_allocator.allocate();
+        self.quad_tree.insert(common.aabb(), id);
+        self.quad_tree_aabbs.insert(id, common.aabb());
         self.common.insert(id, common);
         id
     }
-    pub fn init_demo(&mut self) {
+    fn collides(&self, id: EntityId) -> bool {
+        let parent_collides = self.parents
+            .get(&id)
+            .map(|parent| parent.collides)
+            .unwrap_or(true);
+        parent_collides && self.resolves_collisions(id)
+    }
+    // Only entities marked dirty since the last rebuild are moved within the
+    // quad tree, so entities that stay put are never touched.
+    fn rebuild_quad_tree(&mut self) {
+        if self.config.broadphase == Broadphase::BruteForce {
+            self.quad_tree.clear();
+            self.quad_tree_aabbs.clear();
+            self.quad_tree_stale = true;
+            return;
+        }
+        if self.quad_tree_stale {
+            self.quad_tree.clear();
+            self.quad_tree_aabbs.clear();
+            self.quad_tree_dirty.extend(self.common.keys().cloned());
+            self.quad_tree_stale = false;
+        }
+        let mut dirty = mem::replace(&mut self.quad_tree_dirty, Default::default());
+        for id in dirty.drain() {
+            let aabb = match self.common.get(&id) {
+                Some(common) if self.collides(id) => common.aabb(),
+                _ => {
+                    if let Some(aabb) = self.quad_tree_aabbs.remove(&id) {
+                        self.quad_tree.remove_inserted(aabb, &id);
+                    }
+                    continue;
+                }
+            };
+            match self.quad_tree_aabbs.insert(id, aabb) {
+                Some(old_aabb) => {
+                    if old_aabb != aabb && !self.quad_tree.update(old_aabb, aabb, id) {
+                        self.quad_tree.insert(aabb, id);
+                    }
+                }
+                None => self.quad_tree.insert(aabb, id),
+            }
+        }
+        self.quad_tree_dirty = dirty;
+    }
+    // With `auto_rebuild_broadphase` off, neither `set_position` nor the start
+    // of `update` rebuild the broadphase, so callers must call this after
+    // moving entities and before the next query or update.
+    pub fn rebuild_broadphase(&mut self) {
+        self.rebuild_quad_tree();
+    }
+    fn for_each_candidate<F: FnMut(EntityId)>(&self, aabb: Aabb, mut f: F) {
+        match self.config.broadphase {
+            Broadphase::QuadTree => {
+                self.quad_tree
+                    .for_each_intersection(aabb, |_aabb, &entity_id| f(entity_id));
+            }
+            Broadphase::BruteForce => for (&id, common) in self.common.iter() {
+                if self.collides(id) && common.aabb().is_intersecting(&aabb) {
+                    f(id);
+                }
+            },
+        }
+    }
+    pub fn user_data(&self, id: EntityId) -> Option<&U> {
+        self.user_data.get(&id)
+    }
+    pub fn user_data_mut(&mut self, id: EntityId) -> Option<&mut U> {
+        self.user_data.get_mut(&id)
+    }
+    pub fn set_sprite(&mut self, id: EntityId, sprite: Sprite) {
+        if self.common.contains_key(&id) {
+            self.sprites.insert(id, sprite);
+        }
+    }
+    pub fn clear_sprite(&mut self, id: EntityId) {
+        self.sprites.remove(&id);
+    }
+    pub fn set_user_data(&mut self, id: EntityId, user_data: U) {
+        if self.common.contains_key(&id) {
+            self.user_data.insert(id, user_data);
+        }
+    }
+    pub fn position(&self, id: EntityId) -> Option<Vector2<f64>> {
+        self.common.get(&id).map(|common| common.position)
+    }
+    pub fn player_id(&self) -> Option<EntityId> {
+        self.player_id
+    }
+    pub fn entity_centre(&self, id: EntityId) -> Option<Vector2<f64>> {
+        self.common.get(&id).map(|common| common.aabb().centre())
+    }
+    // The bottom centre of the entity's bounding box.
+    pub fn entity_feet(&self, id: EntityId) -> Option<Vector2<f64>> {
+        self.common.get(&id).map(|common| {
+            let aabb = common.aabb();
+            vec2(aabb.centre().x, aabb.bottom_right_coord().y)
+        })
+    }
+    // Entities moved this way aren't interpolated from their old position.
+    pub fn set_position(&mut self, id: EntityId, position: Vector2<f64>) {
+        if let Some(common) = self.common.get_mut(&id) {
+            common.position = position;
+            self.previous_positions.insert(id, position);
+            self.embedded_normals.remove(&id);
+            self.quad_tree_dirty.insert(id);
+        }
+        if self.config.auto_rebuild_broadphase {
+            self.rebuild_quad_tree();
+        }
+    }
+    pub fn load(&mut self, level: Level) -> Result<(), binary_level::Error> {
         self.clear();
+        match level {
+            Level::Demo => self.spawn_demo(),
+            Level::Static(ref bytes) => self.load_static(bytes)?,
+        }
+        self.level = Some(level);
+        Ok(())
+    }
+    pub fn reset(&mut self) -> Result<(), binary_level::Error> {
+        match self.level.take() {
+            Some(level) => self.load(level),
+            None => {
+                self.clear();
+                Ok(())
+            }
+        }
+    }
+    pub fn init_demo(&mut self) {
+        self.load(Level::Demo).expect("Failed to load demo");
+    }
+    pub fn spawn_player(
+        &mut self,
+        position: Vector2<f64>,
+        jump: Option<JumpStateMachine>,
+    ) -> EntityId {
+        if let Some(player_id) = self.player_id {
+            self.remove_entity(player_id);
+            self.rebuild_quad_tree();
+        }
         let player_id = self.add_common(EntityCommon::new(
-            vec2(550., 500. - 64.),
+            position,
             Shape::AxisAlignedRect(AxisAlignedRect::new_character(vec2(32., 64.))),
             [1., 0., 0.],
         ));
@@ -305,7 +1176,55 @@ impl GameState {
         self.velocity.insert(player_id, vec2(0., 0.));
         self.dynamic_physics.insert(player_id);
         self.jump
-            .insert(player_id, JumpStateMachine::NotJumping);
+            .insert(player_id, jump.unwrap_or(JumpStateMachine::NotJumping));
+        player_id
+    }
+    // `update` borrows the whole state mutably, so entities can't be spawned
+    // part way through a frame; anything spawned between calls to `update`
+    // takes part in the next one.
+    pub fn spawn_static(
+        &mut self,
+        position: Vector2<f64>,
+        shape: Shape,
+        colour: [f32; 3],
+    ) -> EntityId {
+        self.add_static_solid(EntityCommon::new(position, shape, colour))
+    }
+    pub fn spawn_dynamic(
+        &mut self,
+        position: Vector2<f64>,
+        shape: Shape,
+        colour: [f32; 3],
+    ) -> EntityId {
+        let id = self.add_common(EntityCommon::new(position, shape, colour));
+        self.velocity.insert(id, vec2(0., 0.));
+        self.dynamic_physics.insert(id);
+        id
+    }
+    // Kinematic entities move at their velocity, pushing dynamic entities out
+    // of the way, and aren't affected by gravity or collisions.
+    pub fn spawn_kinematic(
+        &mut self,
+        position: Vector2<f64>,
+        shape: Shape,
+        colour: [f32; 3],
+    ) -> EntityId {
+        let id = self.add_static_solid(EntityCommon::new(position, shape, colour));
+        self.velocity.insert(id, vec2(0., 0.));
+        self.static_physics.insert(id);
+        id
+    }
+    pub fn velocity(&self, id: EntityId) -> Option<Vector2<f64>> {
+        self.velocity.get(&id).cloned()
+    }
+    // Has no effect on static entities, which don't have a velocity.
+    pub fn set_velocity(&mut self, id: EntityId, velocity: Vector2<f64>) {
+        if let Some(current) = self.velocity.get_mut(&id) {
+            *current = velocity;
+        }
+    }
+    fn spawn_demo(&mut self) {
+        self.spawn_player(vec2(550., 500. - 64.), None);
         let moving_platform_id = self.add_static_solid(EntityCommon::new(
             vec2(200., 350.),
             Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(128., 32.))),
@@ -314,6 +1233,7 @@ impl GameState {
         self.moving_platform_ids.push(moving_platform_id);
         self.velocity.insert(moving_platform_id, vec2(0., 0.));
         self.static_physics.insert(moving_platform_id);
+        self.world_bounds_exempt.insert(moving_platform_id);
 
         let moving_platform_id = self.add_static_solid(EntityCommon::new(
             vec2(700., 450.),
@@ -326,6 +1246,7 @@ impl GameState {
         self.moving_platform_ids.push(moving_platform_id);
         self.velocity.insert(moving_platform_id, vec2(0., 0.));
         self.static_physics.insert(moving_platform_id);
+        self.world_bounds_exempt.insert(moving_platform_id);
 
         self.add_static_solid(EntityCommon::new(
             vec2(700., 200.),
@@ -343,6 +1264,21 @@ impl GameState {
             Shape::AxisAlignedRect(AxisAlignedRect::new_floor_only(vec2(500., 20.))),
             [1., 1., 1.],
         ));
+        self.add_static_solid(EntityCommon::new(
+            vec2(450., 330.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new_ceiling_only(vec2(100., 20.))),
+            [1., 1., 1.],
+        ));
+        self.add_static_solid(EntityCommon::new(
+            vec2(180., 400.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new_wall_right_only(vec2(20., 80.))),
+            [1., 1., 1.],
+        ));
+        self.add_static_solid(EntityCommon::new(
+            vec2(250., 400.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new_wall_left_only(vec2(20., 80.))),
+            [1., 1., 1.],
+        ));
         self.add_static_solid(EntityCommon::new(
             vec2(50., 450.),
             Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(100., 20.))),
@@ -430,33 +1366,478 @@ impl GameState {
         self.moving_platform_ids.push(moving_platform_id);
         self.velocity.insert(moving_platform_id, vec2(0., 0.));
         self.static_physics.insert(moving_platform_id);
+        self.world_bounds_exempt.insert(moving_platform_id);
     }
-    pub fn update(
+    pub fn add_polyline(
         &mut self,
-        input_model: &InputModel,
-        changes: &mut GameStateChanges,
-        movement_context: &mut MovementContext,
-    ) {
-        self.quad_tree.clear();
-        for (id, common) in self.common.iter() {
-            self.quad_tree.insert(common.aabb(), *id);
+        position: Vector2<f64>,
+        polyline: &Polyline,
+        colour: [f32; 3],
+    ) -> Vec<EntityId> {
+        polyline
+            .line_segments()
+            .into_iter()
+            .map(|line_segment| {
+                self.add_static_solid(EntityCommon::new(
+                    position,
+                    Shape::LineSegment(line_segment),
+                    colour,
+                ))
+            })
+            .collect()
+    }
+    pub fn save_static(&self) -> Vec<u8> {
+        let entities = self.common
+            .iter()
+            .filter(|&(id, _)| {
+                !self.dynamic_physics.contains(id) && !self.static_physics.contains(id)
+            })
+            .map(|(_, common)| StaticEntity {
+                position: common.position,
+                shape: common.shape.clone(),
+                colour: common.colour,
+            })
+            .collect::<Vec<_>>();
+        binary_level::encode(&entities)
+    }
+    pub fn load_static(&mut self, bytes: &[u8]) -> Result<(), binary_level::Error> {
+        for entity in binary_level::decode(bytes)? {
+            self.add_static_solid(EntityCommon::new(
+                entity.position,
+                entity.shape,
+                entity.colour,
+            ));
         }
-
-        self.velocity.insert(
-            self.moving_platform_ids[0],
-            vec2(((self.frame_count as f64) * 0.05).sin() * 2., 0.),
-        );
-        self.velocity.insert(
-            self.moving_platform_ids[1],
-            vec2(0., ((self.frame_count as f64) * 0.1).sin() * 4.),
-        );
-        self.velocity.insert(
-            self.moving_platform_ids[2],
-            vec2(((self.frame_count as f64) * 0.1).sin() * 5., 0.),
+        Ok(())
+    }
+    #[cfg(feature = "tiled")]
+    pub fn load_tiled(&mut self, tmx: &str) -> Result<(), tiled::Error> {
+        for entity in tiled::parse(tmx)? {
+            self.add_static_solid(EntityCommon::new(
+                entity.position,
+                entity.shape,
+                entity.colour,
+            ));
+        }
+        Ok(())
+    }
+    // Replaces the current level with the entities described in a RON file.
+    // Nothing is changed if the file can't be loaded. `reset` clears a level
+    // loaded this way rather than reloading it.
+    #[cfg(feature = "ron_level")]
+    pub fn load_level(&mut self, path: &Path) -> Result<(), LevelError> {
+        let mut ron = String::new();
+        File::open(path)?.read_to_string(&mut ron)?;
+        let entities = ron_level::parse(&ron)?;
+        self.clear();
+        self.level = None;
+        for entity in entities {
+            let LevelEntity {
+                kind,
+                position,
+                shape,
+                colour,
+            } = entity;
+            match (kind, shape) {
+                (EntityKind::Player, _) => {
+                    self.spawn_player(position, None);
+                }
+                (EntityKind::Static, Some(shape)) => {
+                    self.spawn_static(position, shape, colour);
+                }
+                (EntityKind::Dynamic, Some(shape)) => {
+                    self.spawn_dynamic(position, shape, colour);
+                }
+                (EntityKind::Kinematic, Some(shape)) => {
+                    self.spawn_kinematic(position, shape, colour);
+                }
+                (_, None) => (),
+            }
+        }
+        Ok(())
+    }
+    pub fn shapes_overlapping_aabb(&self, aabb: Aabb) -> Vec<EntityId> {
+        let mut entity_ids = Vec::new();
+        self.for_each_candidate(aabb, |entity_id| {
+            let common = self.common.get(&entity_id).unwrap();
+            if common.shape.is_intersecting_aabb(common.position, &aabb) {
+                entity_ids.push(entity_id);
+            }
+        });
+        entity_ids
+    }
+    // Unlike `shapes_overlapping_aabb`, this only tests bounding boxes, so a
+    // diagonal line segment may be returned for a region it doesn't touch.
+    pub fn entities_in_region(&self, aabb: Aabb) -> Vec<EntityId> {
+        let mut seen = FnvHashSet::default();
+        let mut entity_ids = Vec::new();
+        self.for_each_candidate(aabb, |entity_id| {
+            if seen.insert(entity_id) {
+                entity_ids.push(entity_id);
+            }
+        });
+        entity_ids.sort();
+        entity_ids
+    }
+    // Line segments have no area, so they're picked from up to `PICK_RADIUS`
+    // away. Where several entities are under the point, the most recently
+    // spawned one is returned.
+    pub fn entity_at_point(&self, point: Vector2<f64>) -> Option<EntityId> {
+        let pick_aabb =
+            Aabb::from_centre_and_half_size(point, vec2(PICK_RADIUS, PICK_RADIUS));
+        let mut picked = None;
+        self.for_each_candidate(pick_aabb, |entity_id| {
+            let common = self.common.get(&entity_id).unwrap();
+            let is_under_point = match common.shape {
+                Shape::LineSegment(_) => {
+                    common.shape.is_intersecting_aabb(common.position, &pick_aabb)
+                }
+                _ => common.shape.contains_point(common.position, point),
+            };
+            if is_under_point {
+                picked = Some(picked.map_or(entity_id, |id: EntityId| id.max(entity_id)));
+            }
+        });
+        picked
+    }
+    pub fn entities_near(
+        &self,
+        point: Vector2<f64>,
+        radius: f64,
+    ) -> Vec<(EntityId, f64)> {
+        let mut entities = Vec::new();
+        let query = Aabb::from_centre_and_half_size(point, vec2(radius, radius));
+        self.for_each_candidate(query, |entity_id| {
+            let aabb = self.common.get(&entity_id).unwrap().aabb();
+            let top_left = aabb.top_left();
+            let bottom_right = aabb.bottom_right_coord();
+            let closest = vec2(
+                point.x.max(top_left.x).min(bottom_right.x),
+                point.y.max(top_left.y).min(bottom_right.y),
+            );
+            let distance = (closest - point).magnitude();
+            if distance <= radius {
+                entities.push((entity_id, distance));
+            }
+        });
+        entities.sort_by(|a, b| {
+            a.1
+                .partial_cmp(&b.1)
+                .unwrap_or(Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+        entities
+    }
+    pub fn raycast_all(
+        &self,
+        origin: Vector2<f64>,
+        direction: Vector2<f64>,
+        max_distance: f64,
+    ) -> Vec<RayHit> {
+        let mut hits = Vec::new();
+        if direction.magnitude2() == 0. {
+            return hits;
+        }
+        let direction = direction.normalize();
+        let end = origin + direction * max_distance;
+        let aabb = Aabb::from_union(
+            &Aabb::new(origin, vec2(0., 0.)),
+            &Aabb::new(end, vec2(0., 0.)),
         );
+        self.for_each_candidate(aabb, |entity_id| {
+            let common = self.common.get(&entity_id).unwrap();
+            let hit = common
+                .shape
+                .raycast(common.position, origin, direction, max_distance);
+            if let Some((distance, normal)) = hit {
+                hits.push(RayHit {
+                    entity_id,
+                    distance,
+                    point: origin + direction * distance,
+                    normal,
+                });
+            }
+        });
+        hits.sort_by(|a, b| {
+            a.distance
+                .partial_cmp(&b.distance)
+                .unwrap_or(Ordering::Equal)
+                .then(a.entity_id.cmp(&b.entity_id))
+        });
+        hits
+    }
+    pub fn ground_profile(
+        &self,
+        x_start: f64,
+        x_end: f64,
+        step: f64,
+        from_y: f64,
+    ) -> Vec<(f64, Option<f64>)> {
+        if step <= 0. || x_end < x_start {
+            return Vec::new();
+        }
+        let num_samples = ((x_end - x_start) / step).floor() as usize + 1;
+        (0..num_samples)
+            .map(|i| {
+                let x = x_start + step * (i as f64);
+                let surface_y = self
+                    .raycast_all(vec2(x, from_y), vec2(0., 1.), GROUND_PROBE_DISTANCE)
+                    .first()
+                    .map(|hit| hit.point.y);
+                (x, surface_y)
+            })
+            .collect()
+    }
+    fn has_contact_towards(
+        &self,
+        id: EntityId,
+        direction: Vector2<f64>,
+        movement_context: &mut MovementContext,
+    ) -> bool {
+        let common = match self.common.get(&id) {
+            Some(common) => common,
+            None => return false,
+        };
+        let shape_position = ShapePosition {
+            entity_id: id,
+            position: common.position,
+            shape: &common.shape,
+        };
+        movement_context
+            .collisions_towards(shape_position, direction, &AllShapePositions(self))
+            .can_jump()
+    }
+    fn deepest_penetration(&self, id: EntityId) -> Option<Vector2<f64>> {
+        if !self.resolves_collisions(id) {
+            return None;
+        }
+        let common = self.common.get(&id)?;
+        let mut deepest: Option<Vector2<f64>> = None;
+        StaticShapePositions(self).for_each(common.aabb(), |other| {
+            if !other.shape.is_solid() || !self.layers_collide(id, other.entity_id) {
+                return;
+            }
+            let translation = common
+                .shape
+                .penetration(common.position, other.shape, other.position);
+            if let Some(translation) = translation {
+                let is_deeper = deepest
+                    .map(|deepest| translation.magnitude2() > deepest.magnitude2())
+                    .unwrap_or(true);
+                if is_deeper {
+                    deepest = Some(translation);
+                }
+            }
+        });
+        deepest
+    }
+    // Per axis, the largest push in each direction out of every overlapping
+    // solid. Opposing pushes cancel rather than shoving the body into a
+    // neighbouring surface.
+    fn combined_penetration(&self, id: EntityId) -> Option<Vector2<f64>> {
+        let common = self.common.get(&id)?;
+        let mut min: Vector2<f64> = vec2(0., 0.);
+        let mut max: Vector2<f64> = vec2(0., 0.);
+        let mut overlapping = false;
+        StaticShapePositions(self).for_each(common.aabb(), |other| {
+            if other.entity_id == id || !other.shape.is_solid()
+                || !self.layers_collide(id, other.entity_id)
+            {
+                return;
+            }
+            let translation = common
+                .shape
+                .penetration(common.position, other.shape, other.position);
+            if let Some(translation) = translation {
+                overlapping = true;
+                min = vec2(min.x.min(translation.x), min.y.min(translation.y));
+                max = vec2(max.x.max(translation.x), max.y.max(translation.y));
+            }
+        });
+        if overlapping {
+            Some(min + max)
+        } else {
+            None
+        }
+    }
+    pub fn resolve_overlaps(&mut self, id: EntityId) -> Vector2<f64> {
+        let mut total = vec2(0., 0.);
+        if !self.resolves_collisions(id) {
+            return total;
+        }
+        for _ in 0..MAX_RESOLVE_ITERATIONS {
+            let translation = match self.combined_penetration(id) {
+                Some(translation) => translation,
+                None => break,
+            };
+            if translation.magnitude2() == 0. {
+                break;
+            }
+            if let Some(common) = self.common.get_mut(&id) {
+                common.position += translation;
+            }
+            self.quad_tree_dirty.insert(id);
+            total += translation;
+        }
+        if self.config.auto_rebuild_broadphase {
+            self.rebuild_quad_tree();
+        }
+        total
+    }
+    pub fn static_overlaps(&self) -> Vec<(EntityId, EntityId)> {
+        let mut overlaps = Vec::new();
+        for (&id, common) in self.common.iter() {
+            if self.dynamic_physics.contains(&id) {
+                continue;
+            }
+            self.for_each_candidate(common.aabb(), |other_id| {
+                if other_id <= id || self.dynamic_physics.contains(&other_id) {
+                    return;
+                }
+                let other = self.common.get(&other_id).unwrap();
+                if common.shape.is_overlapping(
+                    common.position,
+                    &other.shape,
+                    other.position,
+                ) {
+                    overlaps.push((id, other_id));
+                }
+            });
+        }
+        overlaps.sort();
+        overlaps
+    }
+    pub fn predict_position(
+        &self,
+        id: EntityId,
+        frames: u32,
+        movement_context: &mut MovementContext,
+    ) -> Option<Vector2<f64>> {
+        let common = self.common.get(&id)?;
+        if !self.dynamic_physics.contains(&id) {
+            return Some(common.position);
+        }
+        movement_context.config_mut().enabled_channels = self.enabled_channels;
+        // Each frame is a call to `update` with `FIXED_DT`.
+        let dt = self.time_scale;
+        let gravity = self.entity_gravity(id);
+        let mut position = common.position;
+        let mut velocity = self.velocity.get(&id).cloned().unwrap_or(vec2(0., 0.));
+        for _ in 0..frames {
+            let (target, next_velocity) =
+                self.integrator.integrate(position, velocity, gravity, dt);
+            let shape_position = ShapePosition {
+                entity_id: id,
+                position,
+                shape: &common.shape,
+            };
+            let allowed = movement_context.position_after_allowed_movement(
+                shape_position,
+                target - position,
+                &StaticShapePositions(self),
+            );
+            velocity = if allowed.position != target && dt > 0. {
+                allowed.velocity / dt
+            } else {
+                next_velocity
+            };
+            position = allowed.position;
+        }
+        Some(position)
+    }
+    pub fn distance_to_ground(
+        &self,
+        id: EntityId,
+        movement_context: &mut MovementContext,
+    ) -> Option<f64> {
+        let common = self.common.get(&id)?;
+        movement_context.config_mut().enabled_channels = self.enabled_channels;
+        let shape_position = ShapePosition {
+            entity_id: id,
+            position: common.position,
+            shape: &common.shape,
+        };
+        movement_context
+            .shape_cast(
+                shape_position,
+                vec2(0., GROUND_PROBE_DISTANCE),
+                &AllShapePositions(self),
+            )
+            .map(|movement| movement.y)
+    }
+    // Edges only collide when moving into each other, so sweeping an entity's
+    // own aabb isn't blocked by the entity.
+    pub fn sweep_aabb(
+        &self,
+        aabb: Aabb,
+        movement: Vector2<f64>,
+        movement_context: &mut MovementContext,
+    ) -> Option<SweepHit> {
+        movement_context.config_mut().enabled_channels = self.enabled_channels;
+        movement_context.sweep_aabb(aabb, movement, &AllShapePositions(self))
+    }
+    // Advances the simulation by `dt` seconds. Settings counted in frames,
+    // such as `coyote_frames`, count calls to `update`, so callers should
+    // pass `FIXED_DT` and run as many updates as the elapsed time calls for.
+    pub fn update(
+        &mut self,
+        input_model: &InputModel,
+        changes: &mut GameStateChanges,
+        movement_context: &mut MovementContext,
+        dt: f64,
+    ) {
+        movement_context.clear_collision_events();
+        movement_context.clear_candidate_count();
+        movement_context.config_mut().enabled_channels = self.enabled_channels;
+        if self.config.auto_rebuild_broadphase {
+            self.rebuild_quad_tree();
+        }
+
+        let dt = dt / FIXED_DT * self.time_scale;
+
+        self.previous_positions.clear();
+        for (id, common) in self.common.iter() {
+            self.previous_positions.insert(*id, common.position);
+        }
 
-        let player_id = self.player_id.expect("No player id");
+        let platform_velocities = [
+            vec2((self.time * 0.05).sin() * 2., 0.),
+            vec2(0., (self.time * 0.1).sin() * 4.),
+            vec2((self.time * 0.1).sin() * 5., 0.),
+        ];
+        for (id, velocity) in self.moving_platform_ids
+            .iter()
+            .zip(platform_velocities.iter())
         {
+            self.velocity.insert(*id, *velocity);
+        }
+
+        self.player_drop_through_frames = match self.player_drop_through_frames {
+            Some(0) | None => None,
+            Some(frames) => Some(frames - 1),
+        };
+        if self.player_drop_through_frames.is_some() {
+            movement_context.config_mut().enabled_channels =
+                self.enabled_channels & !channels::FLOOR;
+        }
+
+        let mut player_ground_contacts = Vec::new();
+        if let Some(player_id) = self.player_id {
+            let gravity = self.entity_gravity(player_id);
+            let up = self.local_up(player_id).unwrap_or(vec2(0., -1.));
+            let wall = {
+                let player_common = self.common.get(&player_id).unwrap();
+                let player_shape_position = ShapePosition {
+                    entity_id: player_id,
+                    position: player_common.position,
+                    shape: &player_common.shape,
+                };
+                movement_context.collisions_beside(
+                    player_shape_position,
+                    up,
+                    &AllShapePositions(self),
+                )
+            };
             let collisions_below_player = {
                 let player_common = self.common.get(&player_id).unwrap();
                 let player_shape_position = ShapePosition {
@@ -465,19 +1846,121 @@ impl GameState {
                     shape: &player_common.shape,
                 };
 
-                movement_context
-                    .collisions_below(player_shape_position, &AllShapePositions(self))
+                movement_context.collisions_towards(
+                    player_shape_position,
+                    -up,
+                    &AllShapePositions(self),
+                )
+            };
+
+            let max_platform_velocity = {
+                let velocity = &self.velocity;
+                let kinematic = &self.static_physics;
+                collisions_below_player
+                    .max_velocity(|id| surface_velocity(velocity, kinematic, id))
             };
+            let max_platform_velocity =
+                match (max_platform_velocity, self.config.max_carry_speed) {
+                    (Some(velocity), Some(max)) if velocity.magnitude() > max => {
+                        Some(velocity.normalize_to(max))
+                    }
+                    (velocity, _) => velocity,
+                };
 
+            let leaving_surface = is_leaving_surface(
+                self.velocity.get(&player_id).cloned().unwrap_or(vec2(0., 0.)),
+                max_platform_velocity,
+                up,
+                self.config.landing_velocity_threshold,
+            );
+            self.player_grounded = collisions_below_player.can_jump() && !leaving_surface;
+            if self.player_grounded {
+                player_ground_contacts.extend(
+                    collisions_below_player
+                        .entity_ids()
+                        .map(|id| (player_id, id)),
+                );
+            }
+            if self.player_grounded && input_model.vertical() > 0.
+                && collisions_below_player.is_floor_only()
+            {
+                self.player_grounded = false;
+                self.player_drop_through_frames = Some(self.config.drop_through_frames);
+            }
+            // The player's velocity is relative to the kinematic entity carrying
+            // it, so switching carrier (including to or from none) swaps which
+            // velocity it's relative to, keeping momentum when stepping off.
+            let carrier = if self.player_grounded {
+                let velocity = &self.velocity;
+                let kinematic = &self.static_physics;
+                collisions_below_player.fastest_entity(|id| {
+                    if kinematic.contains(&id) {
+                        velocity.get(&id).cloned()
+                    } else {
+                        None
+                    }
+                })
+            } else {
+                None
+            };
+            if carrier != self.player_carrier {
+                let change = self.carry_velocity(self.player_carrier)
+                    - self.carry_velocity(carrier);
+                if let Some(velocity) = self.velocity.get_mut(&player_id) {
+                    *velocity += change;
+                }
+                self.player_carrier = carrier;
+            }
+            self.player_frames_since_grounded = if self.player_grounded {
+                Some(0)
+            } else {
+                self.player_frames_since_grounded.map(|frames| frames + 1)
+            };
+            // Coyote time: a jump still registers for a few frames after
+            // running off a ledge.
+            let coyote_frames = self.config.coyote_frames;
+            let can_jump = self.player_frames_since_grounded
+                .map(|frames| frames <= coyote_frames)
+                .unwrap_or(false);
+            self.player_frames_since_jump_pressed = match input_model.jump_count {
+                Some(0) => Some(0),
+                _ => self.player_frames_since_jump_pressed.map(|frames| frames + 1),
+            };
             let jump = self.jump
                 .get_mut(&player_id)
                 .expect("No jump for player");
+            jump.step(can_jump, input_model);
+            // A press shortly before landing is remembered and jumps on the
+            // first frame the player can.
+            let jump_buffer_frames = self.config.jump_buffer_frames;
+            let jump_buffered = self.player_frames_since_jump_pressed
+                .map(|frames| frames <= jump_buffer_frames)
+                .unwrap_or(false);
+            if let JumpStateMachine::NotJumping = *jump {
+                if can_jump && jump_buffered {
+                    *jump = JumpStateMachine::JumpingForFrames(0);
+                }
+            }
+            // Pressing jump in the air against a wall kicks off it instead.
+            let wall_jump = match wall {
+                Some(side) if !can_jump && input_model.jump_count == Some(0) => {
+                    self.player_frames_since_jump_pressed = None;
+                    Some(side)
+                }
+                _ => None,
+            };
+            if let JumpStateMachine::JumpingForFrames(0) = *jump {
+                self.player_frames_since_grounded = None;
+                self.player_frames_since_jump_pressed = None;
+            }
 
-            jump.step(collisions_below_player.can_jump(), input_model);
-
-            let max_platform_velocity = {
-                let velocity = &mut self.velocity;
-                collisions_below_player.max_velocity(|id| velocity.get(&id).cloned())
+            let surface_friction = if self.player_grounded {
+                let friction = &self.friction;
+                collisions_below_player.max_friction(|id| {
+                    friction.get(&id).cloned().unwrap_or(DEFAULT_FRICTION)
+                })
+            } else {
+                None
             };
 
             if let Some(velocity) = self.velocity.get_mut(&player_id) {
@@ -485,88 +1968,2809 @@ impl GameState {
                     *velocity,
                     input_model,
                     max_platform_velocity,
+                    surface_friction,
+                    wall,
                     jump,
+                    &self.config,
+                    gravity,
+                    up,
+                    dt,
                 );
+                if let Some(side) = wall_jump {
+                    let away = match side {
+                        Side::Left => 1.,
+                        Side::Right => -1.,
+                    };
+                    let tangent = vec2(-up.y, up.x);
+                    *velocity = tangent * self.config.wall_jump_velocity.x * away
+                        + up * self.config.wall_jump_velocity.y;
+                }
             }
         }
 
         for id in self.dynamic_physics.iter() {
+            if self.parents.contains_key(id) {
+                continue;
+            }
             if let Some(velocity) = self.velocity.get(id) {
                 if let Some(common) = self.common.get(id) {
+                    // The player's gravity was applied along with its input.
+                    let gravity = if self.player_id == Some(*id) {
+                        vec2(0., 0.)
+                    } else {
+                        self.entity_gravity(*id)
+                    };
+                    let acceleration = gravity + match self.global_force {
+                        Some(ref global_force) => {
+                            global_force(self.frame_count, common.position)
+                        }
+                        None => vec2(0., 0.),
+                    };
+                    let (target, velocity) = self.integrator.integrate(
+                        common.position,
+                        *velocity,
+                        acceleration,
+                        dt,
+                    );
+                    let mut movement = target - common.position;
+                    let mut velocity = velocity;
+                    // A body embedded in geometry can't collide with the surface
+                    // it's inside, so stop it moving any deeper while the bias
+                    // eases it out.
+                    if let Some(&normal) = self.embedded_normals.get(id) {
+                        movement -= normal * movement.dot(normal).min(0.);
+                        velocity -= normal * velocity.dot(normal).min(0.);
+                    }
                     let shape_position = ShapePosition {
                         entity_id: *id,
                         position: common.position,
                         shape: &common.shape,
                     };
-                    let movement = movement_context.position_after_allowed_movement(
-                        shape_position,
-                        *velocity,
-                        &AllShapePositions(self),
-                    );
-                    changes.velocity.insert(*id, movement.velocity);
-                    changes.position.push((*id, movement.position));
+                    movement_context.config_mut().enabled_channels =
+                        if self.player_id == Some(*id)
+                            && self.player_drop_through_frames.is_some()
+                        {
+                            self.enabled_channels & !channels::FLOOR
+                        } else {
+                            self.enabled_channels
+                        };
+                    if self.ghosts.contains(id) {
+                        movement_context.collisions_along_movement(
+                            shape_position,
+                            movement,
+                            &AllShapePositions(self),
+                        );
+                        changes.velocity.insert(*id, velocity);
+                        changes.position.push((*id, target));
+                        continue;
+                    }
+                    let allowed = movement_context
+                        .position_after_allowed_movement_with_restitution(
+                            shape_position,
+                            movement,
+                            self.restitution(*id),
+                            &AllShapePositions(self),
+                        );
+                    if allowed.position != common.position + movement && dt > 0. {
+                        velocity = allowed.velocity / dt;
+                    }
+                    changes.velocity.insert(*id, velocity);
+                    changes.position.push((*id, allowed.position));
                 }
             }
         }
+        movement_context.config_mut().enabled_channels = self.enabled_channels;
 
         for (id, position) in changes.position.drain(..) {
             if let Some(common) = self.common.get_mut(&id) {
                 common.position = position;
             }
+            self.quad_tree_dirty.insert(id);
         }
 
         for (id, velocity) in changes.velocity.drain() {
             self.velocity.insert(id, velocity);
         }
 
-        self.quad_tree.clear();
-        for (id, common) in self.common.iter() {
-            self.quad_tree.insert(common.aabb(), *id);
+        for event in movement_context.collision_events() {
+            if let Some(up) = self.surface_up.get_mut(&event.moving_entity_id) {
+                *up = event.normal.normalize();
+            }
         }
 
-        for id in self.static_physics.iter() {
-            if let Some(velocity) = self.velocity.get(id) {
-                if let Some(common) = self.common.get(id) {
+        self.rebuild_quad_tree();
+
+        let kinematic_ids = self.static_physics
+            .iter()
+            .filter(|id| !self.parents.contains_key(id))
+            .cloned()
+            .collect::<Vec<_>>();
+        for id in kinematic_ids {
+            let velocity = self.velocity.get(&id).cloned();
+            let (movement, substeps) = match (velocity, self.common.get(&id)) {
+                (Some(velocity), Some(common)) => {
+                    let movement = velocity * dt;
+                    let size = common.aabb().size();
+                    let max_step = (size.x.min(size.y) / 2.).max(MIN_PUSH_STEP);
+                    let substeps = (movement.magnitude() / max_step).ceil() as u32;
+                    (
+                        movement,
+                        substeps.max(1).min(self.config.max_kinematic_substeps),
+                    )
+                }
+                _ => continue,
+            };
+            let step = movement / (substeps as f64);
+            let step_dt = dt / (substeps as f64);
+            let carry_step = self.carry_velocity(Some(id)) * step_dt;
+            let carried_id = if self.player_carrier == Some(id) {
+                self.player_id
+            } else {
+                None
+            };
+            for substep in 0..substeps {
+                let first_event = movement_context.collision_events().len();
+                // The carried player moves first, ignoring its carrier, so it's
+                // only pushed if something blocked it.
+                if let Some(player_id) = carried_id {
+                    let carried = {
+                        let common = self.common.get(&player_id).unwrap();
+                        let shape_position = ShapePosition {
+                            entity_id: player_id,
+                            position: common.position,
+                            shape: &common.shape,
+                        };
+                        movement_context.position_after_allowed_movement(
+                            shape_position,
+                            carry_step,
+                            &ShapePositionsExcept(self, id),
+                        )
+                    };
+                    if let Some(common) = self.common.get_mut(&player_id) {
+                        common.position = carried.position;
+                    }
+                    self.quad_tree_dirty.insert(player_id);
+                    self.rebuild_quad_tree();
+                }
+                {
+                    let common = self.common.get(&id).unwrap();
                     let shape_position = ShapePosition {
-                        entity_id: *id,
+                        entity_id: id,
                         position: common.position,
                         shape: &common.shape,
                     };
                     movement_context.displacement_after_movement(
                         shape_position,
-                        *velocity,
+                        step,
                         &DynamicPhysicsShapePositions(self),
                         &mut changes.displacements,
                     );
-                    changes
-                        .position
-                        .push((*id, common.position + velocity));
                 }
+                // Pushed bodies move before the pusher, so it's behind them,
+                // and are stopped by anything else in the way rather than
+                // being shoved through it. A body pinned like this is left
+                // overlapping the pusher.
+                for (id, displacement) in changes.displacements.drain(..) {
+                    let position = {
+                        let common = self.common.get(&id).unwrap();
+                        let shape_position = ShapePosition {
+                            entity_id: id,
+                            position: common.position,
+                            shape: &common.shape,
+                        };
+                        movement_context
+                            .position_after_allowed_movement(
+                                shape_position,
+                                displacement.movement,
+                                &StaticShapePositions(self),
+                            )
+                            .position
+                    };
+                    if let Some(common) = self.common.get_mut(&id) {
+                        common.position = position;
+                    }
+                    self.quad_tree_dirty.insert(id);
+                    // The carried player's velocity is already relative to
+                    // the carrier.
+                    if Some(id) == carried_id {
+                        continue;
+                    }
+                    if let Some(velocity) = self.velocity.get_mut(&id) {
+                        let displacement = Displacement {
+                            movement: displacement.movement,
+                            velocity: displacement.velocity / step_dt,
+                        };
+                        *velocity = displacement.combine_velocity(*velocity);
+                    }
+                }
+                movement_context.rescale_collision_events(
+                    first_event,
+                    substep as f64 / substeps as f64,
+                    1. / substeps as f64,
+                );
+                if let Some(common) = self.common.get_mut(&id) {
+                    common.position += step;
+                }
+                self.quad_tree_dirty.insert(id);
+                self.rebuild_quad_tree();
             }
         }
 
-        for (id, displacement) in changes.displacements.drain(..) {
-            if let Some(common) = self.common.get_mut(&id) {
-                common.position += displacement.movement;
+        // Penetrations are found once per frame, once everything has moved,
+        // and used both for the bias and for detecting crushed bodies.
+        let penetrations = self.dynamic_physics
+            .iter()
+            .filter_map(|&id| {
+                self.deepest_penetration(id)
+                    .map(|translation| (id, translation))
+            })
+            .collect::<Vec<_>>();
+        self.embedded_normals.clear();
+        if self.config.penetration_bias > 0. {
+            for &(id, translation) in penetrations.iter() {
+                let depth = translation.magnitude();
+                let correction = (depth - PENETRATION_SLOP).max(0.)
+                    * (self.config.penetration_bias * dt).min(1.);
+                let position = self.common.get(&id).unwrap().position;
+                changes
+                    .position
+                    .push((id, position + translation * (correction / depth)));
+                self.embedded_normals.insert(id, translation / depth);
+            }
+            for (id, position) in changes.position.drain(..) {
+                if let Some(common) = self.common.get_mut(&id) {
+                    common.position = position;
+                }
+                self.quad_tree_dirty.insert(id);
+            }
+            self.rebuild_quad_tree();
+        }
+
+        self.crushed.clear();
+        for id in self.dynamic_physics.iter() {
+            if self.ghosts.contains(id) {
+                continue;
+            }
+            let up = self.local_up(*id).unwrap_or(vec2(0., -1.));
+            let on_floor = self.has_contact_towards(*id, -up, movement_context);
+            let on_ceiling = self.has_contact_towards(*id, up, movement_context);
+            if on_floor && on_ceiling {
+                if let Some(velocity) = self.velocity.get(id) {
+                    changes.velocity.insert(*id, velocity - up * velocity.dot(up));
+                }
             }
-            if let Some(velocity) = self.velocity.get_mut(&id) {
-                *velocity = displacement.combine_velocity(*velocity);
+        }
+        for &(id, translation) in penetrations.iter() {
+            if self.ghosts.contains(&id) {
+                continue;
+            }
+            let is_pinned = translation.magnitude() > PENETRATION_SLOP
+                && self.has_contact_towards(id, translation, movement_context);
+            if is_pinned {
+                self.crushed.push(id);
+            }
+        }
+        for (id, velocity) in changes.velocity.drain() {
+            self.velocity.insert(id, velocity);
+        }
+
+        if let Some(world_bounds) = self.config.world_bounds {
+            let moving = self.dynamic_physics.iter().chain(self.static_physics.iter());
+            for id in moving {
+                if self.world_bounds_exempt.contains(id)
+                    || self.parents.contains_key(id)
+                {
+                    continue;
+                }
+                if let Some(common) = self.common.get_mut(id) {
+                    let offset = world_bounds.offset_to_contain(&common.aabb());
+                    common.position += offset;
+                    self.quad_tree_dirty.insert(*id);
+                    if let Some(velocity) = self.velocity.get_mut(id) {
+                        if offset.x != 0. {
+                            velocity.x = 0.;
+                        }
+                        if offset.y != 0. {
+                            velocity.y = 0.;
+                        }
+                    }
+                }
             }
         }
 
+        for id in self.parents.keys() {
+            if let Some(position) = self.parented_position(*id) {
+                changes.position.push((*id, position));
+            }
+        }
         for (id, position) in changes.position.drain(..) {
             if let Some(common) = self.common.get_mut(&id) {
                 common.position = position;
             }
+            self.quad_tree_dirty.insert(id);
+        }
+
+        self.killed.clear();
+        self.player_killed = false;
+        if let Some(kill_plane_y) = self.config.kill_plane_y {
+            for id in self.dynamic_physics.iter() {
+                if let Some(common) = self.common.get(id) {
+                    if common.aabb().top_left().y > kill_plane_y {
+                        self.killed.push(*id);
+                    }
+                }
+            }
+            for id in self.killed.clone() {
+                if self.player_id == Some(id) {
+                    self.player_killed = true;
+                }
+                self.remove_entity(id);
+            }
+            if !self.killed.is_empty() {
+                self.rebuild_quad_tree();
+            }
+        }
+
+        if let Some(player_id) = self.player_id {
+            self.rebuild_quad_tree();
+            let up = self.local_up(player_id).unwrap_or(vec2(0., -1.));
+            let player_can_jump = {
+                let common = self.common.get(&player_id).unwrap();
+                let shape_position = ShapePosition {
+                    entity_id: player_id,
+                    position: common.position,
+                    shape: &common.shape,
+                };
+                let collisions_below = movement_context.collisions_towards(
+                    shape_position,
+                    -up,
+                    &AllShapePositions(self),
+                );
+                let velocity = &self.velocity;
+                let kinematic = &self.static_physics;
+                let leaving_surface = is_leaving_surface(
+                    velocity.get(&player_id).cloned().unwrap_or(vec2(0., 0.)),
+                    collisions_below
+                        .max_velocity(|id| surface_velocity(velocity, kinematic, id)),
+                    up,
+                    self.config.landing_velocity_threshold,
+                );
+                collisions_below.can_jump() && !leaving_surface
+            };
+            let in_coyote_time = self.player_frames_since_grounded
+                .map(|frames| frames < self.config.coyote_frames)
+                .unwrap_or(false);
+            self.player_can_jump = player_can_jump || in_coyote_time;
+        }
+
+        self.collision_events.clear();
+        self.collision_events
+            .extend_from_slice(movement_context.collision_events());
+        self.collision_events.sort_by(|a, b| {
+            a.time_of_impact
+                .partial_cmp(&b.time_of_impact)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        // Removed entities drop out of the overlaps, so they get an exit event.
+        let mut sensor_overlaps = FnvHashSet::default();
+        for id in self.dynamic_physics.iter() {
+            if let Some(common) = self.common.get(id) {
+                let shape_position = ShapePosition {
+                    entity_id: *id,
+                    position: common.position,
+                    shape: &common.shape,
+                };
+                let sensor_ids = movement_context
+                    .overlapping_sensors(shape_position, &AllShapePositions(self));
+                for sensor_id in sensor_ids {
+                    sensor_overlaps.insert((*id, sensor_id));
+                }
+            }
+        }
+        self.sensor_events.clear();
+        for &(entity_id, sensor_id) in sensor_overlaps.iter() {
+            if !self.sensor_overlaps.contains(&(entity_id, sensor_id)) {
+                self.sensor_events.push(SensorEvent {
+                    entity_id,
+                    sensor_id,
+                    kind: SensorEventKind::Enter,
+                });
+            }
+        }
+        for &(entity_id, sensor_id) in self.sensor_overlaps.iter() {
+            if !sensor_overlaps.contains(&(entity_id, sensor_id)) {
+                self.sensor_events.push(SensorEvent {
+                    entity_id,
+                    sensor_id,
+                    kind: SensorEventKind::Exit,
+                });
+            }
+        }
+        self.sensor_events
+            .sort_by_key(|event| (event.entity_id, event.sensor_id));
+        self.sensor_overlaps = sensor_overlaps;
+
+        // Entities are in contact if either collided with the other this frame,
+        // or the player is standing on the other.
+        let contacts = movement_context
+            .collision_events()
+            .iter()
+            .map(|event| (event.moving_entity_id, event.stationary_entity_id))
+            .chain(player_ground_contacts)
+            .filter(|&(a, b)| {
+                self.common.contains_key(&a) && self.common.contains_key(&b)
+            })
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect::<FnvHashSet<_>>();
+        self.contact_events.clear();
+        for &(a, b) in contacts.iter() {
+            if !self.contacts.contains(&(a, b)) {
+                self.contact_events.push(ContactEvent {
+                    a,
+                    b,
+                    kind: ContactKind::Begin,
+                });
+            }
+        }
+        for &(a, b) in self.contacts.iter() {
+            if !contacts.contains(&(a, b)) {
+                self.contact_events.push(ContactEvent {
+                    a,
+                    b,
+                    kind: ContactKind::End,
+                });
+            }
         }
+        self.contact_events.sort_by_key(|event| (event.a, event.b));
+        self.contacts = contacts;
 
         self.frame_count += 1;
+        self.time += dt;
+    }
+    fn is_at_rest(&self) -> bool {
+        self.dynamic_physics.iter().all(|id| {
+            self.velocity
+                .get(id)
+                .map(|velocity| {
+                    velocity.magnitude2()
+                        < REST_VELOCITY_THRESHOLD * REST_VELOCITY_THRESHOLD
+                })
+                .unwrap_or(true)
+        })
+    }
+    // Runs the simulation with no input until every dynamic body comes to
+    // rest or `max_frames` have passed, returning the number of frames run.
+    pub fn settle(
+        &mut self,
+        max_frames: u32,
+        movement_context: &mut MovementContext,
+    ) -> u32 {
+        let input_model = InputModel::default();
+        let mut changes = GameStateChanges::default();
+        for frame in 1..(max_frames + 1) {
+            self.update(&input_model, &mut changes, movement_context, FIXED_DT);
+            if self.is_at_rest() {
+                return frame;
+            }
+        }
+        max_frames
+    }
+    pub fn last_frame_collisions(&self) -> &[CollisionEvent] {
+        &self.collision_events
+    }
+    pub fn last_frame_killed(&self) -> &[EntityId] {
+        &self.killed
+    }
+    pub fn player_killed(&self) -> bool {
+        self.player_killed
+    }
+    pub fn last_frame_crushed(&self) -> &[EntityId] {
+        &self.crushed
+    }
+    pub fn last_frame_sensor_events(&self) -> &[SensorEvent] {
+        &self.sensor_events
+    }
+    pub fn last_frame_contact_events(&self) -> &[ContactEvent] {
+        &self.contact_events
+    }
+    pub fn player_can_jump(&self) -> bool {
+        self.player_can_jump
+    }
+    pub fn player_motion_state(&self) -> Option<PlayerMotionState> {
+        let player_id = self.player_id?;
+        let velocity = self.velocity.get(&player_id)?;
+        let jump = self.jump.get(&player_id)?;
+        let state = match jump {
+            JumpStateMachine::JumpingForFrames(_) if velocity.y < 0. => {
+                PlayerMotionState::Rising
+            }
+            _ if self.player_grounded => PlayerMotionState::Grounded,
+            _ if velocity.y < 0. => PlayerMotionState::Rising,
+            _ => PlayerMotionState::Falling,
+        };
+        Some(state)
+    }
+    fn entity_view(&self, id: EntityId) -> Option<EntityView> {
+        let common = self.common.get(&id)?;
+        Some(EntityView {
+            position: common.position,
+            velocity: self.velocity.get(&id).cloned().unwrap_or(vec2(0., 0.)),
+            shape: &common.shape,
+            colour: common.colour,
+        })
+    }
+    pub fn iter_dynamic<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (EntityId, EntityView<'a>)> + 'a {
+        self.dynamic_physics
+            .iter()
+            .filter_map(move |&id| self.entity_view(id).map(|view| (id, view)))
+    }
+    pub fn iter_kinematic<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (EntityId, EntityView<'a>)> + 'a {
+        self.static_physics
+            .iter()
+            .filter_map(move |&id| self.entity_view(id).map(|view| (id, view)))
+    }
+    pub fn iter_static<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (EntityId, EntityView<'a>)> + 'a {
+        self.common
+            .keys()
+            .filter(move |id| {
+                !self.dynamic_physics.contains(id) && !self.static_physics.contains(id)
+            })
+            .filter_map(move |&id| self.entity_view(id).map(|view| (id, view)))
+    }
+    pub fn diff<V>(&self, other: &GameState<V>) -> Vec<EntityDiff> {
+        self.diff_with_tolerance(other, DIFF_TOLERANCE)
+    }
+    pub fn diff_with_tolerance<V>(
+        &self,
+        other: &GameState<V>,
+        tolerance: f64,
+    ) -> Vec<EntityDiff> {
+        let mut ids = self.common
+            .keys()
+            .chain(other.common.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+        let differs = |a: Vector2<f64>, b: Vector2<f64>| (a - b).magnitude() > tolerance;
+        let mut diffs = Vec::new();
+        for id in ids {
+            let (ours, theirs) = match (self.common.get(&id), other.common.get(&id)) {
+                (Some(ours), Some(theirs)) => (ours, theirs),
+                (Some(_), None) => {
+                    diffs.push(EntityDiff::OnlyInSelf(id));
+                    continue;
+                }
+                (None, _) => {
+                    diffs.push(EntityDiff::OnlyInOther(id));
+                    continue;
+                }
+            };
+            if differs(ours.position, theirs.position) {
+                diffs.push(EntityDiff::Position {
+                    entity_id: id,
+                    ours: ours.position,
+                    theirs: theirs.position,
+                });
+            }
+            let our_velocity = self.velocity.get(&id).cloned().unwrap_or(vec2(0., 0.));
+            let their_velocity = other.velocity.get(&id).cloned().unwrap_or(vec2(0., 0.));
+            if differs(our_velocity, their_velocity) {
+                diffs.push(EntityDiff::Velocity {
+                    entity_id: id,
+                    ours: our_velocity,
+                    theirs: their_velocity,
+                });
+            }
+            if ours.shape != theirs.shape {
+                diffs.push(EntityDiff::Shape(id));
+            }
+        }
+        diffs
+    }
+    pub fn velocity_debug_segments<'a>(
+        &'a self,
+        scale: f64,
+    ) -> impl Iterator<Item = (Vector2<f64>, Vector2<f64>)> + 'a {
+        self.dynamic_physics.iter().filter_map(move |id| {
+            let centre = self.common.get(id)?.aabb().centre();
+            let velocity = self.velocity.get(id)?;
+            if velocity.x.is_finite() && velocity.y.is_finite() {
+                Some((centre, centre + velocity * scale))
+            } else {
+                None
+            }
+        })
+    }
+    // The bounding boxes the broadphase sees, at the current (not
+    // interpolated) positions.
+    pub fn debug_aabbs<'a>(&'a self) -> impl Iterator<Item = Aabb> + 'a {
+        self.common.values().map(|common| common.aabb())
+    }
+    pub fn for_each_quad_tree_node<F: FnMut(Aabb, usize, usize)>(&self, f: F) {
+        self.quad_tree.for_each_node(f);
+    }
+    pub fn to_svg(&self) -> String {
+        let mut ids = self.common.keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        svg::render(ids.iter().map(|id| {
+            let common = self.common.get(id).unwrap();
+            RenderUpdate {
+                position: common.position,
+                shape: &common.shape,
+                colour: common.colour,
+                sprite: self.sprites.get(id).cloned(),
+            }
+        }))
     }
     pub fn render_updates(&self) -> impl Iterator<Item = RenderUpdate> {
-        self.common.values().map(|common| RenderUpdate {
+        self.common.iter().map(move |(id, common)| RenderUpdate {
             position: common.position,
             shape: &common.shape,
             colour: common.colour,
+            sprite: self.sprites.get(id).cloned(),
+        })
+    }
+    // Blends each entity's position before and after the last update, where
+    // an `alpha` of 0 gives the position before and 1 the position after.
+    pub fn render_updates_interpolated(
+        &self,
+        alpha: f64,
+    ) -> impl Iterator<Item = RenderUpdate> {
+        self.common.iter().map(move |(id, common)| {
+            let position = match self.previous_positions.get(id) {
+                Some(&previous) => previous + (common.position - previous) * alpha,
+                None => common.position,
+            };
+            RenderUpdate {
+                position,
+                shape: &common.shape,
+                colour: common.colour,
+                sprite: self.sprites.get(id).cloned(),
+            }
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capsule::Capsule;
+    use circle::Circle;
+    use convex_polygon::ConvexPolygon;
+    use movement::Surface;
+    use std::f64::consts::PI;
+
+    const FLOOR_Y: f64 = 500.;
+
+    // A state with a wide floor along `FLOOR_Y`.
+    fn floor_state(config: PhysicsConfig) -> GameState {
+        let mut game_state = GameState::with_config(vec2(1000., 1000.), config);
+        game_state.spawn_static(
+            vec2(0., FLOOR_Y),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(1000., 20.))),
+            [1., 1., 1.],
+        );
+        game_state
+    }
+
+    fn run(game_state: &mut GameState, input_model: &mut InputModel, frames: usize) {
+        let mut changes = GameStateChanges::default();
+        let mut movement_context = MovementContext::default();
+        for _ in 0..frames {
+            game_state.update(input_model, &mut changes, &mut movement_context, FIXED_DT);
+            input_model.after_process();
+        }
+    }
+
+    #[test]
+    fn lateral_speed_follows_configured_rates() {
+        let config = PhysicsConfig {
+            lateral_acceleration: 1.,
+            lateral_deceleration: 2.,
+            ..Default::default()
+        };
+        let mut game_state = floor_state(config);
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        let mut input_model = InputModel::default();
+        input_model.set_right(1.);
+        let mut frames_to_max_speed = 0;
+        while game_state.velocity(player_id).unwrap().x < config.max_lateral_speed {
+            run(&mut game_state, &mut input_model, 1);
+            frames_to_max_speed += 1;
+        }
+        assert_eq!(frames_to_max_speed, 4);
+        input_model.set_right(0.);
+        let mut frames_to_stop = 0;
+        while game_state.velocity(player_id).unwrap().x > 0. {
+            run(&mut game_state, &mut input_model, 1);
+            frames_to_stop += 1;
+        }
+        assert_eq!(frames_to_stop, 2);
+    }
+
+    #[test]
+    fn default_lateral_movement_is_instant() {
+        let mut game_state = floor_state(Default::default());
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        let mut input_model = InputModel::default();
+        input_model.set_right(1.);
+        run(&mut game_state, &mut input_model, 1);
+        assert_eq!(game_state.velocity(player_id).unwrap().x, 4.);
+        input_model.set_right(0.);
+        input_model.set_left(1.);
+        run(&mut game_state, &mut input_model, 1);
+        assert_eq!(game_state.velocity(player_id).unwrap().x, -4.);
+        input_model.set_left(0.);
+        run(&mut game_state, &mut input_model, 1);
+        assert_eq!(game_state.velocity(player_id).unwrap().x, 0.);
+    }
+
+    #[test]
+    fn embedded_body_rises_at_penetration_bias() {
+        let config = PhysicsConfig {
+            gravity: vec2(0., 0.),
+            penetration_bias: 0.2,
+            ..Default::default()
+        };
+        let mut game_state = floor_state(config);
+        let id = game_state.spawn_dynamic(
+            vec2(100., FLOOR_Y - 27.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(32., 32.))),
+            [1., 1., 1.],
+        );
+        let mut input_model = InputModel::default();
+        let mut depth = 5.;
+        for _ in 0..10 {
+            run(&mut game_state, &mut input_model, 1);
+            depth -= (depth - PENETRATION_SLOP) * 0.2;
+            let y = game_state.position(id).unwrap().y;
+            assert!((y - (FLOOR_Y - 32. + depth)).abs() < 1e-9);
+        }
+        run(&mut game_state, &mut input_model, 100);
+        let y = game_state.position(id).unwrap().y;
+        assert!(y > FLOOR_Y - 32. && y <= FLOOR_Y - 32. + PENETRATION_SLOP + 1e-6);
+    }
+
+    #[test]
+    fn integrate_forces_only_changes_velocity() {
+        let mut game_state = floor_state(Default::default());
+        let shape = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(32., 32.)));
+        let a = game_state.spawn_dynamic(vec2(100., 100.), shape.clone(), [1., 1., 1.]);
+        let b = game_state.spawn_dynamic(vec2(200., 100.), shape, [1., 1., 1.]);
+        game_state.set_velocity(b, vec2(3., -1.));
+        game_state.set_gravity_scale(b, 2.);
+        game_state.integrate_forces(FIXED_DT);
+        assert_eq!(game_state.velocity(a), Some(vec2(0., 0.5)));
+        assert_eq!(game_state.velocity(b), Some(vec2(3., 0.)));
+        assert_eq!(game_state.position(a), Some(vec2(100., 100.)));
+        assert_eq!(game_state.position(b), Some(vec2(200., 100.)));
+        game_state.set_time_scale(2.);
+        game_state.integrate_forces(FIXED_DT);
+        assert_eq!(game_state.velocity(a), Some(vec2(0., 1.5)));
+    }
+
+    #[test]
+    fn dynamic_bodies_fall_under_gravity() {
+        let mut game_state = floor_state(Default::default());
+        let id = game_state.spawn_dynamic(
+            vec2(100., 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(32., 32.))),
+            [1., 1., 1.],
+        );
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 1);
+        assert_eq!(game_state.velocity(id), Some(vec2(0., 0.5)));
+        run(&mut game_state, &mut input_model, 200);
+        assert_eq!(game_state.position(id).unwrap().y, FLOOR_Y - 32.);
+    }
+
+    #[test]
+    fn collision_events_are_ordered_by_time_of_impact() {
+        let config = PhysicsConfig {
+            gravity: vec2(0., 0.),
+            ..Default::default()
+        };
+        let mut game_state = floor_state(config);
+        let shape = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.)));
+        // Falls 10px a frame, and hits the floor 0.55 of the way through.
+        let falling = game_state.spawn_dynamic(
+            vec2(100., FLOOR_Y - 25.5),
+            shape.clone(),
+            [1., 1., 1.],
+        );
+        game_state.set_velocity(falling, vec2(0., 10.));
+        // Moves 40px a frame in 4 substeps, and hits the resting body halfway
+        // through the third, 0.625 of the way through the frame.
+        let pusher = game_state.spawn_kinematic(
+            vec2(200., FLOOR_Y - 20.),
+            shape.clone(),
+            [1., 1., 1.],
+        );
+        game_state.set_velocity(pusher, vec2(40., 0.));
+        let resting =
+            game_state.spawn_dynamic(vec2(245., FLOOR_Y - 20.), shape, [1., 1., 1.]);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 1);
+        let events = game_state
+            .last_frame_collisions()
+            .iter()
+            .map(|event| {
+                (
+                    event.moving_entity_id,
+                    event.stationary_entity_id,
+                    event.time_of_impact,
+                )
+            })
+            .collect::<Vec<_>>();
+        // The pusher keeps pushing in the final substep.
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].0, falling);
+        assert!((events[0].2 - 0.55).abs() < 1e-9);
+        for &(moving, stationary, _) in events[1..].iter() {
+            assert_eq!((moving, stationary), (pusher, resting));
+        }
+        assert!((events[1].2 - 0.625).abs() < 1e-9);
+        assert!((events[2].2 - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fast_body_lands_on_thin_floors() {
+        // Line segments are given a little thickness, above the line.
+        let floors = vec![
+            (
+                Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(1000., 20.))),
+                FLOOR_Y,
+            ),
+            (
+                Shape::AxisAlignedRect(AxisAlignedRect::new_floor_only(vec2(1000., 20.))),
+                FLOOR_Y,
+            ),
+            (
+                Shape::LineSegment(LineSegment::new_both_solid(
+                    vec2(0., 0.),
+                    vec2(1000., 0.),
+                )),
+                FLOOR_Y - 0.1,
+            ),
+        ];
+        for (floor, top) in floors {
+            let mut game_state = GameState::new(vec2(1000., 1000.));
+            game_state.spawn_static(vec2(0., FLOOR_Y), floor, [1., 1., 1.]);
+            let id = game_state.spawn_dynamic(
+                vec2(100., 100.),
+                Shape::AxisAlignedRect(AxisAlignedRect::new_character(vec2(32., 32.))),
+                [1., 1., 1.],
+            );
+            game_state.set_velocity(id, vec2(0., 200.));
+            let mut input_model = InputModel::default();
+            run(&mut game_state, &mut input_model, 10);
+            let y = game_state.position(id).unwrap().y;
+            assert!((y - (top - 32.)).abs() < 1e-9);
+        }
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn deserialized_state_steps_identically() {
+        let mut game_state = floor_state(Default::default());
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        let box_id = game_state.spawn_dynamic(
+            vec2(300., 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(32., 32.))),
+            [1., 1., 1.],
+        );
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 10);
+        input_model.set_jump(true);
+        input_model.set_right(1.);
+        run(&mut game_state, &mut input_model, 3);
+        assert!(!game_state.player_grounded);
+        let json = ::serde_json::to_string(&game_state).unwrap();
+        let mut restored: GameState = ::serde_json::from_str(&json).unwrap();
+        let mut changes = GameStateChanges::default();
+        let mut restored_changes = GameStateChanges::default();
+        let mut movement_context = MovementContext::default();
+        let mut restored_movement_context = MovementContext::default();
+        for _ in 0..60 {
+            game_state.update(
+                &input_model,
+                &mut changes,
+                &mut movement_context,
+                FIXED_DT,
+            );
+            restored.update(
+                &input_model,
+                &mut restored_changes,
+                &mut restored_movement_context,
+                FIXED_DT,
+            );
+            input_model.after_process();
+            for &id in [player_id, box_id].iter() {
+                assert_eq!(game_state.position(id), restored.position(id));
+                assert_eq!(game_state.velocity(id), restored.velocity(id));
+            }
+        }
+        assert_eq!(
+            ::serde_json::to_string(&game_state).unwrap(),
+            ::serde_json::to_string(&restored).unwrap()
+        );
+    }
+
+    #[cfg(feature = "ron_level")]
+    #[test]
+    fn load_level_spawns_sample_entities() {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        let path = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/levels/sample.ron"));
+        game_state.load_level(path).unwrap();
+        let everything = Aabb::new(vec2(-1000., -1000.), vec2(3000., 3000.));
+        assert_eq!(game_state.entities_in_region(everything).len(), 8);
+        assert!(game_state.player_id().is_some());
+        // A level that can't be loaded leaves the current one in place.
+        assert!(game_state.load_level(Path::new("missing.ron")).is_err());
+        assert_eq!(game_state.entities_in_region(everything).len(), 8);
+        assert!(game_state.player_id().is_some());
+    }
+
+    #[cfg(feature = "tiled")]
+    #[test]
+    fn load_tiled_spawns_objects_at_their_positions() {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        game_state
+            .load_tiled(
+                r#"<map><objectgroup>
+                <object x="10" y="600" width="900" height="40"/>
+                <object x="300" y="200"><polyline points="0,0 100,0"/></object>
+                </objectgroup></map>"#,
+            )
+            .unwrap();
+        assert_eq!(sorted_positions(&game_state), vec![(10., 600.), (300., 200.)]);
+        assert!(game_state.load_tiled("<map><object").is_err());
+    }
+
+    #[test]
+    fn entity_at_point_picks_the_shape_under_the_point() {
+        let mut game_state = floor_state(Default::default());
+        let rect = game_state.spawn_static(
+            vec2(100., 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(50., 50.))),
+            [1., 1., 1.],
+        );
+        let line = game_state.spawn_static(
+            vec2(300., 100.),
+            Shape::LineSegment(LineSegment::new_both_solid(
+                vec2(0., 0.),
+                vec2(100., 0.),
+            )),
+            [1., 1., 1.],
+        );
+        let ramp = game_state.spawn_static(
+            vec2(500., 100.),
+            Shape::ConvexPolygon(
+                ConvexPolygon::from_vertices(vec![
+                    vec2(0., 100.),
+                    vec2(100., 0.),
+                    vec2(100., 100.),
+                ]).unwrap(),
+            ),
+            [1., 1., 1.],
+        );
+        let capsule = game_state.spawn_static(
+            vec2(700., 100.),
+            Shape::Capsule(Capsule::new(20., 10.)),
+            [1., 1., 1.],
+        );
+        assert_eq!(game_state.entity_at_point(vec2(125., 125.)), Some(rect));
+        assert_eq!(game_state.entity_at_point(vec2(350., 103.)), Some(line));
+        assert_eq!(game_state.entity_at_point(vec2(590., 190.)), Some(ramp));
+        assert_eq!(game_state.entity_at_point(vec2(710., 130.)), Some(capsule));
+        assert_eq!(game_state.entity_at_point(vec2(250., 300.)), None);
+        // Inside the bounding boxes, but outside the shapes.
+        assert_eq!(game_state.entity_at_point(vec2(510., 110.)), None);
+        assert_eq!(game_state.entity_at_point(vec2(701., 101.)), None);
+    }
+
+    // 5000 static tiles, with 50 boxes falling onto them.
+    fn tiles_and_movers() -> (GameState, Vec<EntityId>) {
+        let mut game_state = GameState::new(vec2(4000., 4000.));
+        let tile = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(32., 32.)));
+        for i in 0..5000 {
+            let position = vec2((i % 100) as f64 * 40., 400. + (i / 100) as f64 * 70.);
+            game_state.spawn_static(position, tile.clone(), [1., 1., 1.]);
+        }
+        let mover = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.)));
+        let movers = (0..50)
+            .map(|i| {
+                let id = game_state.spawn_dynamic(
+                    vec2(i as f64 * 70., 10.),
+                    mover.clone(),
+                    [1., 1., 1.],
+                );
+                game_state.set_velocity(id, vec2(1., 3.));
+                id
+            })
+            .collect();
+        (game_state, movers)
+    }
+
+    #[test]
+    fn incremental_quad_tree_matches_full_rebuild() {
+        let (mut incremental, movers) = tiles_and_movers();
+        let (mut full, _) = tiles_and_movers();
+        let mut input_model = InputModel::default();
+        run(&mut incremental, &mut input_model, 100);
+        for _ in 0..100 {
+            // Reinserts every entity, as each rebuild used to.
+            full.quad_tree_stale = true;
+            run(&mut full, &mut input_model, 1);
+        }
+        for &id in movers.iter() {
+            assert_eq!(incremental.position(id), full.position(id));
+        }
+        let region = Aabb::new(vec2(0., 0.), vec2(1000., 1000.));
+        let mut incremental_ids = incremental.entities_in_region(region);
+        let mut full_ids = full.entities_in_region(region);
+        incremental_ids.sort();
+        full_ids.sort();
+        assert_eq!(incremental_ids, full_ids);
+    }
+
+    #[test]
+    fn bounce_height_follows_restitution() {
+        let mut game_state = floor_state(Default::default());
+        let drop_height = 400.;
+        let rest_y = FLOOR_Y - 32.;
+        let id = game_state.spawn_dynamic(
+            vec2(100., rest_y - drop_height),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(32., 32.))),
+            [1., 1., 1.],
+        );
+        game_state.set_restitution(id, 0.8);
+        let mut input_model = InputModel::default();
+        while game_state.velocity(id).unwrap().y >= 0. {
+            run(&mut game_state, &mut input_model, 1);
+        }
+        let mut peak_y = rest_y;
+        while game_state.velocity(id).unwrap().y < 0. {
+            run(&mut game_state, &mut input_model, 1);
+            peak_y = peak_y.min(game_state.position(id).unwrap().y);
+        }
+        let rebound = (rest_y - peak_y) / drop_height;
+        assert!((rebound - 0.64).abs() < 0.05, "rebound {}", rebound);
+    }
+
+    // Walks the player off a ledge, then presses jump after falling for
+    // `frames`, returning whether it jumped.
+    fn jumps_after_leaving_ledge(frames: usize) -> bool {
+        let mut game_state = GameState::new(vec2(1000., 1000.));
+        game_state.spawn_static(
+            vec2(0., FLOOR_Y),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(100., 20.))),
+            [1., 1., 1.],
+        );
+        let player_id = game_state.spawn_player(vec2(50., FLOOR_Y - 64.), None);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 5);
+        assert!(game_state.player_grounded);
+        game_state.set_position(player_id, vec2(110., FLOOR_Y - 64.));
+        run(&mut game_state, &mut input_model, frames);
+        assert!(!game_state.player_grounded);
+        input_model.set_jump(true);
+        input_model.after_process();
+        run(&mut game_state, &mut input_model, 1);
+        match game_state.jump[&player_id] {
+            JumpStateMachine::JumpingForFrames(_) => true,
+            JumpStateMachine::NotJumping => false,
+        }
+    }
+
+    #[test]
+    fn jump_is_allowed_during_coyote_time() {
+        assert!(jumps_after_leaving_ledge(3));
+        assert!(!jumps_after_leaving_ledge(10));
+    }
+
+    #[test]
+    fn half_steps_match_full_steps() {
+        let mut full = floor_state(PhysicsConfig::default());
+        let mut half = floor_state(PhysicsConfig::default());
+        let full_id = full.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        let half_id = half.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        let mut input_model = InputModel::default();
+        input_model.set_right(1.);
+        let mut changes = GameStateChanges::default();
+        let mut movement_context = MovementContext::default();
+        for _ in 0..60 {
+            full.update(&input_model, &mut changes, &mut movement_context, FIXED_DT);
+            for _ in 0..2 {
+                half.update(
+                    &input_model,
+                    &mut changes,
+                    &mut movement_context,
+                    FIXED_DT / 2.,
+                );
+            }
+        }
+        let full_position = full.position(full_id).unwrap();
+        let half_position = half.position(half_id).unwrap();
+        assert!(full_position.x > 200.);
+        assert!((full_position - half_position).magnitude() < 1e-6);
+        let full_velocity = full.velocity(full_id).unwrap();
+        let half_velocity = half.velocity(half_id).unwrap();
+        assert!((full_velocity - half_velocity).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn overlap_query_excludes_segments_outside_the_box() {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        let segment_id = game_state.spawn_static(
+            vec2(100., 100.),
+            Shape::LineSegment(LineSegment::new_both_solid(
+                vec2(0., 0.),
+                vec2(100., 100.),
+            )),
+            [1., 1., 1.],
+        );
+        let rect_id = game_state.spawn_static(
+            vec2(300., 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(50., 50.))),
+            [1., 1., 1.],
+        );
+        // Inside the segment's bounding box, but above the diagonal.
+        let corner = Aabb::new(vec2(180., 105.), vec2(15., 15.));
+        assert!(game_state.shapes_overlapping_aabb(corner).is_empty());
+        let on_line = Aabb::new(vec2(140., 140.), vec2(20., 20.));
+        assert_eq!(game_state.shapes_overlapping_aabb(on_line), vec![segment_id]);
+        let both = Aabb::new(vec2(130., 130.), vec2(180., 10.));
+        let mut ids = game_state.shapes_overlapping_aabb(both);
+        ids.sort();
+        assert_eq!(ids, vec![segment_id, rect_id]);
+    }
+
+    #[test]
+    fn queries_work_without_update() {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        let rect = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.)));
+        let id = game_state.spawn_static(vec2(100., 100.), rect, [1., 1., 1.]);
+        let old = Aabb::new(vec2(105., 105.), vec2(5., 5.));
+        let new = Aabb::new(vec2(505., 505.), vec2(5., 5.));
+        assert_eq!(game_state.shapes_overlapping_aabb(old), vec![id]);
+        assert!(game_state.shapes_overlapping_aabb(new).is_empty());
+        game_state.set_position(id, vec2(500., 500.));
+        assert!(game_state.shapes_overlapping_aabb(old).is_empty());
+        assert_eq!(game_state.shapes_overlapping_aabb(new), vec![id]);
+        assert_eq!(game_state.entity_at_point(vec2(510., 510.)), Some(id));
+    }
+
+    #[test]
+    fn motion_state_follows_a_jump_arc() {
+        let mut game_state = floor_state(PhysicsConfig::default());
+        game_state.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 5);
+        let mut states = vec![game_state.player_motion_state().unwrap()];
+        input_model.set_jump(true);
+        for _ in 0..120 {
+            run(&mut game_state, &mut input_model, 1);
+            let state = game_state.player_motion_state().unwrap();
+            if states.last() != Some(&state) {
+                states.push(state);
+            }
+        }
+        assert_eq!(
+            states,
+            vec![
+                PlayerMotionState::Grounded,
+                PlayerMotionState::Rising,
+                PlayerMotionState::Falling,
+                PlayerMotionState::Grounded,
+            ]
+        );
+    }
+
+    fn weightless_state() -> GameState {
+        let config = PhysicsConfig {
+            gravity: vec2(0., 0.),
+            ..Default::default()
+        };
+        GameState::with_config(vec2(1000., 1000.), config)
+    }
+
+    #[test]
+    fn one_way_segments_only_block_against_the_pass_through_direction() {
+        let mut game_state = weightless_state();
+        game_state.spawn_static(
+            vec2(0., 300.),
+            Shape::LineSegment(LineSegment::new_one_way_from(
+                vec2(0., 0.),
+                vec2(400., 0.),
+                vec2(0., -1.),
+            )),
+            [1., 1., 1.],
+        );
+        let rect = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.)));
+        let rising_id =
+            game_state.spawn_dynamic(vec2(100., 350.), rect.clone(), [1., 1., 1.]);
+        game_state.set_velocity(rising_id, vec2(0., -5.));
+        let falling_id = game_state.spawn_dynamic(vec2(200., 230.), rect, [1., 1., 1.]);
+        game_state.set_velocity(falling_id, vec2(0., 5.));
+        run(&mut game_state, &mut InputModel::default(), 30);
+        assert!(game_state.position(rising_id).unwrap().y < 250.);
+        let falling_bottom = game_state.position(falling_id).unwrap().y + 20.;
+        assert!(falling_bottom <= 300.);
+        assert!(falling_bottom > 299.);
+    }
+
+    #[test]
+    fn static_overlaps_reports_overlapping_level_geometry() {
+        let mut author: GameState = GameState::new(vec2(1000., 1000.));
+        let rect = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(100., 20.)));
+        author.spawn_static(vec2(0., 500.), rect.clone(), [1., 1., 1.]);
+        author.spawn_static(vec2(90., 510.), rect.clone(), [1., 1., 1.]);
+        author.spawn_static(vec2(400., 500.), rect, [1., 1., 1.]);
+        let bytes = author.save_static();
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        game_state.load(Level::Static(bytes)).unwrap();
+        let overlaps = game_state.static_overlaps();
+        assert_eq!(overlaps.len(), 1);
+        let (a, b) = overlaps[0];
+        let mut positions = vec![
+            game_state.position(a).unwrap().x,
+            game_state.position(b).unwrap().x,
+        ];
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(positions, vec![0., 90.]);
+    }
+
+    #[test]
+    fn prediction_stops_leading_at_a_wall() {
+        let mut game_state = floor_state(PhysicsConfig::default());
+        let wall_id = game_state.spawn_static(
+            vec2(300., FLOOR_Y - 200.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 200.))),
+            [1., 1., 1.],
+        );
+        let player_id = game_state.spawn_player(vec2(200., FLOOR_Y - 64.), None);
+        run(&mut game_state, &mut InputModel::default(), 5);
+        game_state.set_velocity(player_id, vec2(4., 0.));
+        let mut movement_context = MovementContext::default();
+        let predicted = game_state
+            .predict_position(player_id, 30, &mut movement_context)
+            .unwrap();
+        assert!((predicted.x - (300. - 32.)).abs() < 0.5);
+        assert!((predicted.y - (FLOOR_Y - 64.)).abs() < 0.5);
+        assert_eq!(game_state.position(player_id), Some(vec2(200., FLOOR_Y - 64.)));
+        let short = game_state
+            .predict_position(player_id, 5, &mut movement_context)
+            .unwrap();
+        assert!((short.x - 220.).abs() < 0.5);
+        let mut predict = |game_state: &GameState, id, frames| {
+            game_state
+                .predict_position(id, frames, &mut movement_context)
+                .unwrap()
+        };
+        assert_eq!(predict(&game_state, wall_id, 30), vec2(300., FLOOR_Y - 200.));
+        game_state.set_time_scale(0.5);
+        assert!((predict(&game_state, player_id, 5).x - 210.).abs() < 0.5);
+        // Mid-air, the prediction falls with the player's own gravity.
+        game_state.set_time_scale(1.);
+        game_state.set_position(player_id, vec2(100., 100.));
+        game_state.set_velocity(player_id, vec2(0., 0.));
+        assert_eq!(predict(&game_state, player_id, 2), vec2(100., 101.5));
+        game_state.set_gravity_scale(player_id, 0.);
+        assert_eq!(predict(&game_state, player_id, 2), vec2(100., 100.));
+    }
+
+    #[test]
+    fn sinusoidal_global_force_oscillates_a_hovering_body() {
+        const PERIOD: f64 = 60.;
+        let mut game_state = weightless_state();
+        game_state.set_global_force(Box::new(|frame, _position| {
+            vec2((frame as f64 * 2. * PI / PERIOD).cos() * 0.1, 0.)
+        }));
+        let id = game_state.spawn_dynamic(
+            vec2(500., 500.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            [1., 1., 1.],
+        );
+        let mut input_model = InputModel::default();
+        let mut turning_frames = Vec::new();
+        let mut previous_velocity = 0.;
+        for frame in 0..240 {
+            run(&mut game_state, &mut input_model, 1);
+            let velocity = game_state.velocity(id).unwrap().x;
+            if previous_velocity > 0. && velocity <= 0. {
+                turning_frames.push(frame);
+            }
+            previous_velocity = velocity;
+        }
+        assert_eq!(turning_frames.len(), 4);
+        for pair in turning_frames.windows(2) {
+            assert!(((pair[1] - pair[0]) as f64 - PERIOD).abs() <= 1.);
+        }
+        let position = game_state.position(id).unwrap();
+        assert!((position.x - 500.).abs() < 20.);
+        assert_eq!(position.y, 500.);
+    }
+
+    fn sorted_positions(game_state: &GameState) -> Vec<(f64, f64)> {
+        let mut positions = game_state
+            .render_updates()
+            .map(|update| (update.position.x, update.position.y))
+            .collect::<Vec<_>>();
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        positions
+    }
+
+    #[test]
+    fn reset_restores_positions_and_keeps_config() {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        game_state.init_demo();
+        let initial = sorted_positions(&game_state);
+        game_state.config_mut().gravity = vec2(0., 1.5);
+        let mut input_model = InputModel::default();
+        input_model.set_right(1.);
+        run(&mut game_state, &mut input_model, 30);
+        assert_ne!(sorted_positions(&game_state), initial);
+        game_state.reset().unwrap();
+        assert_eq!(game_state.config().gravity, vec2(0., 1.5));
+        assert_eq!(sorted_positions(&game_state), initial);
+    }
+
+    #[test]
+    fn frame_collisions_aggregate_every_body() {
+        let mut game_state = floor_state(PhysicsConfig::default());
+        let floor_id = game_state.entity_at_point(vec2(10., FLOOR_Y + 10.)).unwrap();
+        let rect = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.)));
+        let ids = [100., 300., 500.]
+            .iter()
+            .map(|&x| {
+                let position = vec2(x, FLOOR_Y - 40.);
+                game_state.spawn_dynamic(position, rect.clone(), [1., 1., 1.])
+            })
+            .collect::<Vec<_>>();
+        let mut input_model = InputModel::default();
+        let mut frames = 0;
+        while game_state.last_frame_collisions().is_empty() {
+            run(&mut game_state, &mut input_model, 1);
+            frames += 1;
+            assert!(frames < 60);
+        }
+        let events = game_state.last_frame_collisions();
+        let per_body = ids.iter()
+            .map(|id| {
+                events
+                    .iter()
+                    .filter(|event| event.moving_entity_id == *id)
+                    .count()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(per_body, vec![1, 1, 1]);
+        assert_eq!(events.len(), per_body.iter().sum::<usize>());
+        assert!(events.iter().all(|event| event.stationary_entity_id == floor_id));
+    }
+
+    #[test]
+    fn velocity_segments_end_at_scaled_velocity() {
+        let mut game_state = weightless_state();
+        let id = game_state.spawn_dynamic(
+            vec2(100., 200.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 40.))),
+            [1., 1., 1.],
+        );
+        game_state.set_velocity(id, vec2(3., -1.5));
+        let segments = game_state.velocity_debug_segments(8.).collect::<Vec<_>>();
+        assert_eq!(segments, vec![(vec2(110., 220.), vec2(134., 208.))]);
+    }
+
+    #[test]
+    fn exempt_platforms_ignore_world_bounds() {
+        let mut game_state = weightless_state();
+        game_state.config_mut().world_bounds =
+            Some(Aabb::new(vec2(0., 0.), vec2(400., 400.)));
+        let rect = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.)));
+        let platform_id =
+            game_state.spawn_kinematic(vec2(350., 100.), rect.clone(), [1., 1., 1.]);
+        game_state.set_world_bounds_exempt(platform_id, true);
+        let body_id = game_state.spawn_dynamic(vec2(350., 300.), rect, [1., 1., 1.]);
+        game_state.set_velocity(body_id, vec2(5., 0.));
+        let mut input_model = InputModel::default();
+        let mut max_platform_x: f64 = 0.;
+        for frame in 0..80 {
+            let direction = if (frame / 20) % 2 == 0 { 1. } else { -1. };
+            game_state.set_velocity(platform_id, vec2(5. * direction, 0.));
+            run(&mut game_state, &mut input_model, 1);
+            let platform_x = game_state.position(platform_id).unwrap().x;
+            max_platform_x = max_platform_x.max(platform_x);
+            assert!(game_state.position(body_id).unwrap().x <= 380.);
+        }
+        assert_eq!(max_platform_x, 450.);
+        assert_eq!(game_state.position(platform_id).unwrap().x, 350.);
+    }
+
+    struct ConstantVelocity;
+
+    impl Integrator for ConstantVelocity {
+        fn integrate(
+            &self,
+            position: Vector2<f64>,
+            velocity: Vector2<f64>,
+            _acceleration: Vector2<f64>,
+            dt: f64,
+        ) -> (Vector2<f64>, Vector2<f64>) {
+            (position + velocity * dt, velocity)
+        }
+    }
+
+    #[test]
+    fn custom_integrator_is_used() {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        game_state.set_integrator(Box::new(ConstantVelocity));
+        let id = game_state.spawn_dynamic(
+            vec2(100., 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            [1., 1., 1.],
+        );
+        game_state.set_velocity(id, vec2(2., 0.));
+        run(&mut game_state, &mut InputModel::default(), 10);
+        assert_eq!(game_state.position(id), Some(vec2(120., 100.)));
+        assert_eq!(game_state.velocity(id), Some(vec2(2., 0.)));
+    }
+
+    fn walked_distance(time_scale: f64) -> f64 {
+        let mut game_state = floor_state(PhysicsConfig::default());
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 5);
+        game_state.set_time_scale(time_scale);
+        input_model.set_right(1.);
+        run(&mut game_state, &mut input_model, 30);
+        game_state.position(player_id).unwrap().x - 100.
+    }
+
+    #[test]
+    fn time_scale_scales_distance_travelled() {
+        let full = walked_distance(1.);
+        assert!((full - 120.).abs() < 1e-6);
+        assert!((walked_distance(0.5) - full / 2.).abs() < 1e-6);
+        assert_eq!(walked_distance(0.), 0.);
+    }
+
+    #[test]
+    fn distance_to_ground_measures_the_gap_below() {
+        let mut game_state: GameState = GameState::new(vec2(960., 640.));
+        game_state.init_demo();
+        let player_id = game_state.player_id().unwrap();
+        let mut movement_context = MovementContext::default();
+        // Above the floor at y = 500, with the bottom of the player at 364.
+        game_state.set_position(player_id, vec2(520., 300.));
+        let distance = game_state
+            .distance_to_ground(player_id, &mut movement_context)
+            .unwrap();
+        assert!((distance - 136.).abs() < 0.01);
+        game_state.set_position(player_id, vec2(520., 436.));
+        let distance = game_state
+            .distance_to_ground(player_id, &mut movement_context)
+            .unwrap();
+        assert!(distance.abs() < 0.01);
+        game_state.set_position(player_id, vec2(-200., 300.));
+        assert_eq!(
+            game_state.distance_to_ground(player_id, &mut movement_context),
+            None
+        );
+    }
+
+    #[test]
+    fn parented_marker_tracks_the_moving_platform() {
+        let mut game_state: GameState = GameState::new(vec2(960., 640.));
+        game_state.init_demo();
+        let platform_id = game_state.moving_platform_ids[0];
+        let marker_id = game_state.spawn_static(
+            vec2(0., 0.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(8., 8.))),
+            [1., 0., 0.],
+        );
+        let offset = vec2(10., -20.);
+        assert!(game_state.set_parent(marker_id, platform_id, offset, false));
+        assert!(!game_state.set_parent(platform_id, marker_id, vec2(0., 0.), false));
+        let start = game_state.position(platform_id).unwrap();
+        let mut input_model = InputModel::default();
+        for _ in 0..20 {
+            run(&mut game_state, &mut input_model, 1);
+            let platform = game_state.position(platform_id).unwrap();
+            assert_eq!(game_state.position(marker_id), Some(platform + offset));
+        }
+        assert_ne!(game_state.position(platform_id).unwrap().x, start.x);
+    }
+
+    #[test]
+    fn demo_svg_has_an_element_per_shape() {
+        let mut game_state: GameState = GameState::new(vec2(960., 640.));
+        game_state.init_demo();
+        let svg = game_state.to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<rect").count(), 16);
+        assert_eq!(svg.matches("<line").count(), 6);
+        assert_eq!(svg.matches("<polygon").count(), 0);
+        assert_eq!(game_state.render_updates().count(), 16 + 6);
+    }
+
+    #[test]
+    fn player_below_kill_plane_is_reported_for_respawn() {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        game_state.set_kill_plane_y(700.);
+        let player_id = game_state.spawn_player(vec2(100., 600.), None);
+        let mut input_model = InputModel::default();
+        let mut frames = 0;
+        while !game_state.player_killed() {
+            assert!(game_state.last_frame_killed().is_empty());
+            run(&mut game_state, &mut input_model, 1);
+            frames += 1;
+            assert!(frames < 60);
+        }
+        assert_eq!(game_state.last_frame_killed(), &[player_id]);
+        assert_eq!(game_state.position(player_id), None);
+        run(&mut game_state, &mut input_model, 1);
+        assert!(!game_state.player_killed());
+    }
+
+    fn player_surfaces(game_state: &GameState, player_id: EntityId) -> Vec<Surface> {
+        game_state
+            .last_frame_collisions()
+            .iter()
+            .filter(|event| event.moving_entity_id == player_id)
+            .map(|event| event.surface)
+            .collect()
+    }
+
+    #[test]
+    fn collisions_are_classified_by_surface() {
+        let mut game_state = floor_state(PhysicsConfig::default());
+        game_state.spawn_static(
+            vec2(0., FLOOR_Y - 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(200., 20.))),
+            [1., 1., 1.],
+        );
+        game_state.spawn_static(
+            vec2(500., FLOOR_Y - 200.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 200.))),
+            [1., 1., 1.],
+        );
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 70.), None);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 1);
+        while player_surfaces(&game_state, player_id).is_empty() {
+            run(&mut game_state, &mut input_model, 1);
+        }
+        assert_eq!(player_surfaces(&game_state, player_id), vec![Surface::Floor]);
+        input_model.set_jump(true);
+        let mut surfaces = Vec::new();
+        for _ in 0..10 {
+            run(&mut game_state, &mut input_model, 1);
+            surfaces.extend(player_surfaces(&game_state, player_id));
+        }
+        assert!(surfaces.contains(&Surface::Ceiling));
+        input_model.set_jump(false);
+        game_state.set_position(player_id, vec2(400., FLOOR_Y - 64.));
+        input_model.set_right(1.);
+        let mut surfaces = Vec::new();
+        for _ in 0..40 {
+            run(&mut game_state, &mut input_model, 1);
+            surfaces.extend(player_surfaces(&game_state, player_id));
+        }
+        assert!(surfaces.contains(&Surface::Wall));
+        assert!(!surfaces.contains(&Surface::Ceiling));
+    }
+
+    fn is_jumping(game_state: &GameState, id: EntityId) -> bool {
+        match game_state.jump[&id] {
+            JumpStateMachine::JumpingForFrames(_) => true,
+            JumpStateMachine::NotJumping => false,
+        }
+    }
+
+    fn press_jump(game_state: &mut GameState, input_model: &mut InputModel) {
+        input_model.set_jump(true);
+        input_model.after_process();
+        run(game_state, input_model, 1);
+        input_model.set_jump(false);
+        input_model.after_process();
+    }
+
+    #[test]
+    fn player_spawned_mid_air_cannot_jump_until_grounded() {
+        let mut game_state = floor_state(PhysicsConfig::default());
+        let player_id = game_state.spawn_player(
+            vec2(100., FLOOR_Y - 264.),
+            Some(JumpStateMachine::NotJumping),
+        );
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 1);
+        press_jump(&mut game_state, &mut input_model);
+        assert!(!is_jumping(&game_state, player_id));
+        assert!(game_state.velocity(player_id).unwrap().y > 0.);
+        while !game_state.player_grounded {
+            run(&mut game_state, &mut input_model, 1);
+        }
+        assert!(!is_jumping(&game_state, player_id));
+        press_jump(&mut game_state, &mut input_model);
+        assert!(is_jumping(&game_state, player_id));
+    }
+
+    fn landing_impulse(velocity: Vector2<f64>) -> f64 {
+        let mut game_state = floor_state(PhysicsConfig {
+            gravity: vec2(0., 0.),
+            ..Default::default()
+        });
+        let id = game_state.spawn_dynamic(
+            vec2(100., FLOOR_Y - 30.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            [1., 1., 1.],
+        );
+        game_state.set_velocity(id, velocity);
+        let mut input_model = InputModel::default();
+        for _ in 0..200 {
+            run(&mut game_state, &mut input_model, 1);
+            if let Some(event) = game_state.last_frame_collisions().first() {
+                return event.impulse;
+            }
+        }
+        panic!("No landing");
+    }
+
+    #[test]
+    fn impulse_grows_with_landing_speed() {
+        let fast = landing_impulse(vec2(0., 8.));
+        let gentle = landing_impulse(vec2(0., 2.));
+        let grazing = landing_impulse(vec2(6., 0.1));
+        assert!((fast - 8.).abs() < 1e-6);
+        assert!((gentle - 2.).abs() < 1e-6);
+        assert!(grazing < 0.2);
+    }
+
+    #[test]
+    fn ray_passes_through_aligned_blocks_in_order() {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        let rect = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 40.)));
+        let ids = [300., 100., 200.]
+            .iter()
+            .map(|&x| game_state.spawn_static(vec2(x, 100.), rect.clone(), [1., 1., 1.]))
+            .collect::<Vec<_>>();
+        let hits = game_state.raycast_all(vec2(0., 110.), vec2(1., 0.), 1000.);
+        assert_eq!(
+            hits.iter().map(|hit| hit.entity_id).collect::<Vec<_>>(),
+            vec![ids[1], ids[2], ids[0]]
+        );
+        for (hit, &x) in hits.iter().zip([100., 200., 300.].iter()) {
+            assert!((hit.distance - x).abs() < 1e-6);
+            assert!((hit.point - vec2(x, 110.)).magnitude() < 1e-6);
+            assert!((hit.normal - vec2(-1., 0.)).magnitude() < 1e-6);
+        }
+        assert_eq!(
+            game_state.raycast_all(vec2(0., 110.), vec2(1., 0.), 250.).len(),
+            2
+        );
+        // Blocks stacked at the same distance are hit in order of id.
+        let stacked = (0..8)
+            .map(|i| {
+                let position = vec2(500., 300. + i as f64 * 10.);
+                game_state.spawn_static(position, rect.clone(), [1., 1., 1.])
+            })
+            .collect::<Vec<_>>();
+        let hits = game_state.raycast_all(vec2(0., 330.), vec2(1., 0.), 1000.);
+        assert_eq!(
+            hits.iter().map(|hit| hit.entity_id).collect::<Vec<_>>(),
+            stacked[..4].to_vec()
+        );
+    }
+
+    #[test]
+    fn can_jump_indicator_follows_coyote_time_and_jumps() {
+        let mut game_state = floor_state(PhysicsConfig::default());
+        game_state.spawn_static(
+            vec2(0., FLOOR_Y - 300.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(100., 20.))),
+            [1., 1., 1.],
+        );
+        let player_id = game_state.spawn_player(vec2(50., FLOOR_Y - 364.), None);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 5);
+        assert!(game_state.player_can_jump());
+        game_state.set_position(player_id, vec2(110., FLOOR_Y - 364.));
+        let coyote_frames = game_state.config().coyote_frames as usize;
+        let mut can_jump = Vec::new();
+        for _ in 0..coyote_frames + 3 {
+            run(&mut game_state, &mut input_model, 1);
+            can_jump.push(game_state.player_can_jump());
+        }
+        // The indicator is for a jump pressed during the next update, which
+        // counts one more frame since the player was grounded.
+        let (available, expired) = can_jump.split_at(coyote_frames - 1);
+        assert!(available.iter().all(|&can_jump| can_jump));
+        assert!(expired.iter().all(|&can_jump| !can_jump));
+        while !game_state.player_grounded {
+            run(&mut game_state, &mut input_model, 1);
+        }
+        assert!(game_state.player_can_jump());
+        press_jump(&mut game_state, &mut input_model);
+        run(&mut game_state, &mut input_model, 2);
+        assert!(!game_state.player_can_jump());
+    }
+
+    #[test]
+    fn fast_platform_does_not_push_a_body_through_a_thin_wall() {
+        let mut game_state = weightless_state();
+        game_state.spawn_static(
+            vec2(300., 0.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(2., 400.))),
+            [1., 1., 1.],
+        );
+        let platform_id = game_state.spawn_kinematic(
+            vec2(0., 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(40., 100.))),
+            [1., 1., 1.],
+        );
+        game_state.set_velocity(platform_id, vec2(30., 0.));
+        let body_id = game_state.spawn_dynamic(
+            vec2(100., 140.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            [1., 1., 1.],
+        );
+        let mut input_model = InputModel::default();
+        for _ in 0..20 {
+            run(&mut game_state, &mut input_model, 1);
+            let position = game_state.position(body_id).unwrap();
+            assert!(position.x + 20. <= 300. + 1e-6);
+        }
+        assert_eq!(game_state.position(body_id), Some(vec2(280., 140.)));
+    }
+
+    fn sorted_ids<'a, I>(iter: I) -> Vec<EntityId>
+    where
+        I: Iterator<Item = (EntityId, EntityView<'a>)>,
+    {
+        let mut ids = iter.map(|(id, _)| id).collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn category_iterators_partition_the_demo() {
+        let mut game_state: GameState = GameState::new(vec2(960., 640.));
+        game_state.init_demo();
+        let player_id = game_state.player_id().unwrap();
+        let mut platform_ids = game_state.moving_platform_ids.clone();
+        platform_ids.sort();
+        assert_eq!(sorted_ids(game_state.iter_dynamic()), vec![player_id]);
+        assert_eq!(sorted_ids(game_state.iter_kinematic()), platform_ids);
+        assert_eq!(platform_ids.len(), 3);
+        let static_ids = sorted_ids(game_state.iter_static());
+        assert_eq!(static_ids.len(), 22 - 1 - platform_ids.len());
+        assert!(!static_ids.contains(&player_id));
+        assert!(platform_ids.iter().all(|id| !static_ids.contains(id)));
+    }
+
+    #[test]
+    fn brute_force_broadphase_matches_quad_tree() {
+        let mut quad_tree: GameState = GameState::new(vec2(960., 640.));
+        quad_tree.init_demo();
+        let mut brute_force: GameState = GameState::new(vec2(960., 640.));
+        brute_force.config_mut().broadphase = Broadphase::BruteForce;
+        brute_force.init_demo();
+        let mut changes = GameStateChanges::default();
+        let mut movement_context = MovementContext::default();
+        let mut input_model = InputModel::default();
+        for frame in 0..200 {
+            input_model.set_left(if frame > 20 && frame < 90 { 1. } else { 0. });
+            input_model.set_jump(frame % 50 < 10);
+            for game_state in [&mut quad_tree, &mut brute_force].iter_mut() {
+                game_state.update(
+                    &input_model,
+                    &mut changes,
+                    &mut movement_context,
+                    FIXED_DT,
+                );
+            }
+            input_model.after_process();
+            assert_eq!(sorted_positions(&quad_tree), sorted_positions(&brute_force));
+            assert_eq!(
+                quad_tree.last_frame_collisions().len(),
+                brute_force.last_frame_collisions().len()
+            );
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Enemy {
+        name: &'static str,
+        health: u32,
+    }
+
+    #[test]
+    fn user_data_survives_stepping() {
+        let mut game_state: GameState<Enemy> = GameState::new(vec2(1000., 1000.));
+        let id = game_state.spawn_dynamic(
+            vec2(100., 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            [1., 1., 1.],
+        );
+        game_state.set_user_data(
+            id,
+            Enemy {
+                name: "slime",
+                health: 3,
+            },
+        );
+        let mut changes = GameStateChanges::default();
+        let mut movement_context = MovementContext::default();
+        let input_model = InputModel::default();
+        for _ in 0..10 {
+            game_state.update(
+                &input_model,
+                &mut changes,
+                &mut movement_context,
+                FIXED_DT,
+            );
+        }
+        game_state.user_data_mut(id).unwrap().health -= 1;
+        assert_eq!(
+            game_state.user_data(id),
+            Some(&Enemy {
+                name: "slime",
+                health: 2,
+            })
+        );
+        game_state.remove_entity(id);
+        assert_eq!(game_state.user_data(id), None);
+    }
+
+    #[test]
+    fn player_in_flip_zone_falls_upward_and_jumps_off_the_ceiling() {
+        const CEILING_Y: f64 = 200.;
+        let mut game_state = floor_state(PhysicsConfig::default());
+        game_state.spawn_static(
+            vec2(0., CEILING_Y - 20.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(1000., 20.))),
+            [1., 1., 1.],
+        );
+        game_state.add_gravity_zone(GravityZone {
+            region: Aabb::new(vec2(500., 0.), vec2(500., 1000.)),
+            up: vec2(0., 1.),
+        });
+        let player_id = game_state.spawn_player(vec2(400., FLOOR_Y - 64.), None);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 5);
+        game_state.set_position(player_id, vec2(600., FLOOR_Y - 64.));
+        run(&mut game_state, &mut input_model, 60);
+        assert_eq!(game_state.position(player_id).unwrap().y, CEILING_Y);
+        assert!(game_state.player_can_jump());
+        press_jump(&mut game_state, &mut input_model);
+        let mut lowest = CEILING_Y;
+        for _ in 0..20 {
+            run(&mut game_state, &mut input_model, 1);
+            lowest = lowest.max(game_state.position(player_id).unwrap().y);
+        }
+        assert!(lowest > CEILING_Y + 5.);
+        assert_eq!(game_state.position(player_id).unwrap().y, CEILING_Y);
+    }
+
+    #[test]
+    fn ground_profile_climbs_the_demo_staircase() {
+        let mut game_state: GameState = GameState::new(vec2(960., 640.));
+        game_state.init_demo();
+        let profile = game_state.ground_profile(590., 650., 20., 480.);
+        assert_eq!(
+            profile,
+            vec![
+                (590., Some(500.)),
+                (610., Some(498.)),
+                (630., Some(496.)),
+                (650., Some(492.)),
+            ]
+        );
+        assert_eq!(game_state.ground_profile(900., 900., 20., 480.), vec![(900., None)]);
+    }
+
+    // Stands the player across two platforms moving apart at the same speed,
+    // spawning the left one first or second, and returns which way the
+    // player was carried along with the ids of the left and right platforms.
+    fn carried_across_equal_platforms(left_first: bool) -> (f64, EntityId, EntityId) {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        let rect = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(100., 20.)));
+        let mut spawn = |x| {
+            game_state.spawn_kinematic(vec2(x, FLOOR_Y), rect.clone(), [1., 1., 1.])
+        };
+        let (left_id, right_id) = if left_first {
+            let left_id = spawn(100.);
+            (left_id, spawn(200.))
+        } else {
+            let right_id = spawn(200.);
+            (spawn(100.), right_id)
+        };
+        let player_id = game_state.spawn_player(vec2(184., FLOOR_Y - 64.), None);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 5);
+        game_state.set_velocity(left_id, vec2(-2., 0.));
+        game_state.set_velocity(right_id, vec2(2., 0.));
+        run(&mut game_state, &mut input_model, 3);
+        let moved = game_state.position(player_id).unwrap().x - 184.;
+        (moved, left_id, right_id)
+    }
+
+    #[test]
+    fn equal_speed_carriers_are_chosen_by_id() {
+        let (moved, left_id, right_id) = carried_across_equal_platforms(true);
+        assert!(left_id < right_id);
+        assert_eq!(moved, -6.);
+        let (moved, left_id, right_id) = carried_across_equal_platforms(false);
+        assert!(right_id < left_id);
+        assert_eq!(moved, 6.);
+        assert_eq!(carried_across_equal_platforms(false).0, moved);
+    }
+
+    #[test]
+    fn sliding_contact_spans_the_overlap_with_the_wall() {
+        let mut game_state = weightless_state();
+        let wall_id = game_state.spawn_static(
+            vec2(300., 0.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 1000.))),
+            [1., 1., 1.],
+        );
+        let id = game_state.spawn_dynamic(
+            vec2(279., 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 40.))),
+            [1., 1., 1.],
+        );
+        let mut input_model = InputModel::default();
+        for _ in 0..10 {
+            game_state.set_velocity(id, vec2(2., 3.));
+            let top = game_state.position(id).unwrap().y;
+            run(&mut game_state, &mut input_model, 1);
+            let events = game_state.last_frame_collisions();
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].stationary_entity_id, wall_id);
+            let (start, end) = events[0].contact;
+            assert_eq!((start.x, end.x), (300., 300.));
+            let top_at_impact = top + 3. * events[0].time_of_impact;
+            assert!((start.y.min(end.y) - top_at_impact).abs() < 1e-6);
+            assert!((start.y.max(end.y) - (top_at_impact + 40.)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn entities_near_sorts_nearest_first() {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        let square = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(10., 10.)));
+        let point = vec2(500., 500.);
+        let colour = [1., 1., 1.];
+        let far = game_state.spawn_static(vec2(530., 495.), square.clone(), colour);
+        let under = game_state.spawn_static(vec2(495., 495.), square.clone(), colour);
+        let near = game_state.spawn_static(vec2(495., 510.), square.clone(), colour);
+        // Beyond the radius, directly below and diagonally.
+        game_state.spawn_static(vec2(495., 560.), square.clone(), colour);
+        game_state.spawn_static(vec2(440., 440.), square, colour);
+        assert_eq!(
+            game_state.entities_near(point, 40.),
+            vec![(under, 0.), (near, 10.), (far, 30.)]
+        );
+        assert_eq!(game_state.entities_near(point, 4.), vec![(under, 0.)]);
+    }
+
+    #[test]
+    fn body_rests_stably_in_a_tight_corridor() {
+        let mut game_state = floor_state(Default::default());
+        game_state.spawn_static(
+            vec2(0., FLOOR_Y - 84.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(1000., 20.))),
+            [1., 1., 1.],
+        );
+        let start = vec2(100., FLOOR_Y - 64.);
+        let player_id = game_state.spawn_player(start, None);
+        let mut input_model = InputModel::default();
+        press_jump(&mut game_state, &mut input_model);
+        input_model.set_right(1.);
+        for _ in 0..60 {
+            run(&mut game_state, &mut input_model, 1);
+            assert_eq!(game_state.position(player_id).unwrap().y, start.y);
+            assert_eq!(game_state.velocity(player_id).unwrap().y, 0.);
+            assert!(game_state.last_frame_crushed().is_empty());
+        }
+        assert!(game_state.position(player_id).unwrap().x > start.x);
+    }
+
+    #[test]
+    fn body_slides_along_a_polyline_without_catching() {
+        let mut game_state: GameState = GameState::new(vec2(1400., 1000.));
+        let polyline = Polyline::new(vec![
+            vec2(0., 600.),
+            vec2(200., 500.),
+            vec2(400., 450.),
+            vec2(600., 450.),
+            vec2(800., 500.),
+            vec2(1000., 500.),
+            vec2(1200., 450.),
+        ]);
+        game_state.add_polyline(vec2(0., 0.), &polyline, [1., 1., 1.]);
+        let player_id = game_state.spawn_player(vec2(50., 450.), None);
+        let mut input_model = InputModel::default();
+        input_model.set_right(1.);
+        run(&mut game_state, &mut input_model, 30);
+        // Walk over the hill, through the valley beyond it and back, passing
+        // each vertex both ways without losing speed at any of them.
+        for &direction in [1., -1.].iter() {
+            input_model.set_right(direction);
+            input_model.set_left(-direction);
+            run(&mut game_state, &mut input_model, 10);
+            for _ in 0..220 {
+                let x = game_state.position(player_id).unwrap().x;
+                run(&mut game_state, &mut input_model, 1);
+                let step = (game_state.position(player_id).unwrap().x - x) * direction;
+                assert!(step > 3.5);
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_scene_tests_few_candidates() {
+        const NUM_BOXES: u64 = 20;
+        let mut game_state = floor_state(Default::default());
+        for i in 0..NUM_BOXES {
+            game_state.spawn_dynamic(
+                vec2(20. + i as f64 * 40., FLOOR_Y - 20.),
+                Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+                [1., 1., 1.],
+            );
+        }
+        let mut changes = GameStateChanges::default();
+        let mut movement_context = MovementContext::default();
+        for _ in 0..3 {
+            game_state.update(
+                &InputModel::default(),
+                &mut changes,
+                &mut movement_context,
+                FIXED_DT,
+            );
+            // Each resting box only ever meets the floor: once falling into it,
+            // once sliding along it, and once each checking for floor and
+            // ceiling contact.
+            let count = movement_context.last_frame_candidate_count();
+            assert_eq!(count, NUM_BOXES * 4);
+            assert!(count < NUM_BOXES * NUM_BOXES / 4);
+        }
+    }
+
+    #[test]
+    fn surface_aligned_player_walks_up_an_inside_corner() {
+        let mut game_state = floor_state(Default::default());
+        game_state.spawn_static(
+            vec2(600., 0.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., FLOOR_Y))),
+            [1., 1., 1.],
+        );
+        let player_id = game_state.spawn_player(vec2(500., FLOOR_Y - 64.), None);
+        game_state.set_surface_aligned(player_id, true);
+        let mut input_model = InputModel::default();
+        input_model.set_right(1.);
+        run(&mut game_state, &mut input_model, 20);
+        assert_eq!(game_state.local_up(player_id), Some(vec2(-1., 0.)));
+        let wall_x = 600. - 32.;
+        let mut y = game_state.position(player_id).unwrap().y;
+        for _ in 0..60 {
+            run(&mut game_state, &mut input_model, 1);
+            let position = game_state.position(player_id).unwrap();
+            assert_eq!(position.x, wall_x);
+            assert!(position.y < y);
+            y = position.y;
+        }
+        assert!(y < FLOOR_Y - 200.);
+    }
+
+    #[test]
+    fn diff_pinpoints_the_divergent_entity() {
+        let simulate = |nudge: Vector2<f64>| {
+            let mut game_state = floor_state(Default::default());
+            let square = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.)));
+            game_state.spawn_dynamic(vec2(100., 300.), square.clone(), [1., 1., 1.]);
+            let nudged = game_state.spawn_dynamic(vec2(300., 300.), square, [1., 1., 1.]);
+            game_state.set_velocity(nudged, nudge);
+            run(&mut game_state, &mut InputModel::default(), 10);
+            (game_state, nudged)
+        };
+        let (original, _) = simulate(vec2(0., 0.));
+        assert!(original.diff(&simulate(vec2(0., 0.)).0).is_empty());
+        let (nudged_state, nudged) = simulate(vec2(1., 0.));
+        let diffs = original.diff(&nudged_state);
+        assert_eq!(
+            diffs,
+            vec![
+                EntityDiff::Position {
+                    entity_id: nudged,
+                    ours: original.position(nudged).unwrap(),
+                    theirs: nudged_state.position(nudged).unwrap(),
+                },
+                EntityDiff::Velocity {
+                    entity_id: nudged,
+                    ours: vec2(0., 5.),
+                    theirs: vec2(1., 5.),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ghost_flies_through_a_wall_reporting_it() {
+        let mut game_state = weightless_state();
+        let wall = game_state.spawn_static(
+            vec2(300., 0.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 1000.))),
+            [1., 1., 1.],
+        );
+        let square = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.)));
+        let colour = [1., 1., 1.];
+        let ghost = game_state.spawn_dynamic(vec2(200., 100.), square.clone(), colour);
+        let solid = game_state.spawn_dynamic(vec2(200., 500.), square, colour);
+        game_state.set_resolve_collisions(ghost, false);
+        game_state.set_velocity(ghost, vec2(10., 0.));
+        game_state.set_velocity(solid, vec2(10., 0.));
+        let mut hits = Vec::new();
+        for _ in 0..20 {
+            run(&mut game_state, &mut InputModel::default(), 1);
+            hits.extend(game_state.last_frame_collisions().iter().filter_map(|event| {
+                if event.moving_entity_id == ghost {
+                    Some(event.stationary_entity_id)
+                } else {
+                    None
+                }
+            }));
+        }
+        assert_eq!(game_state.position(ghost), Some(vec2(400., 100.)));
+        assert_eq!(game_state.velocity(ghost), Some(vec2(10., 0.)));
+        assert!(!hits.is_empty());
+        assert!(hits.iter().all(|&id| id == wall));
+        assert_eq!(game_state.position(solid), Some(vec2(280., 500.)));
+    }
+
+    #[test]
+    fn ascending_past_a_ledge_corner_does_not_ground() {
+        let grounded_after_rising_past_ledge = |config| {
+            let mut game_state = floor_state(config);
+            game_state.spawn_static(
+                vec2(300., 300.),
+                Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(100., 20.))),
+                [1., 1., 1.],
+            );
+            let player_id = game_state.spawn_player(vec2(380., 300. - 64.), None);
+            game_state.set_velocity(player_id, vec2(0., -6.));
+            let mut input_model = InputModel::default();
+            run(&mut game_state, &mut input_model, 1);
+            let rising = game_state.player_grounded;
+            run(&mut game_state, &mut input_model, 40);
+            assert_eq!(game_state.position(player_id), Some(vec2(380., 300. - 64.)));
+            (rising, game_state.player_grounded)
+        };
+        assert_eq!(grounded_after_rising_past_ledge(Default::default()), (false, true));
+        let config = PhysicsConfig {
+            landing_velocity_threshold: ::std::f64::INFINITY,
+            ..Default::default()
+        };
+        assert_eq!(grounded_after_rising_past_ledge(config), (true, true));
+    }
+
+    #[test]
+    fn manual_broadphase_rebuild_updates_queries() {
+        let config = PhysicsConfig {
+            auto_rebuild_broadphase: false,
+            ..Default::default()
+        };
+        let mut game_state: GameState =
+            GameState::with_config(vec2(1000., 1000.), config);
+        let square = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.)));
+        let ids = (0..3)
+            .map(|i| {
+                let position = vec2(i as f64 * 100., 0.);
+                game_state.spawn_static(position, square.clone(), [1., 1., 1.])
+            })
+            .collect::<Vec<_>>();
+        game_state.rebuild_broadphase();
+        let old_region = Aabb::new(vec2(0., 0.), vec2(250., 50.));
+        let new_region = Aabb::new(vec2(0., 500.), vec2(250., 50.));
+        for (i, &id) in ids.iter().enumerate() {
+            game_state.set_position(id, vec2(i as f64 * 100., 500.));
+        }
+        // Queries still see the old positions until the caller rebuilds.
+        assert_eq!(game_state.entities_in_region(old_region).len(), 3);
+        assert!(game_state.entities_in_region(new_region).is_empty());
+        game_state.rebuild_broadphase();
+        assert!(game_state.entities_in_region(old_region).is_empty());
+        let mut found = game_state.entities_in_region(new_region);
+        found.sort();
+        assert_eq!(found, ids);
+    }
+
+    #[test]
+    fn circle_settles_on_a_rect_without_jitter() {
+        let mut game_state = floor_state(Default::default());
+        let ball = game_state.spawn_dynamic(
+            vec2(100., FLOOR_Y - 100.),
+            Shape::Circle(Circle::new(10.)),
+            [1., 1., 1.],
+        );
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 30);
+        let rest = game_state.position(ball).unwrap();
+        assert!((rest.y + 20. - FLOOR_Y).abs() < 0.5);
+        for _ in 0..60 {
+            run(&mut game_state, &mut input_model, 1);
+            assert_eq!(game_state.position(ball), Some(rest));
+            assert_eq!(game_state.velocity(ball), Some(vec2(0., 0.)));
+        }
+    }
+
+    #[test]
+    fn player_walks_over_a_hill_of_triangle_ramps() {
+        let mut game_state = floor_state(Default::default());
+        let up = vec![vec2(0., 100.), vec2(200., 0.), vec2(200., 100.)];
+        let down = vec![vec2(0., 0.), vec2(200., 100.), vec2(0., 100.)];
+        for &(x, ref vertices) in [(300., up), (500., down)].iter() {
+            let ramp = ConvexPolygon::from_vertices(vertices.clone()).unwrap();
+            game_state.spawn_static(
+                vec2(x, FLOOR_Y - 100.),
+                Shape::ConvexPolygon(ramp),
+                [1., 1., 1.],
+            );
+        }
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 70.), None);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 10);
+        // Walk over the hill and back, never slowing much on either slope.
+        for &(right, left) in [(1., 0.), (0., 1.)].iter() {
+            input_model.set_right(right);
+            input_model.set_left(left);
+            let mut highest = FLOOR_Y;
+            for _ in 0..175 {
+                let before = game_state.position(player_id).unwrap();
+                run(&mut game_state, &mut input_model, 1);
+                let after = game_state.position(player_id).unwrap();
+                assert!((after.x - before.x).abs() > 3.);
+                highest = highest.min(after.y);
+            }
+            let x = game_state.position(player_id).unwrap().x;
+            assert!(if right > 0. { x > 700. } else { x < 268. });
+            assert!(highest < FLOOR_Y - 64. - 90.);
+        }
+    }
+
+    #[test]
+    fn disabling_the_floor_channel_drops_through_floor_only_platforms() {
+        let mut game_state = floor_state(Default::default());
+        for &y in [300., 400.].iter() {
+            game_state.spawn_static(
+                vec2(0., y),
+                Shape::AxisAlignedRect(AxisAlignedRect::new_floor_only(vec2(600., 20.))),
+                [1., 1., 1.],
+            );
+        }
+        game_state.spawn_static(
+            vec2(400., 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 400.))),
+            [1., 1., 1.],
+        );
+        let player_id = game_state.spawn_player(vec2(100., 200.), None);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 30);
+        assert_eq!(game_state.position(player_id), Some(vec2(100., 300. - 64.)));
+        game_state.set_channel_enabled(channels::FLOOR, false);
+        assert!(!game_state.is_channel_enabled(channels::FLOOR));
+        run(&mut game_state, &mut input_model, 30);
+        assert_eq!(game_state.position(player_id), Some(vec2(100., FLOOR_Y - 64.)));
+        input_model.set_right(1.);
+        run(&mut game_state, &mut input_model, 100);
+        assert_eq!(game_state.position(player_id), Some(vec2(400. - 32., FLOOR_Y - 64.)));
+    }
+
+    // Boxes dropped at random onto the floor, piling onto each other.
+    fn random_pile(seed: u64) -> Vec<(f64, f64)> {
+        let mut game_state = floor_state(Default::default());
+        game_state.set_rng_seed(seed);
+        for _ in 0..10 {
+            let x = game_state.rng().range(100., 300.);
+            let y = game_state.rng().range(0., 400.);
+            game_state.spawn_dynamic(
+                vec2(x, y),
+                Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+                [1., 1., 1.],
+            );
+        }
+        run(&mut game_state, &mut InputModel::default(), 100);
+        sorted_positions(&game_state)
+    }
+
+    #[test]
+    fn same_rng_seed_reproduces_a_simulation() {
+        assert_eq!(random_pile(7), random_pile(7));
+        assert_ne!(random_pile(7), random_pile(8));
+    }
+
+    #[test]
+    fn resolve_overlaps_pops_out_of_a_three_wall_junction() {
+        let mut game_state = floor_state(Default::default());
+        let wall = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 200.)));
+        game_state.spawn_static(vec2(80., FLOOR_Y - 200.), wall, [1., 1., 1.]);
+        let step = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(10., 20.)));
+        game_state.spawn_static(vec2(100., FLOOR_Y - 20.), step, [1., 1., 1.]);
+        // Embedded in the floor, the wall and the step in the corner between
+        // them.
+        let player_id = game_state.spawn_player(vec2(95., FLOOR_Y - 60.), None);
+        assert_eq!(game_state.resolve_overlaps(player_id), vec2(15., -4.));
+        assert_eq!(game_state.position(player_id), Some(vec2(110., FLOOR_Y - 64.)));
+        assert_eq!(game_state.resolve_overlaps(player_id), vec2(0., 0.));
+    }
+
+    // Frames taken to stop after running at full speed over a floor with the
+    // given friction.
+    fn frames_to_stop(friction: f64) -> usize {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        let floor = game_state.spawn_static(
+            vec2(0., FLOOR_Y),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(1000., 20.))),
+            [1., 1., 1.],
+        );
+        game_state.set_friction(floor, friction);
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        let mut input_model = InputModel::default();
+        input_model.set_right(1.);
+        run(&mut game_state, &mut input_model, 60);
+        assert_eq!(game_state.velocity(player_id).unwrap().x, 4.);
+        input_model.set_right(0.);
+        let mut frames = 0;
+        while game_state.velocity(player_id).unwrap().x > 0. {
+            run(&mut game_state, &mut input_model, 1);
+            frames += 1;
+        }
+        frames
+    }
+
+    #[test]
+    fn high_friction_surfaces_stop_the_player_sooner() {
+        assert_eq!(frames_to_stop(0.8), 1);
+        assert_eq!(frames_to_stop(0.02), 25);
+    }
+
+    #[test]
+    fn demo_player_feet_are_at_the_bottom_centre() {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        game_state.init_demo();
+        let player_id = game_state.player_id().unwrap();
+        assert_eq!(game_state.position(player_id), Some(vec2(550., 436.)));
+        assert_eq!(game_state.entity_feet(player_id), Some(vec2(566., 500.)));
+        assert_eq!(game_state.entity_feet(player_id + 1000), None);
+    }
+
+    // How far a player standing on a platform moving right at 10 pixels per
+    // frame is carried each frame.
+    fn carried_steps(max_carry_speed: Option<f64>) -> Vec<f64> {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        game_state.config_mut().max_carry_speed = max_carry_speed;
+        let platform = game_state.spawn_kinematic(
+            vec2(0., FLOOR_Y),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(300., 20.))),
+            [1., 1., 1.],
+        );
+        game_state.set_velocity(platform, vec2(10., 0.));
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        let mut input_model = InputModel::default();
+        (0..10)
+            .map(|_| {
+                let x = game_state.position(player_id).unwrap().x;
+                run(&mut game_state, &mut input_model, 1);
+                game_state.position(player_id).unwrap().x - x
+            })
+            .collect()
+    }
+
+    #[test]
+    fn carried_velocity_is_capped() {
+        // Uncapped, the player keeps up with the platform once standing on it.
+        assert_eq!(carried_steps(None)[1..], [10.; 9]);
+        assert_eq!(carried_steps(Some(3.)), vec![3.; 10]);
+    }
+
+    #[test]
+    fn jump_count_advances_each_frame_while_held() {
+        let mut input_model = InputModel::default();
+        input_model.after_process();
+        assert_eq!(input_model.jump_count, None);
+        input_model.set_jump(true);
+        for count in 0..5 {
+            input_model.after_process();
+            assert_eq!(input_model.jump_count, Some(count));
+        }
+        input_model.set_jump(false);
+        input_model.after_process();
+        assert_eq!(input_model.jump_count, None);
+        // Held from the floor, the player keeps rising as the jump advances.
+        let mut game_state = floor_state(Default::default());
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        input_model.set_jump(true);
+        input_model.after_process();
+        let mut heights = Vec::new();
+        for _ in 0..5 {
+            run(&mut game_state, &mut input_model, 1);
+            heights.push(FLOOR_Y - 64. - game_state.position(player_id).unwrap().y);
+        }
+        assert!(heights[0] > 0.);
+        assert!(heights.windows(2).all(|pair| pair[1] > pair[0]));
+    }
+
+    #[test]
+    fn settle_brings_a_stack_of_boxes_to_rest() {
+        let mut game_state = floor_state(Default::default());
+        let boxes = (0..3)
+            .map(|i| {
+                game_state.spawn_dynamic(
+                    vec2(100., FLOOR_Y - 100. - i as f64 * 50.),
+                    Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+                    [1., 1., 1.],
+                )
+            })
+            .collect::<Vec<_>>();
+        let frames = game_state.settle(1000, &mut MovementContext::default());
+        assert!(frames < 1000);
+        for (i, &id) in boxes.iter().enumerate() {
+            assert!(game_state.velocity(id).unwrap().magnitude() < 0.01);
+            let y = FLOOR_Y - 20. * (i + 1) as f64;
+            assert_eq!(game_state.position(id), Some(vec2(100., y)));
+        }
+    }
+
+    #[test]
+    fn jump_pressed_just_before_landing_is_buffered() {
+        let falling_player = || {
+            let mut game_state = floor_state(Default::default());
+            let player_id = game_state.spawn_player(
+                vec2(100., FLOOR_Y - 164.),
+                Some(JumpStateMachine::NotJumping),
+            );
+            (game_state, player_id)
+        };
+        let (mut game_state, _) = falling_player();
+        let mut input_model = InputModel::default();
+        let mut frames_to_land = 0;
+        while !game_state.player_grounded {
+            run(&mut game_state, &mut input_model, 1);
+            frames_to_land += 1;
+        }
+        let (mut game_state, player_id) = falling_player();
+        run(&mut game_state, &mut input_model, frames_to_land - 2);
+        input_model.set_jump(true);
+        input_model.after_process();
+        run(&mut game_state, &mut input_model, 1);
+        assert!(!game_state.player_grounded);
+        assert!(!is_jumping(&game_state, player_id));
+        run(&mut game_state, &mut input_model, 1);
+        assert!(is_jumping(&game_state, player_id));
+        // The buffered press is used up by the jump it triggers.
+        assert_eq!(game_state.player_frames_since_jump_pressed, None);
+        run(&mut game_state, &mut input_model, 5);
+        assert!(game_state.position(player_id).unwrap().y < FLOOR_Y - 64. - 10.);
+    }
+
+    #[test]
+    fn player_slides_down_a_wall_and_kicks_off_it() {
+        let config = PhysicsConfig {
+            wall_jump_velocity: vec2(5., 7.),
+            ..Default::default()
+        };
+        let mut game_state = floor_state(config);
+        game_state.spawn_static(
+            vec2(300., 0.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., FLOOR_Y))),
+            [1., 1., 1.],
+        );
+        let player_id = game_state.spawn_player(vec2(268., 50.), None);
+        let mut input_model = InputModel::default();
+        input_model.set_right(1.);
+        run(&mut game_state, &mut input_model, 30);
+        for _ in 0..10 {
+            let y = game_state.position(player_id).unwrap().y;
+            run(&mut game_state, &mut input_model, 1);
+            let position = game_state.position(player_id).unwrap();
+            assert_eq!(position.x, 268.);
+            assert_eq!(position.y - y, 2.);
+        }
+        // Letting go of the wall, the fall speeds up again.
+        input_model.set_right(0.);
+        run(&mut game_state, &mut input_model, 1);
+        assert!(game_state.velocity(player_id).unwrap().y > 2.);
+        input_model.set_right(1.);
+        run(&mut game_state, &mut input_model, 5);
+        input_model.set_right(0.);
+        input_model.set_jump(true);
+        input_model.after_process();
+        let before = game_state.position(player_id).unwrap();
+        run(&mut game_state, &mut input_model, 1);
+        assert_eq!(game_state.position(player_id), Some(before + vec2(-5., -7.)));
+    }
+
+    #[test]
+    fn removed_entities_are_no_longer_rendered_or_simulated() {
+        let mut game_state = floor_state(Default::default());
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        let box_id = game_state.spawn_dynamic(
+            vec2(300., FLOOR_Y - 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            [1., 1., 1.],
+        );
+        assert_eq!(game_state.render_updates().count(), 3);
+        game_state.remove_entity(box_id);
+        assert_eq!(
+            sorted_positions(&game_state),
+            vec![(0., FLOOR_Y), (100., FLOOR_Y - 64.)]
+        );
+        assert_eq!(game_state.position(box_id), None);
+        assert!(game_state.entities_near(vec2(310., FLOOR_Y - 90.), 50.).is_empty());
+        game_state.remove_entity(player_id);
+        assert_eq!(game_state.player_id(), None);
+        assert_eq!(sorted_positions(&game_state), vec![(0., FLOOR_Y)]);
+        let mut input_model = InputModel::default();
+        input_model.set_right(1.);
+        run(&mut game_state, &mut input_model, 10);
+        assert_eq!(sorted_positions(&game_state), vec![(0., FLOOR_Y)]);
+    }
+
+    #[test]
+    fn entities_in_region_tests_bounding_boxes() {
+        let mut game_state = floor_state(Default::default());
+        let rect = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(50., 50.)));
+        let a = game_state.spawn_static(vec2(100., 100.), rect.clone(), [1., 1., 1.]);
+        let b = game_state.spawn_static(vec2(130., 130.), rect, [1., 1., 1.]);
+        let line = game_state.spawn_static(
+            vec2(400., 100.),
+            Shape::LineSegment(LineSegment::new_both_solid(
+                vec2(0., 0.),
+                vec2(100., 100.),
+            )),
+            [1., 1., 1.],
+        );
+        let region = |x, y, size| Aabb::new(vec2(x, y), vec2(size, size));
+        assert_eq!(game_state.entities_in_region(region(120., 120., 20.)), vec![a, b]);
+        assert_eq!(game_state.entities_in_region(region(90., 90., 20.)), vec![a]);
+        assert_eq!(game_state.entities_in_region(region(170., 170., 20.)), vec![b]);
+        assert_eq!(
+            game_state.entities_in_region(region(250., 100., 50.)),
+            Vec::<EntityId>::new()
+        );
+        // The line's bounding box covers the region, though the line doesn't.
+        assert_eq!(game_state.entities_in_region(region(480., 100., 10.)), vec![line]);
+    }
+
+    #[test]
+    fn interpolated_positions_blend_the_last_step() {
+        let mut game_state = weightless_state();
+        let id = game_state.spawn_dynamic(
+            vec2(100., 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            [1., 1., 1.],
+        );
+        game_state.set_velocity(id, vec2(4., 2.));
+        let interpolated = |game_state: &GameState, alpha| {
+            game_state
+                .render_updates_interpolated(alpha)
+                .next()
+                .unwrap()
+                .position
+        };
+        // Nothing to blend from before the first update.
+        assert_eq!(interpolated(&game_state, 0.), vec2(100., 100.));
+        run(&mut game_state, &mut InputModel::default(), 1);
+        assert_eq!(interpolated(&game_state, 0.), vec2(100., 100.));
+        assert_eq!(interpolated(&game_state, 0.5), vec2(102., 101.));
+        assert_eq!(interpolated(&game_state, 1.), vec2(104., 102.));
+        assert_eq!(game_state.position(id), Some(vec2(104., 102.)));
+    }
+
+    #[test]
+    fn falling_is_capped_at_the_max_fall_speed() {
+        let config = PhysicsConfig {
+            max_fall_speed: Some(3.),
+            ..Default::default()
+        };
+        let mut game_state = floor_state(config);
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 1);
+        // Jumping up isn't limited.
+        input_model.set_jump(true);
+        run(&mut game_state, &mut input_model, 6);
+        assert!(game_state.velocity(player_id).unwrap().y < -6.);
+        input_model.set_jump(false);
+        game_state.set_position(player_id, vec2(100., -2000.));
+        let mut max_fall_speed: f64 = 0.;
+        for _ in 0..200 {
+            run(&mut game_state, &mut input_model, 1);
+            let velocity = game_state.velocity(player_id).unwrap();
+            max_fall_speed = max_fall_speed.max(velocity.y);
+        }
+        assert_eq!(max_fall_speed, 3.);
+    }
+
+    // How far a player falls from rest in 20 frames.
+    fn fall_distance(config: PhysicsConfig) -> f64 {
+        let mut game_state = GameState::with_config(vec2(1000., 1000.), config);
+        let player_id = game_state.spawn_player(vec2(100., 0.), None);
+        run(&mut game_state, &mut InputModel::default(), 20);
+        game_state.position(player_id).unwrap().y
+    }
+
+    #[test]
+    fn doubled_gravity_falls_twice_as_far() {
+        let default = PhysicsConfig::default();
+        let doubled = PhysicsConfig {
+            gravity: default.gravity * 2.,
+            ..default
+        };
+        assert!(fall_distance(default) > 0.);
+        assert_eq!(fall_distance(doubled), fall_distance(default) * 2.);
+    }
+
+    #[test]
+    fn holding_down_drops_through_a_floor_only_platform() {
+        let mut game_state = floor_state(Default::default());
+        game_state.spawn_static(
+            vec2(0., 300.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new_floor_only(vec2(500., 20.))),
+            [1., 1., 1.],
+        );
+        let player_id = game_state.spawn_player(vec2(100., 300. - 64.), None);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 10);
+        assert!(game_state.player_grounded);
+        assert_eq!(game_state.position(player_id), Some(vec2(100., 300. - 64.)));
+        input_model.set_down(1.);
+        run(&mut game_state, &mut input_model, 60);
+        assert_eq!(game_state.position(player_id), Some(vec2(100., FLOOR_Y - 64.)));
+        // Solid floors can't be dropped through.
+        run(&mut game_state, &mut input_model, 60);
+        assert_eq!(game_state.position(player_id), Some(vec2(100., FLOOR_Y - 64.)));
+    }
+
+    #[test]
+    fn player_stays_centred_on_an_oscillating_platform() {
+        let mut game_state = floor_state(Default::default());
+        let platform = game_state.spawn_kinematic(
+            vec2(300., FLOOR_Y - 200.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(128., 20.))),
+            [1., 1., 1.],
+        );
+        let player_id = game_state.spawn_player(vec2(348., FLOOR_Y - 264.), None);
+        let mut input_model = InputModel::default();
+        run(&mut game_state, &mut input_model, 5);
+        let offset = vec2(48., -64.);
+        for frame in 0..240 {
+            let direction = if (frame / 30) % 2 == 0 { 1. } else { -1. };
+            game_state.set_velocity(platform, vec2(3. * direction, 0.));
+            run(&mut game_state, &mut input_model, 1);
+            let platform_position = game_state.position(platform).unwrap();
+            assert_eq!(
+                game_state.position(player_id),
+                Some(platform_position + offset)
+            );
+        }
+        assert_eq!(game_state.position(platform), Some(vec2(300., FLOOR_Y - 200.)));
+    }
+
+    #[test]
+    fn walking_through_a_sensor_enters_and_exits_it_once() {
+        let mut game_state = floor_state(Default::default());
+        let sensor = game_state.spawn_static(
+            vec2(200., FLOOR_Y - 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new_sensor(vec2(50., 100.))),
+            [1., 1., 1.],
+        );
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 64.), None);
+        let mut input_model = InputModel::default();
+        input_model.set_right(1.);
+        let mut events = Vec::new();
+        for _ in 0..60 {
+            run(&mut game_state, &mut input_model, 1);
+            events.extend(game_state.last_frame_sensor_events().iter().cloned());
+        }
+        // The sensor doesn't block.
+        assert_eq!(game_state.position(player_id), Some(vec2(340., FLOOR_Y - 64.)));
+        let event = |kind| SensorEvent {
+            entity_id: player_id,
+            sensor_id: sensor,
+            kind,
+        };
+        assert_eq!(
+            events,
+            vec![event(SensorEventKind::Enter), event(SensorEventKind::Exit)]
+        );
+    }
+
+    #[test]
+    fn landing_begins_contact_and_jumping_ends_it() {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        let floor = game_state.spawn_static(
+            vec2(0., FLOOR_Y),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(1000., 20.))),
+            [1., 1., 1.],
+        );
+        let player_id = game_state.spawn_player(vec2(100., FLOOR_Y - 100.), None);
+        let mut input_model = InputModel::default();
+        let mut events = Vec::new();
+        for _ in 0..30 {
+            run(&mut game_state, &mut input_model, 1);
+            events.extend(game_state.last_frame_contact_events().iter().cloned());
+        }
+        input_model.set_jump(true);
+        for _ in 0..10 {
+            run(&mut game_state, &mut input_model, 1);
+            events.extend(game_state.last_frame_contact_events().iter().cloned());
+        }
+        let event = |kind| ContactEvent {
+            a: floor,
+            b: player_id,
+            kind,
+        };
+        assert_eq!(events, vec![event(ContactKind::Begin), event(ContactKind::End)]);
+    }
+
+    #[test]
+    fn reset_puts_the_player_back_at_the_demo_spawn() {
+        let mut game_state: GameState = GameState::new(vec2(1000., 1000.));
+        game_state.init_demo();
+        let spawn = vec2(550., 500. - 64.);
+        let mut input_model = InputModel::default();
+        input_model.set_left(1.);
+        input_model.set_jump(true);
+        run(&mut game_state, &mut input_model, 20);
+        let player_id = game_state.player_id().unwrap();
+        assert_ne!(game_state.position(player_id), Some(spawn));
+        game_state.reset().unwrap();
+        assert_eq!(game_state.frame_count, 0);
+        let player_id = game_state.player_id().unwrap();
+        assert_eq!(game_state.position(player_id), Some(spawn));
+        run(&mut game_state, &mut InputModel::default(), 10);
+        assert_eq!(game_state.position(player_id), Some(spawn));
+    }
+
+    // Where a box moving right ends up after reaching a wall on layer 2 with
+    // a mask of layers 1 and 2, with the box on the given layer and mask.
+    fn box_moving_into_wall(layer: u32, mask: u32) -> Vector2<f64> {
+        let mut game_state = weightless_state();
+        let wall = game_state.spawn_static(
+            vec2(300., 0.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 1000.))),
+            [1., 1., 1.],
+        );
+        game_state.set_layer(wall, 2);
+        game_state.set_mask(wall, 3);
+        let id = game_state.spawn_dynamic(
+            vec2(100., 100.),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            [1., 1., 1.],
+        );
+        game_state.set_velocity(id, vec2(4., 0.));
+        game_state.set_layer(id, layer);
+        game_state.set_mask(id, mask);
+        run(&mut game_state, &mut InputModel::default(), 100);
+        game_state.position(id).unwrap()
+    }
+
+    #[test]
+    fn entities_on_non_overlapping_masks_pass_through_each_other() {
+        assert_eq!(box_moving_into_wall(1, !0), vec2(280., 100.));
+        // The box's mask doesn't include the wall's layer.
+        assert_eq!(box_moving_into_wall(1, 1), vec2(500., 100.));
+        // The wall's mask doesn't include the box's layer.
+        assert_eq!(box_moving_into_wall(4, !0), vec2(500., 100.));
+    }
+}
@@ -116,6 +116,7 @@ mod instance_renderer {
     }
     pub trait PipelineData<R: gfx::Resources>: gfx::pso::PipelineData<R> {
         type Instance: Copy + gfx::traits::Pod;
+        type Extra;
         type PipeInit: gfx::pso::PipelineInit<
             Meta = <Self as gfx::pso::PipelineData<R>>::Meta,
         >;
@@ -124,6 +125,7 @@ mod instance_renderer {
             instances: gfx::handle::Buffer<R, Self::Instance>,
             properties: gfx::handle::Buffer<R, buffer_types::WindowProperties>,
             target: gfx::handle::RenderTargetView<R, formats::Colour>,
+            extra: Self::Extra,
         ) -> Self;
         fn new_pipe() -> Self::PipeInit;
         fn instances(&self) -> &gfx::handle::Buffer<R, Self::Instance>;
@@ -139,6 +141,7 @@ mod instance_renderer {
         pub fn new<F>(
             colour_rtv: &gfx::handle::RenderTargetView<R, formats::Colour>,
             window_properties: &gfx::handle::Buffer<R, buffer_types::WindowProperties>,
+            extra: D::Extra,
             factory: &mut F,
         ) -> Self
         where
@@ -165,6 +168,7 @@ mod instance_renderer {
                 instances,
                 window_properties.clone(),
                 colour_rtv.clone(),
+                extra,
             );
             let bundle = gfx::pso::bundle::Bundle::new(slice, pso, data);
 
@@ -232,12 +236,14 @@ pub mod quad {
     });
     impl<R: gfx::Resources> PipelineData<R> for pipe::Data<R> {
         type Instance = Instance;
+        type Extra = ();
         type PipeInit = pipe::Init<'static>;
         fn new_data(
             corners: gfx::handle::Buffer<R, buffer_types::QuadCorners>,
             instances: gfx::handle::Buffer<R, Self::Instance>,
             properties: gfx::handle::Buffer<R, buffer_types::WindowProperties>,
             target: gfx::handle::RenderTargetView<R, formats::Colour>,
+            _extra: (),
         ) -> Self {
             pipe::Data {
                 quad_corners: corners,
@@ -273,6 +279,7 @@ pub mod line_segment {
         start: [f32; 2] = "i_Start",
         end: [f32; 2] = "i_End",
         colour: [f32; 3] = "i_Colour",
+        end_colour: [f32; 3] = "i_EndColour",
     });
 
     gfx_pipeline!(pipe {
@@ -285,12 +292,14 @@ pub mod line_segment {
 
     impl<R: gfx::Resources> PipelineData<R> for pipe::Data<R> {
         type Instance = Instance;
+        type Extra = ();
         type PipeInit = pipe::Init<'static>;
         fn new_data(
             corners: gfx::handle::Buffer<R, buffer_types::QuadCorners>,
             instances: gfx::handle::Buffer<R, Self::Instance>,
             properties: gfx::handle::Buffer<R, buffer_types::WindowProperties>,
             target: gfx::handle::RenderTargetView<R, formats::Colour>,
+            _extra: (),
         ) -> Self {
             pipe::Data {
                 quad_corners: corners,
@@ -316,8 +325,109 @@ pub mod line_segment {
     pub type Renderer<R> = instance_renderer::Renderer<R, pipe::Data<R>>;
 }
 
-use cgmath::Vector2;
+pub mod sprite {
+    use super::buffer_types;
+    use super::formats;
+    use super::instance_renderer::{self, PipelineData, ShaderBytes};
+    use gfx;
+
+    gfx_vertex_struct!(Instance {
+        position_of_top_left_in_pixels: [f32; 2] = "i_PositionOfTopLeftInPixels",
+        dimensions_in_pixels: [f32; 2] = "i_DimensionsInPixels",
+        uv_top_left: [f32; 2] = "i_UvTopLeft",
+        uv_size: [f32; 2] = "i_UvSize",
+    });
+
+    gfx_pipeline!(pipe {
+        quad_corners: gfx::VertexBuffer<buffer_types::QuadCorners> = (),
+        instances: gfx::InstanceBuffer<Instance> = (),
+        properties: gfx::ConstantBuffer<buffer_types::WindowProperties> = "WindowProperties",
+        atlas: gfx::TextureSampler<[f32; 4]> = "t_Atlas",
+        target: gfx::BlendTarget<formats::Colour> =
+            ("Target", gfx::state::ColorMask::all(), gfx::preset::blend::ALPHA),
+    });
+
+    pub type Atlas<R> = (
+        gfx::handle::ShaderResourceView<R, [f32; 4]>,
+        gfx::handle::Sampler<R>,
+    );
+
+    impl<R: gfx::Resources> PipelineData<R> for pipe::Data<R> {
+        type Instance = Instance;
+        type Extra = Atlas<R>;
+        type PipeInit = pipe::Init<'static>;
+        fn new_data(
+            corners: gfx::handle::Buffer<R, buffer_types::QuadCorners>,
+            instances: gfx::handle::Buffer<R, Self::Instance>,
+            properties: gfx::handle::Buffer<R, buffer_types::WindowProperties>,
+            target: gfx::handle::RenderTargetView<R, formats::Colour>,
+            atlas: Atlas<R>,
+        ) -> Self {
+            pipe::Data {
+                quad_corners: corners,
+                instances,
+                properties,
+                atlas,
+                target,
+            }
+        }
+        fn new_pipe() -> Self::PipeInit {
+            pipe::new()
+        }
+        fn instances(&self) -> &gfx::handle::Buffer<R, Self::Instance> {
+            &self.instances
+        }
+        fn shader_bytes() -> ShaderBytes {
+            ShaderBytes {
+                vertex: include_bytes!("shaders/sprite/shader.150.vert"),
+                fragment: include_bytes!("shaders/sprite/shader.150.frag"),
+            }
+        }
+    }
+
+    pub type Renderer<R> = instance_renderer::Renderer<R, pipe::Data<R>>;
+}
+
+use cgmath::{vec2, Vector2};
 use gfx;
+use std::f32::consts::PI;
+
+const CIRCLE_NUM_SEGMENTS: u32 = 24;
+
+// The top, bottom, left and right edges of a rect's outline, each as a top
+// left corner and size. The sides fit between the top and bottom so the
+// corners are only covered once.
+fn outline_edges(
+    top_left: Vector2<f32>,
+    size: Vector2<f32>,
+    thickness: f32,
+) -> [(Vector2<f32>, Vector2<f32>); 4] {
+    let thickness = thickness.min(size.x / 2.).min(size.y / 2.);
+    let horizontal = vec2(size.x, thickness);
+    let vertical = vec2(thickness, size.y - thickness * 2.);
+    [
+        (top_left, horizontal),
+        (top_left + vec2(0., size.y - thickness), horizontal),
+        (top_left + vec2(0., thickness), vertical),
+        (top_left + vec2(size.x - thickness, thickness), vertical),
+    ]
+}
+
+// The sprite shader stretches both the quad and its region of the atlas over
+// the corners of `consts::QUAD_COORDS`.
+fn sprite_instance(
+    top_left: Vector2<f32>,
+    size: Vector2<f32>,
+    uv_top_left: Vector2<f32>,
+    uv_size: Vector2<f32>,
+) -> sprite::Instance {
+    sprite::Instance {
+        position_of_top_left_in_pixels: top_left.into(),
+        dimensions_in_pixels: size.into(),
+        uv_top_left: uv_top_left.into(),
+        uv_size: uv_size.into(),
+    }
+}
 
 pub struct InstanceWriter<'a, R: gfx::Resources, T: 'a + Copy> {
     num_instances: &'a mut usize,
@@ -356,13 +466,117 @@ impl<'a, R: gfx::Resources, T: Copy> InstanceWriter<'a, R, T> {
 pub struct Frame<'a, R: gfx::Resources> {
     quad: InstanceWriter<'a, R, quad::Instance>,
     line_segment: InstanceWriter<'a, R, line_segment::Instance>,
+    sprites: Vec<InstanceWriter<'a, R, sprite::Instance>>,
 }
 
 impl<'a, R: gfx::Resources> Frame<'a, R> {
+    // Draws in window coordinates.
     pub fn updater(&mut self) -> FrameUpdater {
+        self.updater_with_transform(CameraTransform::identity())
+    }
+    // Draws in world coordinates, as seen by the camera.
+    pub fn camera_updater(&mut self, camera: &Camera) -> FrameUpdater {
+        self.updater_with_transform(camera.transform())
+    }
+    fn updater_with_transform(&mut self, transform: CameraTransform) -> FrameUpdater {
         FrameUpdater {
             quad: self.quad.iter_mut(),
             line_segment: self.line_segment.iter_mut(),
+            sprites: self.sprites.iter_mut().map(|sprite| sprite.iter_mut()).collect(),
+            transform,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CameraTransform {
+    offset: Vector2<f32>,
+    scale: f32,
+}
+
+impl CameraTransform {
+    fn identity() -> Self {
+        Self {
+            offset: vec2(0., 0.),
+            scale: 1.,
+        }
+    }
+    fn point(&self, point: Vector2<f32>) -> Vector2<f32> {
+        point * self.scale + self.offset
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    centre: Vector2<f64>,
+    zoom: f64,
+    window_size: Vector2<f64>,
+    smoothing: f64,
+    dead_zone: Vector2<f64>,
+}
+
+impl Camera {
+    pub fn new(window_size: Vector2<f64>) -> Self {
+        Self {
+            centre: window_size / 2.,
+            zoom: 1.,
+            window_size,
+            smoothing: 1.,
+            dead_zone: vec2(0., 0.),
+        }
+    }
+    pub fn centre(&self) -> Vector2<f64> {
+        self.centre
+    }
+    pub fn set_centre(&mut self, centre: Vector2<f64>) {
+        self.centre = centre;
+    }
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+    pub fn set_zoom(&mut self, zoom: f64) {
+        self.zoom = zoom;
+    }
+    pub fn set_window_size(&mut self, window_size: Vector2<f64>) {
+        self.window_size = window_size;
+    }
+    // The fraction of the remaining distance to the target covered by each
+    // call to `follow`. 1 snaps straight to the target.
+    pub fn set_smoothing(&mut self, smoothing: f64) {
+        self.smoothing = smoothing.max(0.).min(1.);
+    }
+    // The size (in world units) of a box around the centre within which the
+    // target can move without the camera following it.
+    pub fn set_dead_zone(&mut self, dead_zone: Vector2<f64>) {
+        self.dead_zone = dead_zone;
+    }
+    // Call once per frame with the position to keep in view.
+    pub fn follow(&mut self, target: Vector2<f64>) {
+        let half_dead_zone = self.dead_zone / 2.;
+        let delta = target - self.centre;
+        let outside = |delta: f64, half: f64| {
+            if delta > half {
+                delta - half
+            } else if delta < -half {
+                delta + half
+            } else {
+                0.
+            }
+        };
+        let desired = vec2(
+            outside(delta.x, half_dead_zone.x),
+            outside(delta.y, half_dead_zone.y),
+        );
+        self.centre += desired * self.smoothing;
+    }
+    pub fn screen_to_world(&self, screen: Vector2<f64>) -> Vector2<f64> {
+        (screen - self.window_size / 2.) / self.zoom + self.centre
+    }
+    fn transform(&self) -> CameraTransform {
+        let offset = self.window_size / 2. - self.centre * self.zoom;
+        CameraTransform {
+            offset: vec2(offset.x as f32, offset.y as f32),
+            scale: self.zoom as f32,
         }
     }
 }
@@ -370,6 +584,8 @@ impl<'a, R: gfx::Resources> Frame<'a, R> {
 pub struct FrameUpdater<'a> {
     quad: InstanceWriterIterMut<'a, quad::Instance>,
     line_segment: InstanceWriterIterMut<'a, line_segment::Instance>,
+    sprites: Vec<InstanceWriterIterMut<'a, sprite::Instance>>,
+    transform: CameraTransform,
 }
 
 impl<'a> FrameUpdater<'a> {
@@ -380,21 +596,92 @@ impl<'a> FrameUpdater<'a> {
         colour: [f32; 3],
     ) {
         if let Some(quad) = self.quad.next() {
-            quad.position_of_top_left_in_pixels = top_left.into();
-            quad.dimensions_in_pixels = size.into();
+            quad.position_of_top_left_in_pixels = self.transform.point(top_left).into();
+            quad.dimensions_in_pixels = (size * self.transform.scale).into();
             quad.colour = colour;
         }
     }
+    // Draws a rect textured with the given region of an atlas loaded with
+    // `Renderer::load_atlas`, falling back to a flat colour if there's no such
+    // atlas.
+    pub fn textured_rect(
+        &mut self,
+        texture_id: u32,
+        top_left: Vector2<f32>,
+        size: Vector2<f32>,
+        uv_top_left: Vector2<f32>,
+        uv_size: Vector2<f32>,
+        fallback_colour: [f32; 3],
+    ) {
+        match self.sprites.get_mut(texture_id as usize) {
+            Some(sprites) => if let Some(sprite) = sprites.next() {
+                *sprite = sprite_instance(
+                    self.transform.point(top_left),
+                    size * self.transform.scale,
+                    uv_top_left,
+                    uv_size,
+                );
+            },
+            None => self.axis_aligned_rect(top_left, size, fallback_colour),
+        }
+    }
+    pub fn axis_aligned_rect_outline(
+        &mut self,
+        top_left: Vector2<f32>,
+        size: Vector2<f32>,
+        colour: [f32; 3],
+        thickness: f32,
+    ) {
+        let edges = outline_edges(top_left, size, thickness);
+        for &(edge_top_left, edge_size) in edges.iter() {
+            self.axis_aligned_rect(edge_top_left, edge_size, colour);
+        }
+    }
+    // Like `axis_aligned_rect_outline`, but drawn with line segments so the
+    // outline stays one pixel wide at any zoom.
+    pub fn axis_aligned_rect_wireframe(
+        &mut self,
+        top_left: Vector2<f32>,
+        size: Vector2<f32>,
+        colour: [f32; 3],
+    ) {
+        let top_right = top_left + vec2(size.x, 0.);
+        let bottom_left = top_left + vec2(0., size.y);
+        let bottom_right = top_left + size;
+        self.line_segment(top_left, top_right, colour);
+        self.line_segment(top_right, bottom_right, colour);
+        self.line_segment(bottom_right, bottom_left, colour);
+        self.line_segment(bottom_left, top_left, colour);
+    }
     pub fn line_segment(
         &mut self,
         start: Vector2<f32>,
         end: Vector2<f32>,
         colour: [f32; 3],
+    ) {
+        self.line_segment_gradient(start, end, colour, colour);
+    }
+    pub fn circle(&mut self, centre: Vector2<f32>, radius: f32, colour: [f32; 3]) {
+        let point = |i: u32| {
+            let angle = (i as f32 / CIRCLE_NUM_SEGMENTS as f32) * 2. * PI;
+            centre + vec2(angle.cos(), angle.sin()) * radius
+        };
+        for i in 0..CIRCLE_NUM_SEGMENTS {
+            self.line_segment(point(i), point(i + 1), colour);
+        }
+    }
+    pub fn line_segment_gradient(
+        &mut self,
+        start: Vector2<f32>,
+        end: Vector2<f32>,
+        start_colour: [f32; 3],
+        end_colour: [f32; 3],
     ) {
         if let Some(line_segment) = self.line_segment.next() {
-            line_segment.start = start.into();
-            line_segment.end = end.into();
-            line_segment.colour = colour;
+            line_segment.start = self.transform.point(start).into();
+            line_segment.end = self.transform.point(end).into();
+            line_segment.colour = start_colour;
+            line_segment.end_colour = end_colour;
         }
     }
 }
@@ -402,6 +689,9 @@ impl<'a> FrameUpdater<'a> {
 pub struct Renderer<R: gfx::Resources> {
     pub quad: quad::Renderer<R>,
     pub line_segment: line_segment::Renderer<R>,
+    pub sprites: Vec<sprite::Renderer<R>>,
+    colour_rtv: gfx::handle::RenderTargetView<R, formats::Colour>,
+    window_properties: gfx::handle::Buffer<R, buffer_types::WindowProperties>,
 }
 
 impl<R: gfx::Resources> Renderer<R> {
@@ -421,14 +711,47 @@ impl<R: gfx::Resources> Renderer<R> {
             encoder,
         );
         Self {
-            quad: quad::Renderer::new(&colour_rtv, &window_properties, factory),
+            quad: quad::Renderer::new(&colour_rtv, &window_properties, (), factory),
             line_segment: line_segment::Renderer::new(
                 &colour_rtv,
                 &window_properties,
+                (),
                 factory,
             ),
+            sprites: Vec::new(),
+            colour_rtv,
+            window_properties,
         }
     }
+    // Takes the atlas as tightly packed rgba rows, top row first, and returns
+    // the texture id to draw it with.
+    pub fn load_atlas<F>(
+        &mut self,
+        factory: &mut F,
+        size: Vector2<u16>,
+        rgba: &[u8],
+    ) -> Result<u32, gfx::CombinedError>
+    where
+        F: gfx::Factory<R> + gfx::traits::FactoryExt<R>,
+    {
+        let kind = gfx::texture::Kind::D2(size.x, size.y, gfx::texture::AaMode::Single);
+        let (_, view) = factory.create_texture_immutable_u8::<formats::Colour>(
+            kind,
+            gfx::texture::Mipmap::Provided,
+            &[rgba],
+        )?;
+        let sampler = factory.create_sampler(gfx::texture::SamplerInfo::new(
+            gfx::texture::FilterMethod::Scale,
+            gfx::texture::WrapMode::Clamp,
+        ));
+        self.sprites.push(sprite::Renderer::new(
+            &self.colour_rtv,
+            &self.window_properties,
+            (view, sampler),
+            factory,
+        ));
+        Ok(self.sprites.len() as u32 - 1)
+    }
     pub fn prepare_frame<F>(&mut self, factory: &mut F) -> Frame<R>
     where
         F: gfx::Factory<R> + gfx::traits::FactoryExt<R>,
@@ -436,6 +759,10 @@ impl<R: gfx::Resources> Renderer<R> {
         Frame {
             quad: self.quad.instance_writer(factory),
             line_segment: self.line_segment.instance_writer(factory),
+            sprites: self.sprites
+                .iter_mut()
+                .map(|sprite| sprite.instance_writer(factory))
+                .collect(),
         }
     }
     pub fn encode<C>(&self, encoder: &mut gfx::Encoder<R, C>)
@@ -443,6 +770,75 @@ impl<R: gfx::Resources> Renderer<R> {
         C: gfx::CommandBuffer<R>,
     {
         self.quad.encode(encoder);
+        for sprite in self.sprites.iter() {
+            sprite.encode(encoder);
+        }
         self.line_segment.encode(encoder);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::ElementWise;
+    use simple_physics::game::Sprite;
+
+    #[test]
+    fn outline_edges_meet_the_rect_corners() {
+        let top_left = vec2(10., 20.);
+        let size = vec2(100., 50.);
+        let edges = outline_edges(top_left, size, 2.);
+        assert_eq!(
+            edges,
+            [
+                (vec2(10., 20.), vec2(100., 2.)),
+                (vec2(10., 68.), vec2(100., 2.)),
+                (vec2(10., 22.), vec2(2., 46.)),
+                (vec2(108., 22.), vec2(2., 46.)),
+            ]
+        );
+        let area = edges
+            .iter()
+            .map(|&(_, edge_size)| edge_size.x * edge_size.y)
+            .sum::<f32>();
+        assert_eq!(area, size.x * size.y - 96. * 46.);
+    }
+
+    #[test]
+    fn sprite_quad_maps_corners_to_the_atlas_region() {
+        let atlas_size = vec2(256., 128.);
+        let sprite =
+            Sprite::from_atlas_pixels(0, atlas_size, vec2(64., 32.), vec2(32., 32.));
+        let instance = sprite_instance(
+            vec2(100., 200.),
+            vec2(40., 40.),
+            sprite.uv_top_left,
+            sprite.uv_size,
+        );
+        // Each corner's pixel and atlas coordinates, as the vertex shader
+        // computes them.
+        let corners = consts::QUAD_COORDS
+            .iter()
+            .map(|corner| {
+                let corner = Vector2::from(*corner);
+                let top_left = Vector2::from(instance.position_of_top_left_in_pixels);
+                let size = Vector2::from(instance.dimensions_in_pixels);
+                let uv_top_left = Vector2::from(instance.uv_top_left);
+                let uv_size = Vector2::from(instance.uv_size);
+                (
+                    top_left + corner.mul_element_wise(size),
+                    uv_top_left + corner.mul_element_wise(uv_size),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            corners,
+            vec![
+                (vec2(100., 200.), vec2(0.25, 0.25)),
+                (vec2(100., 240.), vec2(0.25, 0.5)),
+                (vec2(140., 240.), vec2(0.375, 0.5)),
+                (vec2(140., 200.), vec2(0.375, 0.25)),
+            ]
+        );
+    }
+}
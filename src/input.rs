@@ -0,0 +1,121 @@
+use cgmath::{vec2, InnerSpace, Vector2};
+use gilrs::{self, Axis, Button, EventType, Gilrs};
+use glutin::VirtualKeyCode;
+use simple_physics::game::InputModel;
+use simple_physics::key_bindings::{Action, KeyBindings};
+
+pub const DEFAULT_DEAD_ZONE: f64 = 0.2;
+
+// The state of a single input device, so the keyboard and gamepad can be used
+// at the same time without one releasing what the other is holding.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Controls {
+    pub left: f64,
+    pub right: f64,
+    pub up: f64,
+    pub down: f64,
+    pub jump: bool,
+}
+
+impl Controls {
+    pub fn combine(&self, other: &Self) -> Self {
+        Self {
+            left: self.left.max(other.left),
+            right: self.right.max(other.right),
+            up: self.up.max(other.up),
+            down: self.down.max(other.down),
+            jump: self.jump || other.jump,
+        }
+    }
+    pub fn apply(&self, input_model: &mut InputModel) {
+        input_model.set_left(self.left);
+        input_model.set_right(self.right);
+        input_model.set_up(self.up);
+        input_model.set_down(self.down);
+        input_model.set_jump(self.jump);
+    }
+}
+
+pub fn default_key_bindings() -> KeyBindings<VirtualKeyCode> {
+    let mut bindings = KeyBindings::new();
+    bindings.bind(VirtualKeyCode::Left, Action::MoveLeft);
+    bindings.bind(VirtualKeyCode::Right, Action::MoveRight);
+    bindings.bind(VirtualKeyCode::Up, Action::MoveUp);
+    bindings.bind(VirtualKeyCode::Down, Action::MoveDown);
+    bindings.bind(VirtualKeyCode::A, Action::MoveLeft);
+    bindings.bind(VirtualKeyCode::D, Action::MoveRight);
+    bindings.bind(VirtualKeyCode::W, Action::MoveUp);
+    bindings.bind(VirtualKeyCode::S, Action::MoveDown);
+    bindings.bind(VirtualKeyCode::Space, Action::Jump);
+    bindings.bind(VirtualKeyCode::Return, Action::Reset);
+    bindings.bind(VirtualKeyCode::P, Action::Pause);
+    bindings.bind(VirtualKeyCode::Period, Action::Step);
+    bindings
+}
+
+pub struct Gamepad {
+    gilrs: Option<Gilrs>,
+    dead_zone: f64,
+    stick: Vector2<f64>,
+    jump: bool,
+}
+
+impl Gamepad {
+    // Gamepads are optional, so if they can't be used this just never
+    // reports any input.
+    pub fn new(dead_zone: f64) -> Self {
+        Self {
+            gilrs: Gilrs::new().ok(),
+            dead_zone,
+            stick: vec2(0., 0.),
+            jump: false,
+        }
+    }
+    pub fn set_dead_zone(&mut self, dead_zone: f64) {
+        self.dead_zone = dead_zone;
+    }
+    pub fn poll(&mut self) {
+        let gilrs = match self.gilrs {
+            Some(ref mut gilrs) => gilrs,
+            None => return,
+        };
+        while let Some(gilrs::Event { event, .. }) = gilrs.next_event() {
+            match event {
+                EventType::AxisChanged(Axis::LeftStickX, value, _) => {
+                    self.stick.x = value as f64
+                }
+                EventType::AxisChanged(Axis::LeftStickY, value, _) => {
+                    self.stick.y = value as f64
+                }
+                EventType::ButtonPressed(Button::South, _) => self.jump = true,
+                EventType::ButtonReleased(Button::South, _) => self.jump = false,
+                EventType::Disconnected => {
+                    self.stick = vec2(0., 0.);
+                    self.jump = false;
+                }
+                _ => (),
+            }
+        }
+    }
+    // The stick is rescaled from the edge of the (circular) dead zone, so
+    // small movements past it still give small speeds.
+    fn stick(&self) -> Vector2<f64> {
+        let magnitude = self.stick.magnitude();
+        if magnitude <= self.dead_zone {
+            return vec2(0., 0.);
+        }
+        let scaled = ((magnitude - self.dead_zone) / (1. - self.dead_zone)).min(1.);
+        self.stick * (scaled / magnitude)
+    }
+    pub fn controls(&self) -> Controls {
+        // The stick's y axis points up.
+        let stick = self.stick();
+        Controls {
+            left: (-stick.x).max(0.),
+            right: stick.x.max(0.),
+            up: stick.y.max(0.),
+            down: (-stick.y).max(0.),
+            jump: self.jump,
+        }
+    }
+}
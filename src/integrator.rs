@@ -0,0 +1,27 @@
+use cgmath::Vector2;
+
+pub trait Integrator {
+    fn integrate(
+        &self,
+        position: Vector2<f64>,
+        velocity: Vector2<f64>,
+        acceleration: Vector2<f64>,
+        dt: f64,
+    ) -> (Vector2<f64>, Vector2<f64>);
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SemiImplicitEuler;
+
+impl Integrator for SemiImplicitEuler {
+    fn integrate(
+        &self,
+        position: Vector2<f64>,
+        velocity: Vector2<f64>,
+        acceleration: Vector2<f64>,
+        dt: f64,
+    ) -> (Vector2<f64>, Vector2<f64>) {
+        let velocity = velocity + acceleration * dt;
+        (position + velocity * dt, velocity)
+    }
+}
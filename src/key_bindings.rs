@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Jump,
+    Reset,
+    Pause,
+    Step,
+}
+
+// Each key triggers at most one action, but an action can have any number of
+// keys. Generic over the key type so it doesn't depend on the windowing
+// library.
+#[derive(Debug, Clone)]
+pub struct KeyBindings<K: Eq + Hash> {
+    actions: HashMap<K, Action>,
+}
+
+impl<K: Eq + Hash> KeyBindings<K> {
+    pub fn new() -> Self {
+        Self {
+            actions: HashMap::new(),
+        }
+    }
+    pub fn bind(&mut self, key: K, action: Action) {
+        self.actions.insert(key, action);
+    }
+    pub fn unbind(&mut self, key: &K) {
+        self.actions.remove(key);
+    }
+    // Replaces every key bound to `action` with `key`.
+    pub fn rebind(&mut self, action: Action, key: K) {
+        self.actions.retain(|_, bound| *bound != action);
+        self.bind(key, action);
+    }
+    pub fn action(&self, key: &K) -> Option<Action> {
+        self.actions.get(key).cloned()
+    }
+}
+
+impl<K: Eq + Hash> Default for KeyBindings<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remapped_jump_resolves_to_the_new_key() {
+        let mut bindings = KeyBindings::new();
+        bindings.bind(' ', Action::Jump);
+        bindings.bind('a', Action::MoveLeft);
+        bindings.bind('<', Action::MoveLeft);
+        assert_eq!(bindings.action(&' '), Some(Action::Jump));
+        bindings.rebind(Action::Jump, 'z');
+        assert_eq!(bindings.action(&'z'), Some(Action::Jump));
+        assert_eq!(bindings.action(&' '), None);
+        assert_eq!(bindings.action(&'a'), Some(Action::MoveLeft));
+        assert_eq!(bindings.action(&'<'), Some(Action::MoveLeft));
+    }
+
+    #[test]
+    fn rebinding_replaces_every_key_for_the_action() {
+        let mut bindings = KeyBindings::new();
+        bindings.bind("x", Action::Jump);
+        bindings.bind("c", Action::Jump);
+        bindings.bind("r", Action::Reset);
+        bindings.rebind(Action::Jump, "z");
+        assert_eq!(bindings.action(&"x"), None);
+        assert_eq!(bindings.action(&"c"), None);
+        assert_eq!(bindings.action(&"z"), Some(Action::Jump));
+        assert_eq!(bindings.action(&"r"), Some(Action::Reset));
+        bindings.unbind(&"r");
+        assert_eq!(bindings.action(&"r"), None);
+    }
+}
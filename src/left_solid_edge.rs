@@ -110,6 +110,13 @@ impl LeftSolidEdgeCollision {
         let perpendicular_to_edge_vector = vec2(self.edge_vector.y, -self.edge_vector.x);
         movement_attempt.project_on(perpendicular_to_edge_vector)
     }
+    pub fn moving_edge_collision_count(&self) -> usize {
+        match self.moving_edge_collisions {
+            EdgeCollisions::Zero => 0,
+            EdgeCollisions::One(_) => 1,
+            EdgeCollisions::Two { .. } => 2,
+        }
+    }
     pub fn moving_edge_min_collision_position(&self) -> Option<EdgeCollisionPosition> {
         self.moving_edge_collisions.min_edge_collision_position()
     }
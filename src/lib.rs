@@ -0,0 +1,52 @@
+#![feature(nonzero)]
+
+extern crate best;
+extern crate cgmath;
+extern crate fnv;
+#[cfg(feature = "ron_level")]
+extern crate ron;
+#[cfg(feature = "serialize")]
+extern crate serde;
+#[cfg(feature = "serialize")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(all(test, feature = "serialize"))]
+extern crate serde_json;
+#[cfg(feature = "tiled")]
+extern crate xml;
+
+pub mod aabb;
+pub mod axis_aligned_rect;
+pub mod binary_level;
+pub mod bump;
+pub mod capsule;
+pub mod circle;
+pub mod collide;
+pub mod convex_polygon;
+pub mod game;
+pub mod integrator;
+pub mod key_bindings;
+pub mod left_solid_edge;
+pub mod line_segment;
+pub mod loose_quad_tree;
+pub mod movement;
+pub mod penetration;
+pub mod polyline;
+pub mod raycast;
+pub mod rng;
+#[cfg(feature = "ron_level")]
+pub mod ron_level;
+pub mod shape;
+pub mod svg;
+#[cfg(feature = "tiled")]
+pub mod tiled;
+
+pub use aabb::Aabb;
+pub use axis_aligned_rect::AxisAlignedRect;
+pub use capsule::Capsule;
+pub use circle::Circle;
+pub use collide::Collide;
+pub use convex_polygon::ConvexPolygon;
+pub use line_segment::LineSegment;
+pub use movement::MovementContext;
+pub use shape::{Shape, ShapePosition};
@@ -3,32 +3,158 @@ use cgmath::{vec2, InnerSpace, Vector2};
 use collide::{Collide, Edge};
 
 const WIDTH: f64 = 0.1;
+const MIN_MITRE_COS: f64 = 0.25;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum Solidity {
+    Both,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct LineSegment {
     pub start: Vector2<f64>,
     pub end: Vector2<f64>,
+    solidity: Solidity,
+    previous: Option<Vector2<f64>>,
+    next: Option<Vector2<f64>>,
 }
 
 impl LineSegment {
+    pub fn with_solidity(
+        start: Vector2<f64>,
+        end: Vector2<f64>,
+        solidity: Solidity,
+    ) -> Self {
+        Self {
+            start,
+            end,
+            solidity,
+            previous: None,
+            next: None,
+        }
+    }
     pub fn new_both_solid(start: Vector2<f64>, end: Vector2<f64>) -> Self {
-        Self { start, end }
+        Self::with_solidity(start, end, Solidity::Both)
+    }
+    pub fn new_left_solid(start: Vector2<f64>, end: Vector2<f64>) -> Self {
+        Self::with_solidity(start, end, Solidity::Left)
+    }
+    pub fn new_right_solid(start: Vector2<f64>, end: Vector2<f64>) -> Self {
+        Self::with_solidity(start, end, Solidity::Right)
+    }
+    pub fn new_one_way_from(
+        start: Vector2<f64>,
+        end: Vector2<f64>,
+        pass_through_direction: Vector2<f64>,
+    ) -> Self {
+        let vector = end - start;
+        let left = vec2(-vector.y, vector.x);
+        if left.dot(pass_through_direction) < 0. {
+            Self::new_left_solid(start, end)
+        } else {
+            Self::new_right_solid(start, end)
+        }
+    }
+    pub fn with_neighbours(
+        self,
+        previous: Option<Vector2<f64>>,
+        next: Option<Vector2<f64>>,
+    ) -> Self {
+        Self {
+            previous,
+            next,
+            ..self
+        }
+    }
+    pub fn solidity(&self) -> Solidity {
+        self.solidity
+    }
+    pub fn neighbours(&self) -> (Option<Vector2<f64>>, Option<Vector2<f64>>) {
+        (self.previous, self.next)
     }
     pub fn add_vector(&self, vector: Vector2<f64>) -> Self {
         Self {
             start: self.start + vector,
             end: self.end + vector,
+            ..*self
         }
     }
     pub fn vector(&self) -> Vector2<f64> {
         self.end - self.start
     }
-    fn left_solid_edge(&self) -> Edge {
-        Edge::new(self.start, self.end)
+    pub fn is_intersecting_aabb(&self, top_left: Vector2<f64>, aabb: &Aabb) -> bool {
+        let start = self.start + top_left;
+        let vector = self.vector();
+        let aabb_top_left = aabb.top_left();
+        let aabb_bottom_right = aabb.bottom_right_coord();
+        let boundaries = [
+            (-vector.x, start.x - aabb_top_left.x),
+            (vector.x, aabb_bottom_right.x - start.x),
+            (-vector.y, start.y - aabb_top_left.y),
+            (vector.y, aabb_bottom_right.y - start.y),
+        ];
+        let mut entering = 0.;
+        let mut leaving = 1.;
+        for &(direction, distance) in boundaries.iter() {
+            if direction == 0. {
+                if distance < 0. {
+                    return false;
+                }
+                continue;
+            }
+            let multiplier = distance / direction;
+            if direction < 0. {
+                if multiplier > leaving {
+                    return false;
+                }
+                entering = multiplier.max(entering);
+            } else {
+                if multiplier < entering {
+                    return false;
+                }
+                leaving = multiplier.min(leaving);
+            }
+        }
+        true
+    }
+    pub fn is_crossing(
+        &self,
+        top_left: Vector2<f64>,
+        other: &LineSegment,
+        other_top_left: Vector2<f64>,
+    ) -> bool {
+        let a = self.add_vector(top_left);
+        let b = other.add_vector(other_top_left);
+        let side = |segment: &LineSegment, point: Vector2<f64>| {
+            let vector = segment.vector();
+            let to_point = point - segment.start;
+            vector.x * to_point.y - vector.y * to_point.x
+        };
+        side(&a, b.start) * side(&a, b.end) < 0.
+            && side(&b, a.start) * side(&b, a.end) < 0.
     }
-    fn left_solid_edge_flipped(&self) -> Edge {
-        Edge::new(self.end, self.start)
+}
+
+fn left_normal(vector: Vector2<f64>) -> Vector2<f64> {
+    vec2(-vector.y, vector.x).normalize()
+}
+
+// Offset of the thin rectangle's corner at a vertex. Where the segment joins
+// a neighbour, the corner is mitred so both segments' sides meet exactly,
+// with no notch or overlapping corner at the shared vertex.
+fn joint_offset(left: Vector2<f64>, neighbour: Option<Vector2<f64>>) -> Vector2<f64> {
+    if let Some(neighbour) = neighbour {
+        let sum = left + left_normal(neighbour);
+        if sum.magnitude2() > 0. {
+            let mitre = sum.normalize();
+            return mitre * (WIDTH / mitre.dot(left).max(MIN_MITRE_COS));
+        }
     }
+    left * WIDTH
 }
 
 impl Collide for LineSegment {
@@ -52,13 +178,63 @@ impl Collide for LineSegment {
         _direction: Vector2<f64>,
         mut f: F,
     ) {
-        let vector = self.vector();
-        let left = vec2(-vector.y, vector.x).normalize_to(WIDTH);
-        let a = self.add_vector(left).left_solid_edge_flipped();
-        let b = self.add_vector(-left).left_solid_edge();
-        f(a);
-        f(b);
-        f(Edge::new(a.end(), b.start()));
-        f(Edge::new(b.end(), a.start()));
+        let left = left_normal(self.vector());
+        let start_offset = joint_offset(left, self.previous);
+        let end_offset = joint_offset(left, self.next);
+        let a = Edge::new(self.end + end_offset, self.start + start_offset);
+        let b = Edge::new(self.start - start_offset, self.end - end_offset);
+        match self.solidity {
+            Solidity::Both => {
+                f(a);
+                f(b);
+                f(Edge::new(a.end(), b.start()));
+                f(Edge::new(b.end(), a.start()));
+            }
+            Solidity::Left => f(b),
+            Solidity::Right => f(a),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axis_aligned_rect::AxisAlignedRect;
+    use movement::resolve_movement;
+    use shape::{Shape, ShapePosition};
+
+    // Moves a 10x10 rect against a segment sloping down at 45 degrees from
+    // (0, 100) to (100, 200).
+    fn move_against_slope(
+        position: Vector2<f64>,
+        movement: Vector2<f64>,
+    ) -> Vector2<f64> {
+        let slope = Shape::LineSegment(LineSegment::new_both_solid(
+            vec2(0., 100.),
+            vec2(100., 200.),
+        ));
+        let rect = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(10., 10.)));
+        let obstacles = [ShapePosition {
+            entity_id: 0,
+            position: vec2(0., 0.),
+            shape: &slope,
+        }];
+        resolve_movement(&rect, position, movement, &obstacles).position
+    }
+
+    #[test]
+    fn rect_collides_with_a_diagonal_segment() {
+        // Falling onto the slope, the bottom left corner lands at (40, 140) and
+        // slides down the slope with what's left of the movement, stopping just
+        // short of the surface.
+        let position = move_against_slope(vec2(40., 0.), vec2(0., 200.));
+        assert!((position - vec2(75., 165.)).magnitude() < 0.2);
+        // Moving right into its underside, the top right corner hits it at
+        // (50, 150) and slides down under it.
+        let position = move_against_slope(vec2(0., 150.), vec2(100., 0.));
+        assert!((position - vec2(70., 180.)).magnitude() < 0.2);
+        // Moving parallel to the slope never touches it.
+        let position = move_against_slope(vec2(20., 100.), vec2(50., 50.));
+        assert_eq!(position, vec2(70., 150.));
     }
 }
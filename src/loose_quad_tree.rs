@@ -1,5 +1,7 @@
 use aabb::*;
-use cgmath::{vec2, Vector2};
+use cgmath::{vec2, InnerSpace, Vector2};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 use std::num::NonZeroUsize;
 
 #[derive(Debug, Clone)]
@@ -34,6 +36,34 @@ impl<T> Node<T> {
     }
 }
 
+// A node waiting to be searched by `nearest`, ordered so that the closest is
+// popped from a `BinaryHeap` first.
+struct NodeToVisit {
+    distance2: f64,
+    index: usize,
+    aabb: Aabb,
+}
+
+impl PartialEq for NodeToVisit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for NodeToVisit {}
+impl PartialOrd for NodeToVisit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for NodeToVisit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .distance2
+            .partial_cmp(&self.distance2)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
 impl<T> LooseQuadTree<T> {
     const TOP_LEFT: usize = 0;
     const TOP_RIGHT: usize = 1;
@@ -55,6 +85,8 @@ impl<T> LooseQuadTree<T> {
         self.nodes[0].reuse(self.seq);
     }
 
+    // Each item is stored in exactly one node (the deepest whose loose bounds
+    // contain it), so `for_each_intersection` yields an item at most once.
     pub fn insert(&mut self, aabb: Aabb, t: T) {
         let mut centre = aabb.centre();
         let mut index = 0;
@@ -83,26 +115,102 @@ impl<T> LooseQuadTree<T> {
                     })
                     .get() as usize
             };
-            if centre.x < max_size.x {
-                if centre.y < max_size.y {
-                    index = child_offset + Self::TOP_LEFT;
-                } else {
-                    index = child_offset + Self::BOTTOM_LEFT;
-                    centre.y = centre.y - max_size.y;
-                }
+            index = Self::child_index(child_offset, &mut centre, max_size);
+            max_size = max_size / 2.
+        }
+    }
+
+    // Picks the child containing `centre`, and makes `centre` relative to it.
+    fn child_index(
+        child_offset: usize,
+        centre: &mut Vector2<f64>,
+        max_size: Vector2<f64>,
+    ) -> usize {
+        if centre.x < max_size.x {
+            if centre.y < max_size.y {
+                child_offset + Self::TOP_LEFT
             } else {
-                if centre.y < max_size.y {
-                    index = child_offset + Self::TOP_RIGHT;
-                    centre.x = centre.x - max_size.x;
-                } else {
-                    index = child_offset + Self::BOTTOM_RIGHT;
-                    centre = centre - max_size;
-                }
+                centre.y = centre.y - max_size.y;
+                child_offset + Self::BOTTOM_LEFT
+            }
+        } else {
+            if centre.y < max_size.y {
+                centre.x = centre.x - max_size.x;
+                child_offset + Self::TOP_RIGHT
+            } else {
+                *centre = *centre - max_size;
+                child_offset + Self::BOTTOM_RIGHT
+            }
+        }
+    }
+
+    // The index of the node an item with the given aabb was inserted into,
+    // following the same path as `insert` without allocating any nodes.
+    fn node_index(&self, aabb: &Aabb) -> Option<usize> {
+        let mut centre = aabb.centre();
+        let mut index = 0;
+        let mut max_size = self.size / 2.;
+        let size = aabb.size();
+        loop {
+            let node = self.nodes.get(index)?;
+            if node.seq != self.seq {
+                return None;
+            }
+            if size.x > max_size.x || size.y > max_size.y {
+                return Some(index);
             }
+            let child_offset = node.child_offset?.get() as usize;
+            index = Self::child_index(child_offset, &mut centre, max_size);
             max_size = max_size / 2.
         }
     }
 
+    fn find(&self, aabb: &Aabb, t: &T) -> Option<(usize, usize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.node_index(aabb)?;
+        let position = self.nodes[index]
+            .items
+            .iter()
+            .position(|&(_, ref other)| other == t)?;
+        Some((index, position))
+    }
+
+    // Like `remove`, but only looks in the node the item was inserted into.
+    pub fn remove_inserted(&mut self, aabb: Aabb, t: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        match self.find(&aabb, t) {
+            Some((index, position)) => {
+                self.nodes[index].items.swap_remove(position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Moves an item that was inserted with `old_aabb`. Items that stay in the
+    // same node are updated in place, so unmoved items cost nothing to
+    // update. Returns false if the item wasn't found under `old_aabb`.
+    pub fn update(&mut self, old_aabb: Aabb, new_aabb: Aabb, t: T) -> bool
+    where
+        T: PartialEq,
+    {
+        let (old_index, position) = match self.find(&old_aabb, &t) {
+            Some(found) => found,
+            None => return false,
+        };
+        if self.node_index(&new_aabb) == Some(old_index) {
+            self.nodes[old_index].items[position].0 = new_aabb;
+        } else {
+            self.nodes[old_index].items.swap_remove(position);
+            self.insert(new_aabb, t);
+        }
+        true
+    }
+
     fn for_each_intersection_rec<F: FnMut(&Aabb, &T)>(
         nodes: &[Node<T>],
         current_index: usize,
@@ -175,8 +283,252 @@ impl<T> LooseQuadTree<T> {
             }
         }
     }
+    fn for_each_node_rec<F: FnMut(Aabb, usize, usize)>(
+        &self,
+        current_index: usize,
+        current_node_aabb: Aabb,
+        depth: usize,
+        f: &mut F,
+    ) {
+        let node = match self.nodes.get(current_index) {
+            Some(node) => node,
+            None => return,
+        };
+        if node.seq != self.seq {
+            return;
+        }
+        f(current_node_aabb.double_about_centre(), depth, node.items.len());
+        if let Some(child_offset) = node.child_offset {
+            let child_offset = child_offset.get() as usize;
+            let AabbSplitFour {
+                top_left,
+                top_right,
+                bottom_left,
+                bottom_right,
+            } = current_node_aabb.split_four();
+            let children = [
+                (Self::TOP_LEFT, top_left),
+                (Self::TOP_RIGHT, top_right),
+                (Self::BOTTOM_LEFT, bottom_left),
+                (Self::BOTTOM_RIGHT, bottom_right),
+            ];
+            for &(offset, aabb) in children.iter() {
+                self.for_each_node_rec(child_offset + offset, aabb, depth + 1, f);
+            }
+        }
+    }
+    // Calls `f` with the loose bounds, depth and number of items of every node
+    // in use, parents before their children. The root has depth 0.
+    pub fn for_each_node<F: FnMut(Aabb, usize, usize)>(&self, mut f: F) {
+        let root_aabb = self.covered_region();
+        self.for_each_node_rec(0, root_aabb, 0, &mut f);
+    }
+    // Removes every item equal to `t`, wherever it was inserted, so the aabb
+    // it was inserted with needn't be known.
+    pub fn remove(&mut self, t: &T)
+    where
+        T: PartialEq,
+    {
+        let seq = self.seq;
+        for node in self.nodes.iter_mut() {
+            if node.seq == seq {
+                node.items.retain(|&(_, ref other)| other != t);
+            }
+        }
+    }
+    pub fn covered_region(&self) -> Aabb {
+        Aabb::new(vec2(0., 0.), self.size)
+    }
     pub fn for_each_intersection<F: FnMut(&Aabb, &T)>(&self, aabb: Aabb, mut f: F) {
-        let root_aabb = Aabb::new(vec2(0., 0.), self.size);
+        let root_aabb = self.covered_region();
         Self::for_each_intersection_rec(&self.nodes, 0, root_aabb, &aabb, &mut f);
     }
+    // The item whose aabb's centre is closest to `point`, with ties going to
+    // the smallest item. Nodes are searched closest first, stopping once the
+    // closest remaining node is further away than the best item so far.
+    pub fn nearest(&self, point: Vector2<f64>) -> Option<T>
+    where
+        T: Clone + Ord,
+    {
+        let root_aabb = self.covered_region();
+        // Items whose centre is outside the tree are stored as if their
+        // centre were on its edge. Measuring node distances from the point
+        // clamped to the tree in the same way never overestimates the
+        // distance to a node's items.
+        let clamped_point = root_aabb.closest_point(point);
+        let mut best: Option<(f64, &T)> = None;
+        let mut to_visit = BinaryHeap::new();
+        to_visit.push(NodeToVisit {
+            distance2: 0.,
+            index: 0,
+            aabb: root_aabb,
+        });
+        while let Some(NodeToVisit {
+            distance2,
+            index,
+            aabb,
+        }) = to_visit.pop()
+        {
+            if let Some((best_distance2, _)) = best {
+                if distance2 > best_distance2 {
+                    break;
+                }
+            }
+            let node = match self.nodes.get(index) {
+                Some(node) if node.seq == self.seq => node,
+                _ => continue,
+            };
+            for &(ref item_aabb, ref t) in node.items.iter() {
+                let item_distance2 = (item_aabb.centre() - point).magnitude2();
+                let is_better = match best {
+                    Some((best_distance2, best_t)) => {
+                        item_distance2 < best_distance2
+                            || (item_distance2 == best_distance2 && t < best_t)
+                    }
+                    None => true,
+                };
+                if is_better {
+                    best = Some((item_distance2, t));
+                }
+            }
+            if let Some(child_offset) = node.child_offset {
+                let child_offset = child_offset.get() as usize;
+                let AabbSplitFour {
+                    top_left,
+                    top_right,
+                    bottom_left,
+                    bottom_right,
+                } = aabb.split_four();
+                let children = [
+                    (Self::TOP_LEFT, top_left),
+                    (Self::TOP_RIGHT, top_right),
+                    (Self::BOTTOM_LEFT, bottom_left),
+                    (Self::BOTTOM_RIGHT, bottom_right),
+                ];
+                for &(offset, child_aabb) in children.iter() {
+                    let closest = child_aabb.closest_point(clamped_point);
+                    to_visit.push(NodeToVisit {
+                        distance2: (closest - clamped_point).magnitude2(),
+                        index: child_offset + offset,
+                        aabb: child_aabb,
+                    });
+                }
+            }
+        }
+        best.map(|(_, t)| t.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rng::Rng;
+
+    #[test]
+    fn nearest_of_three() {
+        let mut tree = LooseQuadTree::new(vec2(256., 256.));
+        assert_eq!(tree.nearest(vec2(1., 1.)), None);
+        tree.insert(Aabb::new(vec2(10., 10.), vec2(10., 10.)), 1);
+        tree.insert(Aabb::new(vec2(200., 20.), vec2(4., 4.)), 2);
+        tree.insert(Aabb::new(vec2(100., 200.), vec2(100., 40.)), 3);
+        assert_eq!(tree.nearest(vec2(0., 0.)), Some(1));
+        assert_eq!(tree.nearest(vec2(250., 0.)), Some(2));
+        assert_eq!(tree.nearest(vec2(150., 250.)), Some(3));
+        assert_eq!(tree.nearest(vec2(-500., -500.)), Some(1));
+        assert_eq!(tree.nearest(vec2(1000., 1000.)), Some(3));
+        tree.clear();
+        assert_eq!(tree.nearest(vec2(1., 1.)), None);
+    }
+
+    #[test]
+    fn nearest_breaks_ties_by_value() {
+        let mut tree = LooseQuadTree::new(vec2(256., 256.));
+        tree.insert(Aabb::new(vec2(10., 10.), vec2(2., 2.)), 5);
+        tree.insert(Aabb::new(vec2(30., 10.), vec2(2., 2.)), 4);
+        assert_eq!(tree.nearest(vec2(21., 11.)), Some(4));
+    }
+
+    #[test]
+    fn nearest_matches_a_linear_scan() {
+        let mut rng = Rng::new(7);
+        for _ in 0..20 {
+            let mut tree = LooseQuadTree::new(vec2(512., 512.));
+            let mut items = Vec::new();
+            for i in 0..60 {
+                let top_left = vec2(rng.range(-100., 600.), rng.range(-100., 600.));
+                let size = vec2(rng.range(1., 200.), rng.range(1., 200.));
+                let aabb = Aabb::new(top_left, size);
+                tree.insert(aabb, i);
+                items.push((aabb, i));
+            }
+            for _ in 0..50 {
+                let point = vec2(rng.range(-300., 800.), rng.range(-300., 800.));
+                let distance = |aabb: &Aabb| (aabb.centre() - point).magnitude2();
+                let expected = items
+                    .iter()
+                    .min_by(|a, b| {
+                        distance(&a.0)
+                            .partial_cmp(&distance(&b.0))
+                            .unwrap()
+                            .then(a.1.cmp(&b.1))
+                    })
+                    .map(|&(_, i)| i);
+                assert_eq!(tree.nearest(point), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn straddling_item_is_yielded_once() {
+        let mut tree = LooseQuadTree::new(vec2(256., 256.));
+        // Centred where the four quadrants meet.
+        tree.insert(Aabb::new(vec2(118., 118.), vec2(20., 20.)), 0);
+        tree.insert(Aabb::new(vec2(10., 10.), vec2(4., 4.)), 1);
+        tree.insert(Aabb::new(vec2(240., 240.), vec2(4., 4.)), 2);
+        let mut yielded = Vec::new();
+        tree.for_each_intersection(Aabb::new(vec2(100., 100.), vec2(56., 56.)), |_, &t| {
+            yielded.push(t)
+        });
+        assert_eq!(yielded, vec![0]);
+    }
+
+    #[test]
+    fn covered_region_matches_size_hint() {
+        let mut tree = LooseQuadTree::new(vec2(960., 640.));
+        assert_eq!(
+            tree.covered_region(),
+            Aabb::new(vec2(0., 0.), vec2(960., 640.))
+        );
+        tree.insert(Aabb::new(vec2(2000., 2000.), vec2(10., 10.)), 0);
+        tree.clear();
+        assert_eq!(tree.covered_region().size(), vec2(960., 640.));
+    }
+
+    #[test]
+    fn items_in_far_corners_are_in_separate_leaves() {
+        let mut tree = LooseQuadTree::new(vec2(256., 256.));
+        let top_left = Aabb::new(vec2(2., 2.), vec2(4., 4.));
+        let bottom_right = Aabb::new(vec2(250., 250.), vec2(4., 4.));
+        tree.insert(top_left, 0);
+        tree.insert(bottom_right, 1);
+        let mut nodes = Vec::new();
+        tree.for_each_node(|aabb, depth, count| nodes.push((aabb, depth, count)));
+        assert_eq!(nodes[0].1, 0);
+        // Nodes are visited parents first, so a node is a leaf unless the next
+        // one is deeper.
+        let leaves = (0..nodes.len())
+            .filter(|&i| nodes.get(i + 1).map_or(true, |next| next.1 <= nodes[i].1))
+            .map(|i| nodes[i])
+            .collect::<Vec<_>>();
+        assert!(leaves.len() >= 2);
+        assert_eq!(leaves.iter().filter(|&&(_, _, count)| count == 1).count(), 2);
+        let holding = |item: &Aabb| {
+            leaves
+                .iter()
+                .filter(|&&(aabb, _, count)| count > 0 && aabb.is_intersecting(item))
+                .count()
+        };
+        assert_eq!(holding(&top_left), 1);
+        assert_eq!(holding(&bottom_right), 1);
+    }
 }
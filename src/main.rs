@@ -1,44 +1,68 @@
-#![feature(nonzero)]
-
-extern crate best;
 extern crate cgmath;
-extern crate fnv;
 #[macro_use]
 extern crate gfx;
 extern crate gfx_device_gl;
 extern crate gfx_window_glutin;
+extern crate gilrs;
 extern crate glutin;
+extern crate simple_physics;
 
-mod aabb;
-mod axis_aligned_rect;
-mod bump;
-mod collide;
-mod game;
 mod glutin_window;
 mod graphics;
-mod left_solid_edge;
-mod line_segment;
-mod loose_quad_tree;
-mod movement;
-mod shape;
+mod input;
 
-use cgmath::vec2;
-use game::{GameState, GameStateChanges, InputModel};
+use cgmath::{vec2, Vector2};
 use gfx::Device;
 use glutin::GlContext;
 use glutin_window::GlutinWindow;
-use graphics::Renderer;
-use movement::MovementContext;
-use shape::Shape;
+use graphics::{Camera, FrameUpdater, Renderer};
+use input::{Controls, Gamepad};
+use simple_physics::game::{GameState, GameStateChanges, InputModel, FIXED_DT};
+use simple_physics::key_bindings::{Action, KeyBindings};
+use simple_physics::movement::MovementContext;
+use simple_physics::shape::Shape;
+use std::time::Instant;
+
+const VELOCITY_DEBUG_SCALE: f64 = 8.;
+const AABB_DEBUG_COLOUR: [f32; 3] = [0., 1., 0.];
+const CONTACT_DEBUG_LENGTH: f64 = 12.;
+const QUAD_TREE_DEBUG_COLOURS: [[f32; 3]; 4] = [
+    [1., 0.2, 0.2],
+    [1., 0.6, 0.],
+    [1., 1., 0.2],
+    [0.2, 0.6, 1.],
+];
+const CAMERA_SMOOTHING: f64 = 0.1;
+const CAMERA_DEAD_ZONE: [f64; 2] = [64., 96.];
+// Caps the time simulated after a stall, so a slow frame doesn't cause a
+// cascade of ever longer catch-up frames.
+const MAX_FRAME_TIME: f64 = 0.25;
 
 enum ExternalEvent {
     Quit,
     Reset,
+    ToggleDebug,
+    ToggleQuadTreeDebug,
+    TogglePause,
+    Step,
+}
+
+fn polygon_outline(
+    updater: &mut FrameUpdater,
+    vertices: &[Vector2<f64>],
+    colour: [f32; 3],
+) {
+    for (i, vertex) in vertices.iter().enumerate() {
+        let next = vertices[(i + 1) % vertices.len()];
+        updater.line_segment(vertex.cast().unwrap(), next.cast().unwrap(), colour);
+    }
 }
 
 fn process_input(
     events_loop: &mut glutin::EventsLoop,
-    input_model: &mut InputModel,
+    key_bindings: &KeyBindings<glutin::VirtualKeyCode>,
+    keyboard: &mut Controls,
+    jump_pressed: &mut bool,
 ) -> Option<ExternalEvent> {
     let mut external_event = None;
 
@@ -49,25 +73,38 @@ fn process_input(
             }
             glutin::WindowEvent::KeyboardInput { input, .. } => {
                 if let Some(virtual_keycode) = input.virtual_keycode {
-                    match input.state {
-                        glutin::ElementState::Pressed => match virtual_keycode {
-                            glutin::VirtualKeyCode::Return => {
-                                external_event = Some(ExternalEvent::Reset)
+                    let pressed = match input.state {
+                        glutin::ElementState::Pressed => true,
+                        glutin::ElementState::Released => false,
+                    };
+                    match virtual_keycode {
+                        glutin::VirtualKeyCode::F1 if pressed => {
+                            external_event = Some(ExternalEvent::ToggleDebug)
+                        }
+                        glutin::VirtualKeyCode::F2 if pressed => {
+                            external_event = Some(ExternalEvent::ToggleQuadTreeDebug)
+                        }
+                        _ => if let Some(action) = key_bindings.action(&virtual_keycode) {
+                            let value = if pressed { 1. } else { 0. };
+                            match action {
+                                Action::MoveLeft => keyboard.left = value,
+                                Action::MoveRight => keyboard.right = value,
+                                Action::MoveUp => keyboard.up = value,
+                                Action::MoveDown => keyboard.down = value,
+                                Action::Jump => {
+                                    keyboard.jump = pressed;
+                                    *jump_pressed |= pressed;
+                                }
+                                Action::Reset => if pressed {
+                                    external_event = Some(ExternalEvent::Reset)
+                                },
+                                Action::Pause => if pressed {
+                                    external_event = Some(ExternalEvent::TogglePause)
+                                },
+                                Action::Step => if pressed {
+                                    external_event = Some(ExternalEvent::Step)
+                                },
                             }
-                            glutin::VirtualKeyCode::Left => input_model.set_left(1.),
-                            glutin::VirtualKeyCode::Right => input_model.set_right(1.),
-                            glutin::VirtualKeyCode::Up => input_model.set_up(1.),
-                            glutin::VirtualKeyCode::Down => input_model.set_down(1.),
-                            glutin::VirtualKeyCode::Space => input_model.set_jump(true),
-                            _ => (),
-                        },
-                        glutin::ElementState::Released => match virtual_keycode {
-                            glutin::VirtualKeyCode::Left => input_model.set_left(0.),
-                            glutin::VirtualKeyCode::Right => input_model.set_right(0.),
-                            glutin::VirtualKeyCode::Up => input_model.set_up(0.),
-                            glutin::VirtualKeyCode::Down => input_model.set_down(0.),
-                            glutin::VirtualKeyCode::Space => input_model.set_jump(false),
-                            _ => (),
                         },
                     }
                 }
@@ -96,38 +133,179 @@ fn main() {
     let mut renderer =
         Renderer::new(render_target_view.clone(), &mut factory, &mut encoder);
 
-    let mut game_state = GameState::new(vec2(width as f64, height as f64));
+    let mut game_state: GameState = GameState::new(vec2(width as f64, height as f64));
     let mut game_changes = GameStateChanges::default();
     game_state.init_demo();
 
     let mut input_model = InputModel::default();
+    let key_bindings = input::default_key_bindings();
+    let mut keyboard = Controls::default();
+    let mut gamepad = Gamepad::new(input::DEFAULT_DEAD_ZONE);
     let mut movement_context = MovementContext::default();
+    let mut debug = false;
+    let mut quad_tree_debug = false;
+    let mut camera = Camera::new(vec2(width as f64, height as f64));
+    camera.set_smoothing(CAMERA_SMOOTHING);
+    camera.set_dead_zone(CAMERA_DEAD_ZONE.into());
+    let mut last_frame = Instant::now();
+    let mut accumulator = 0.;
+    let mut paused = false;
+    // A jump pressed since the last step, such as while paused, is held over
+    // to the next step so that releasing it first doesn't lose it.
+    let mut pending_jump = false;
 
     loop {
         encoder.clear(&render_target_view, [0.0, 0.0, 0.0, 1.0]);
-        match process_input(&mut events_loop, &mut input_model) {
+        let mut single_step = false;
+        match process_input(
+            &mut events_loop,
+            &key_bindings,
+            &mut keyboard,
+            &mut pending_jump,
+        ) {
             Some(ExternalEvent::Quit) => break,
-            Some(ExternalEvent::Reset) => game_state.init_demo(),
+            Some(ExternalEvent::Reset) => {
+                game_state.reset().expect("Failed to reload level");
+                input_model = InputModel::default();
+                pending_jump = false;
+                game_changes = GameStateChanges::default();
+                if let Some(centre) = game_state
+                    .player_id()
+                    .and_then(|id| game_state.entity_centre(id))
+                {
+                    camera.set_centre(centre);
+                }
+            }
+            Some(ExternalEvent::ToggleDebug) => {
+                debug = !debug;
+                movement_context.set_record_debug_contacts(debug);
+            }
+            Some(ExternalEvent::ToggleQuadTreeDebug) => {
+                quad_tree_debug = !quad_tree_debug
+            }
+            Some(ExternalEvent::TogglePause) => paused = !paused,
+            Some(ExternalEvent::Step) => single_step = paused,
             None => (),
         }
-        input_model.after_process();
+        gamepad.poll();
+        let controls = keyboard.combine(&gamepad.controls());
+        controls.apply(&mut input_model);
+        pending_jump |= controls.jump;
 
-        game_state.update(&input_model, &mut game_changes, &mut movement_context);
+        let now = Instant::now();
+        let elapsed = now - last_frame;
+        last_frame = now;
+        let mut num_steps = 0;
+        if paused {
+            if single_step {
+                num_steps = 1;
+            }
+        } else {
+            accumulator += (elapsed.as_secs() as f64
+                + elapsed.subsec_nanos() as f64 * 1e-9)
+                .min(MAX_FRAME_TIME);
+            while accumulator >= FIXED_DT {
+                accumulator -= FIXED_DT;
+                num_steps += 1;
+            }
+        }
+        for _ in 0..num_steps {
+            input_model.set_jump(controls.jump || pending_jump);
+            pending_jump = false;
+            input_model.after_process();
+            game_state.update(
+                &input_model,
+                &mut game_changes,
+                &mut movement_context,
+                FIXED_DT,
+            );
+        }
+        if let Some(centre) = game_state
+            .player_id()
+            .and_then(|id| game_state.entity_centre(id))
+        {
+            camera.follow(centre);
+        }
         {
             let mut frame = renderer.prepare_frame(&mut factory);
-            let mut updater = frame.updater();
-            for update in game_state.render_updates() {
+            let mut updater = frame.camera_updater(&camera);
+            // Paused frames show exactly the state after the last step.
+            let alpha = if paused { 1. } else { accumulator / FIXED_DT };
+            for update in game_state.render_updates_interpolated(alpha) {
                 match update.shape {
-                    &Shape::AxisAlignedRect(ref rect) => updater.axis_aligned_rect(
-                        update.position.cast().unwrap(),
-                        rect.dimensions().cast().unwrap(),
-                        update.colour,
-                    ),
+                    &Shape::AxisAlignedRect(ref rect) => match update.sprite {
+                        Some(sprite) => updater.textured_rect(
+                            sprite.texture_id,
+                            update.position.cast().unwrap(),
+                            rect.dimensions().cast().unwrap(),
+                            sprite.uv_top_left,
+                            sprite.uv_size,
+                            update.colour,
+                        ),
+                        None => updater.axis_aligned_rect(
+                            update.position.cast().unwrap(),
+                            rect.dimensions().cast().unwrap(),
+                            update.colour,
+                        ),
+                    },
                     &Shape::LineSegment(ref line_segment) => updater.line_segment(
                         (line_segment.start + update.position).cast().unwrap(),
                         (line_segment.end + update.position).cast().unwrap(),
                         update.colour,
                     ),
+                    &Shape::Circle(ref circle) => updater.circle(
+                        circle.centre(update.position).cast().unwrap(),
+                        circle.radius() as f32,
+                        update.colour,
+                    ),
+                    &Shape::ConvexPolygon(ref polygon) => polygon_outline(
+                        &mut updater,
+                        &polygon.vertices_at(update.position),
+                        update.colour,
+                    ),
+                    &Shape::Capsule(ref capsule) => polygon_outline(
+                        &mut updater,
+                        &capsule.vertices_at(update.position),
+                        update.colour,
+                    ),
+                }
+            }
+            if quad_tree_debug {
+                game_state.for_each_quad_tree_node(|aabb, depth, _count| {
+                    updater.axis_aligned_rect_wireframe(
+                        aabb.top_left().cast().unwrap(),
+                        aabb.size().cast().unwrap(),
+                        QUAD_TREE_DEBUG_COLOURS[depth % QUAD_TREE_DEBUG_COLOURS.len()],
+                    );
+                });
+            }
+            if debug {
+                for aabb in game_state.debug_aabbs() {
+                    updater.axis_aligned_rect_wireframe(
+                        aabb.top_left().cast().unwrap(),
+                        aabb.size().cast().unwrap(),
+                        AABB_DEBUG_COLOUR,
+                    );
+                }
+                for (start, end) in
+                    game_state.velocity_debug_segments(VELOCITY_DEBUG_SCALE)
+                {
+                    updater.line_segment_gradient(
+                        start.cast().unwrap(),
+                        end.cast().unwrap(),
+                        [1., 1., 1.],
+                        [1., 0., 1.],
+                    );
+                }
+                for contact in movement_context.drain_debug_contacts() {
+                    updater.line_segment_gradient(
+                        contact.point.cast().unwrap(),
+                        (contact.point + contact.normal * CONTACT_DEBUG_LENGTH)
+                            .cast()
+                            .unwrap(),
+                        [1., 1., 0.],
+                        [0., 1., 1.],
+                    );
                 }
             }
         }
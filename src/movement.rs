@@ -1,16 +1,128 @@
 use aabb::Aabb;
+use axis_aligned_rect::AxisAlignedRect;
 use best::BestMultiSet;
 use bump::max_bump;
 use cgmath::{vec2, InnerSpace, Vector2};
-use collide::Collision;
-use shape::ShapePosition;
+use collide::{channels, Channels, Collision};
+use left_solid_edge::EPSILON;
+use raycast::RayHit;
+use shape::{Shape, ShapePosition};
 use std::cmp::Ordering;
+use std::f64::consts::FRAC_PI_4;
 
 const BELOW_TEST_MOVEMENT: Vector2<f64> = Vector2 { x: 0., y: 1. };
+const CORNER_EPSILON: f64 = 0.001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Surface {
+    Floor,
+    Wall,
+    Ceiling,
+}
+
+impl Surface {
+    pub fn classify(normal: Vector2<f64>, up: Vector2<f64>, max_slope: f64) -> Self {
+        let alignment = normal.dot(up.normalize());
+        let threshold = max_slope.cos();
+        if alignment >= threshold {
+            Surface::Floor
+        } else if alignment <= -threshold {
+            Surface::Ceiling
+        } else {
+            Surface::Wall
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CollisionEvent {
+    pub moving_entity_id: EntityId,
+    pub stationary_entity_id: EntityId,
+    pub normal: Vector2<f64>,
+    pub surface: Surface,
+    pub impulse: f64,
+    pub contact: (Vector2<f64>, Vector2<f64>),
+    // How far through the frame the contact happened, from 0 to 1.
+    pub time_of_impact: f64,
+}
+
+impl CollisionEvent {
+    fn new(
+        collision: &Collision,
+        config: &MovementConfig,
+        impulse: f64,
+        time_of_impact: f64,
+    ) -> Self {
+        let normal = collision.normal();
+        Self {
+            moving_entity_id: collision.moving_entity_id,
+            stationary_entity_id: collision.stationary_entity_id,
+            normal,
+            surface: Surface::classify(normal, config.up, config.max_slope),
+            impulse,
+            contact: collision.contact,
+            time_of_impact,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MovementConfig {
+    // Slides and bumps each have their own budget, so a movement that bumps
+    // up many small steps still has every slide it needs.
+    pub max_slide_iterations: u8,
+    pub max_bump_iterations: u8,
+    pub up: Vector2<f64>,
+    pub max_slope: f64,
+    pub enabled_channels: Channels,
+    pub preserve_corner_momentum: bool,
+    pub min_bounce_speed: f64,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        Self {
+            max_slide_iterations: 16,
+            max_bump_iterations: 16,
+            up: vec2(0., -1.),
+            max_slope: FRAC_PI_4,
+            enabled_channels: channels::ALL,
+            preserve_corner_momentum: false,
+            min_bounce_speed: 1.,
+        }
+    }
+}
+
+// The first shape hit by `MovementContext::sweep_aabb`, and how much of the
+// movement was made before hitting it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepHit {
+    pub entity_id: EntityId,
+    pub fraction: f64,
+    pub normal: Vector2<f64>,
+}
+
+// Where a moving shape hit an edge, for debug drawing.
+#[derive(Debug, Clone, Copy)]
+pub struct DebugContact {
+    pub point: Vector2<f64>,
+    pub normal: Vector2<f64>,
+}
 
 #[derive(Default)]
 pub struct MovementContext {
     closest_collisions: BestMultiSet<Collision>,
+    collision_events: Vec<CollisionEvent>,
+    config: MovementConfig,
+    candidate_count: u64,
+    record_debug_contacts: bool,
+    debug_contacts: Vec<DebugContact>,
 }
 
 pub type ClosestCollisions<'a> = &'a BestMultiSet<Collision>;
@@ -21,6 +133,36 @@ impl<'a> CollisionsBelow<'a> {
     pub fn can_jump(&self) -> bool {
         !self.0.is_empty()
     }
+    pub fn entity_ids<'b>(&'b self) -> impl Iterator<Item = EntityId> + 'b {
+        self.0
+            .iter()
+            .map(|collision| collision.stationary_entity_id)
+    }
+    // True when standing only on edges that are solid on the floor channel
+    // alone, such as the tops of floor-only platforms.
+    pub fn is_floor_only(&self) -> bool {
+        !self.0.is_empty() && self.0.iter().all(|collision| {
+            collision.stationary_edge_vector.channels & !channels::FLOOR == 0
+        })
+    }
+    fn fastest<F>(&self, get_velocity: F) -> Option<(EntityId, Vector2<f64>)>
+    where
+        F: Fn(EntityId) -> Option<Vector2<f64>>,
+    {
+        self.0
+            .iter()
+            .filter_map(|collision| {
+                let id = collision.stationary_entity_id;
+                get_velocity(id).map(|velocity| (id, velocity))
+            })
+            .filter(|&(_, velocity)| !velocity.x.is_nan() && !velocity.y.is_nan())
+            .max_by(|&(a_id, a), &(b_id, b)| {
+                a.magnitude2()
+                    .partial_cmp(&b.magnitude2())
+                    .unwrap_or(Ordering::Equal)
+                    .then(b_id.cmp(&a_id))
+            })
+    }
     pub fn max_velocity(
         &self,
         get_velocity: impl Fn(EntityId) -> Option<Vector2<f64>>,
@@ -29,25 +171,85 @@ impl<'a> CollisionsBelow<'a> {
             return None;
         }
         Some(
-            self.0
-                .iter()
-                .filter_map(|collision| get_velocity(collision.stationary_entity_id))
-                .max_by(|a, b| {
-                    a.magnitude2()
-                        .partial_cmp(&b.magnitude2())
-                        .unwrap_or(Ordering::Equal)
-                })
+            self.fastest(get_velocity)
+                .map(|(_, velocity)| velocity)
                 .unwrap_or(vec2(0., 0.)),
         )
     }
+    // The entity below moving fastest, out of those with a velocity.
+    pub fn fastest_entity(
+        &self,
+        get_velocity: impl Fn(EntityId) -> Option<Vector2<f64>>,
+    ) -> Option<EntityId> {
+        self.fastest(get_velocity).map(|(id, _)| id)
+    }
+    pub fn max_friction(&self, get_friction: impl Fn(EntityId) -> f64) -> Option<f64> {
+        self.0
+            .iter()
+            .map(|collision| get_friction(collision.stationary_entity_id))
+            .fold(None, |max, friction| match max {
+                Some(max) if max >= friction => Some(max),
+                _ => Some(friction),
+            })
+    }
 }
 
 pub type EntityId = u32;
 
 pub trait ForEachShapePosition {
     fn for_each<F: FnMut(ShapePosition)>(&self, aabb: Aabb, f: F);
+    // Movement ignores any shape for which this returns false, regardless of
+    // the channels of its edges.
+    fn is_collision_enabled(&self, _moving: EntityId, _stationary: EntityId) -> bool {
+        true
+    }
+}
+
+struct Obstacles<'a, 'b: 'a>(&'a [ShapePosition<'b>]);
+
+impl<'a, 'b> ForEachShapePosition for Obstacles<'a, 'b> {
+    fn for_each<F: FnMut(ShapePosition)>(&self, aabb: Aabb, mut f: F) {
+        for obstacle in self.0.iter() {
+            if obstacle.shape.aabb(obstacle.position).is_intersecting(&aabb) {
+                f(ShapePosition {
+                    entity_id: obstacle.entity_id,
+                    position: obstacle.position,
+                    shape: obstacle.shape,
+                });
+            }
+        }
+    }
+}
+
+// The id given to the moving shape in `resolve_movement`. Obstacles with this
+// id are ignored.
+pub const RESOLVE_MOVEMENT_ENTITY_ID: EntityId = ::std::u32::MAX;
+
+// The id given to the aabb in `MovementContext::sweep_aabb`. Obstacles with
+// this id are ignored.
+pub const SWEEP_ENTITY_ID: EntityId = ::std::u32::MAX;
+
+// Moves a shape against a fixed list of obstacles without a game state or
+// broadphase, sliding and bumping exactly as entities do in the game.
+pub fn resolve_movement(
+    shape: &Shape,
+    position: Vector2<f64>,
+    movement: Vector2<f64>,
+    obstacles: &[ShapePosition],
+) -> Movement {
+    let shape_position = ShapePosition {
+        entity_id: RESOLVE_MOVEMENT_ENTITY_ID,
+        position,
+        shape,
+    };
+    MovementContext::default().position_after_allowed_movement(
+        shape_position,
+        movement,
+        &Obstacles(obstacles),
+    )
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct Movement {
     pub position: Vector2<f64>,
     pub velocity: Vector2<f64>,
@@ -61,6 +263,10 @@ pub struct Displacement {
 impl Displacement {
     pub fn combine_velocity(&self, current_velocity: Vector2<f64>) -> Vector2<f64> {
         let lateral_direction = vec2(self.velocity.y, -self.velocity.x);
+        // A pusher that isn't moving has no lateral direction to project onto.
+        if lateral_direction.magnitude2() < EPSILON {
+            return current_velocity;
+        }
         let lateral_component = current_velocity.project_on(lateral_direction);
         self.velocity + lateral_component
     }
@@ -82,13 +288,28 @@ impl MovementContext {
             shape_position.movement_aabb(movement),
             |other_shape_position: ShapePosition| {
                 let other_entity_id = other_shape_position.entity_id;
-                if other_entity_id != shape_position.entity_id {
+                if other_entity_id != shape_position.entity_id
+                    && for_each_shape_position
+                        .is_collision_enabled(shape_position.entity_id, other_entity_id)
+                {
+                    self.candidate_count += 1;
                     shape_position.movement_collision_test(
                         other_shape_position,
                         movement,
+                        self.config.enabled_channels,
                         &mut self.closest_collisions,
                     );
                     if let Some(collision) = self.closest_collisions.drain().next() {
+                        let impulse = collision
+                            .left_solid_edge_collision
+                            .displacement_full_movement(movement)
+                            .magnitude();
+                        self.collision_events.push(CollisionEvent::new(
+                            &collision,
+                            &self.config,
+                            impulse,
+                            collision.left_solid_edge_collision.movement_multiplier(),
+                        ));
                         f(other_entity_id, collision);
                     }
                 }
@@ -104,21 +325,52 @@ impl MovementContext {
     where
         F: ForEachShapePosition,
     {
-        self.closest_collisions.clear();
-        for_each_shape_position.for_each(
-            shape_position.movement_aabb(movement),
-            |other_shape_position: ShapePosition| {
-                if other_shape_position.entity_id != shape_position.entity_id {
-                    shape_position.movement_collision_test(
-                        other_shape_position,
-                        movement,
-                        &mut self.closest_collisions,
-                    );
-                }
-            },
-        );
-
-        &self.closest_collisions
+        find_closest_collisions(
+            &mut self.closest_collisions,
+            &mut self.candidate_count,
+            shape_position,
+            movement,
+            self.config.enabled_channels,
+            for_each_shape_position,
+        )
+    }
+    pub fn config(&self) -> &MovementConfig {
+        &self.config
+    }
+    pub fn config_mut(&mut self) -> &mut MovementConfig {
+        &mut self.config
+    }
+    pub fn collision_events(&self) -> &[CollisionEvent] {
+        &self.collision_events
+    }
+    pub fn clear_collision_events(&mut self) {
+        self.collision_events.clear();
+    }
+    // Events are recorded with a time of impact relative to the movement that
+    // produced them. For a movement covering only part of the frame, this
+    // maps the events recorded since `first` onto the whole frame, given
+    // where the movement started and how long it took as fractions of it.
+    pub fn rescale_collision_events(&mut self, first: usize, start: f64, length: f64) {
+        for event in self.collision_events[first..].iter_mut() {
+            event.time_of_impact = start + event.time_of_impact * length;
+        }
+    }
+    pub fn last_frame_candidate_count(&self) -> u64 {
+        self.candidate_count
+    }
+    pub fn clear_candidate_count(&mut self) {
+        self.candidate_count = 0;
+    }
+    // While enabled, every collision resolved by `position_after_allowed_movement`
+    // is recorded until it's drained with `drain_debug_contacts`.
+    pub fn set_record_debug_contacts(&mut self, record: bool) {
+        self.record_debug_contacts = record;
+        if !record {
+            self.debug_contacts.clear();
+        }
+    }
+    pub fn drain_debug_contacts(&mut self) -> ::std::vec::Drain<DebugContact> {
+        self.debug_contacts.drain(..)
     }
 
     pub fn collisions_below<F>(
@@ -129,12 +381,165 @@ impl MovementContext {
     where
         F: ForEachShapePosition,
     {
-        CollisionsBelow(self.closest_collisions(
+        self.collisions_towards(
             shape_position,
             BELOW_TEST_MOVEMENT,
             for_each_shape_position,
+        )
+    }
+    pub fn collisions_towards<F>(
+        &mut self,
+        shape_position: ShapePosition,
+        down: Vector2<f64>,
+        for_each_shape_position: &F,
+    ) -> CollisionsBelow
+    where
+        F: ForEachShapePosition,
+    {
+        CollisionsBelow(self.closest_collisions(
+            shape_position,
+            down.normalize() * BELOW_TEST_MOVEMENT.magnitude(),
+            for_each_shape_position,
         ))
     }
+    // Which side, relative to `up`, has a wall within the same distance that
+    // `collisions_below` checks for the ground. Sloped surfaces don't count.
+    pub fn collisions_beside<F>(
+        &mut self,
+        shape_position: ShapePosition,
+        up: Vector2<f64>,
+        for_each_shape_position: &F,
+    ) -> Option<Side>
+    where
+        F: ForEachShapePosition,
+    {
+        let right = vec2(-up.y, up.x);
+        let max_slope = self.config.max_slope;
+        for &(side, direction) in [(Side::Left, -right), (Side::Right, right)].iter() {
+            let collisions = self.collisions_towards(
+                ShapePosition { ..shape_position },
+                direction,
+                for_each_shape_position,
+            );
+            let touching_wall = collisions.0.iter().any(|collision| {
+                Surface::classify(collision.normal(), up, max_slope) == Surface::Wall
+            });
+            if touching_wall {
+                return Some(side);
+            }
+        }
+        None
+    }
+    pub fn shape_cast<F>(
+        &mut self,
+        shape_position: ShapePosition,
+        movement: Vector2<f64>,
+        for_each_shape_position: &F,
+    ) -> Option<Vector2<f64>>
+    where
+        F: ForEachShapePosition,
+    {
+        self.closest_collisions(shape_position, movement, for_each_shape_position)
+            .first()
+            .map(|closest| {
+                closest
+                    .left_solid_edge_collision
+                    .movement_to_collision(movement)
+            })
+    }
+    // Like `shape_cast`, but for an aabb that isn't an entity, so no shape is
+    // skipped for being the one that's moving.
+    pub fn sweep_aabb<F>(
+        &mut self,
+        aabb: Aabb,
+        movement: Vector2<f64>,
+        for_each_shape_position: &F,
+    ) -> Option<SweepHit>
+    where
+        F: ForEachShapePosition,
+    {
+        let shape = Shape::AxisAlignedRect(AxisAlignedRect::new(aabb.size()));
+        let shape_position = ShapePosition {
+            entity_id: SWEEP_ENTITY_ID,
+            position: aabb.top_left(),
+            shape: &shape,
+        };
+        self.closest_collisions(shape_position, movement, for_each_shape_position)
+            .first()
+            .map(|closest| SweepHit {
+                entity_id: closest.stationary_entity_id,
+                fraction: closest.left_solid_edge_collision.movement_multiplier(),
+                normal: closest.normal(),
+            })
+    }
+    // Ids of the sensors whose shapes intersect the aabb of `shape_position`,
+    // in ascending order.
+    pub fn overlapping_sensors<F>(
+        &self,
+        shape_position: ShapePosition,
+        for_each_shape_position: &F,
+    ) -> Vec<EntityId>
+    where
+        F: ForEachShapePosition,
+    {
+        let aabb = shape_position.shape.aabb(shape_position.position);
+        let mut sensor_ids = Vec::new();
+        for_each_shape_position.for_each(aabb, |other| {
+            if other.entity_id != shape_position.entity_id && other.shape.is_sensor()
+                && other.shape.is_intersecting_aabb(other.position, &aabb)
+            {
+                sensor_ids.push(other.entity_id);
+            }
+        });
+        sensor_ids.sort();
+        sensor_ids.dedup();
+        sensor_ids
+    }
+    // Returns the nearest shape hit by the ray. A ray starting inside a shape
+    // hits it at distance 0, with the normal pointing back along the ray.
+    pub fn raycast<F>(
+        &self,
+        origin: Vector2<f64>,
+        direction: Vector2<f64>,
+        max_distance: f64,
+        for_each_shape_position: &F,
+    ) -> Option<RayHit>
+    where
+        F: ForEachShapePosition,
+    {
+        if direction.magnitude2() == 0. {
+            return None;
+        }
+        let direction = direction.normalize();
+        let end = origin + direction * max_distance;
+        let aabb = Aabb::from_union(
+            &Aabb::new(origin, vec2(0., 0.)),
+            &Aabb::new(end, vec2(0., 0.)),
+        );
+        let mut closest: Option<RayHit> = None;
+        for_each_shape_position.for_each(aabb, |shape_position| {
+            let hit = shape_position.shape.raycast(
+                shape_position.position,
+                origin,
+                direction,
+                max_distance,
+            );
+            if let Some((distance, normal)) = hit {
+                let is_closer = closest
+                    .map(|closest| distance < closest.distance)
+                    .unwrap_or(true);
+                if is_closer {
+                    closest = Some(RayHit {
+                        entity_id: shape_position.entity_id,
+                        distance,
+                        point: origin + direction * distance,
+                        normal,
+                    });
+                }
+            }
+        });
+        closest
+    }
     pub fn position_after_allowed_movement<F>(
         &mut self,
         shape_position: ShapePosition,
@@ -144,7 +549,32 @@ impl MovementContext {
     where
         F: ForEachShapePosition,
     {
-        let mut state = MovementStateMachine::new(movement, shape_position.position);
+        self.position_after_allowed_movement_with_restitution(
+            shape_position,
+            movement,
+            0.,
+            for_each_shape_position,
+        )
+    }
+    // With a restitution above 0, the part of the movement and velocity along
+    // the normal of each surface hit is reflected and scaled rather than
+    // removed. A restitution of 0 slides along surfaces.
+    pub fn position_after_allowed_movement_with_restitution<F>(
+        &mut self,
+        shape_position: ShapePosition,
+        movement: Vector2<f64>,
+        restitution: f64,
+        for_each_shape_position: &F,
+    ) -> Movement
+    where
+        F: ForEachShapePosition,
+    {
+        let mut state = MovementStateMachine::new(
+            movement,
+            shape_position.position,
+            restitution,
+            &self.config,
+        );
         let env = MovementEnv {
             for_each_shape_position,
             original: shape_position,
@@ -155,6 +585,21 @@ impl MovementContext {
             }
         }
     }
+    pub fn collisions_along_movement<F>(
+        &mut self,
+        shape_position: ShapePosition,
+        movement: Vector2<f64>,
+        for_each_shape_position: &F,
+    ) where
+        F: ForEachShapePosition,
+    {
+        self.for_each_collision(
+            shape_position,
+            movement,
+            for_each_shape_position,
+            |_, _| (),
+        );
+    }
     pub fn displacement_after_movement<F>(
         &mut self,
         shape_position: ShapePosition,
@@ -185,6 +630,40 @@ impl MovementContext {
     }
 }
 
+fn find_closest_collisions<'a, F>(
+    closest_collisions: &'a mut BestMultiSet<Collision>,
+    candidate_count: &mut u64,
+    shape_position: ShapePosition,
+    movement: Vector2<f64>,
+    enabled_channels: Channels,
+    for_each_shape_position: &F,
+) -> ClosestCollisions<'a>
+where
+    F: ForEachShapePosition,
+{
+    closest_collisions.clear();
+    for_each_shape_position.for_each(
+        shape_position.movement_aabb(movement),
+        |other_shape_position: ShapePosition| {
+            if other_shape_position.entity_id != shape_position.entity_id
+                && for_each_shape_position.is_collision_enabled(
+                    shape_position.entity_id,
+                    other_shape_position.entity_id,
+                ) {
+                *candidate_count += 1;
+                shape_position.movement_collision_test(
+                    other_shape_position,
+                    movement,
+                    enabled_channels,
+                    closest_collisions,
+                );
+            }
+        },
+    );
+
+    closest_collisions
+}
+
 struct MovementEnv<'a, F: 'a + ForEachShapePosition> {
     for_each_shape_position: &'a F,
     original: ShapePosition<'a>,
@@ -201,39 +680,108 @@ impl<'a, F: ForEachShapePosition> MovementEnv<'a, F> {
         &self,
         position: Vector2<f64>,
         movement: Vector2<f64>,
-        ctx: &'b mut MovementContext,
+        closest_collisions: &'b mut BestMultiSet<Collision>,
+        candidate_count: &mut u64,
+        enabled_channels: Channels,
     ) -> ClosestCollisions<'b> {
-        ctx.closest_collisions(
+        find_closest_collisions(
+            closest_collisions,
+            candidate_count,
             self.shape_position(position),
             movement,
+            enabled_channels,
             self.for_each_shape_position,
         )
     }
 }
 
+// Redirects all of the remaining movement along the surface just hit, away
+// from the surface hit before it, instead of losing the part of the movement
+// pushing into the corner.
+fn corner_slide(
+    remaining: Vector2<f64>,
+    normal: Vector2<f64>,
+    last_normal: Vector2<f64>,
+) -> Vector2<f64> {
+    let along = vec2(-normal.y, normal.x);
+    let along = if along.dot(last_normal) < 0. {
+        -along
+    } else {
+        along
+    };
+    along * remaining.magnitude()
+}
+
+// Grows `contact` to also cover `other` when both lie on the same line along
+// `vector`, so a face pressed flat against an edge reports the whole overlap
+// rather than whichever of its colliding edges was found first.
+fn merge_contact(
+    contact: &mut (Vector2<f64>, Vector2<f64>),
+    other: (Vector2<f64>, Vector2<f64>),
+    vector: Vector2<f64>,
+) {
+    let length2 = vector.magnitude2();
+    if length2 == 0. {
+        return;
+    }
+    let origin = contact.0;
+    let normal = vec2(-vector.y, vector.x) / length2.sqrt();
+    let is_on_line = |point: Vector2<f64>| (point - origin).dot(normal).abs() < EPSILON;
+    if !is_on_line(other.0) || !is_on_line(other.1) {
+        return;
+    }
+    let along = |point: Vector2<f64>| (point - origin).dot(vector) / length2;
+    let (start, end) = [contact.1, other.0, other.1].iter().fold(
+        (0_f64, along(contact.1)),
+        |(start, end), &point| (start.min(along(point)), end.max(along(point))),
+    );
+    *contact = (origin + vector * start, origin + vector * end);
+}
+
 struct MovementStateMachine {
     movement: Vector2<f64>,
     position: Vector2<f64>,
     bump: Option<Vector2<f64>>,
+    velocity: Vector2<f64>,
     velocity_correction: Vector2<f64>,
-    remaining: u8,
+    elapsed: f64,
+    restitution: f64,
+    bounced: bool,
+    last_normal: Option<Vector2<f64>>,
+    remaining_slides: u8,
+    remaining_bumps: u8,
 }
 
 impl MovementStateMachine {
-    fn new(movement: Vector2<f64>, position: Vector2<f64>) -> Self {
-        const MAX_ITERATIONS: u8 = 16;
+    fn new(
+        movement: Vector2<f64>,
+        position: Vector2<f64>,
+        restitution: f64,
+        config: &MovementConfig,
+    ) -> Self {
         Self {
             movement,
             position,
             bump: None,
+            velocity: movement,
             velocity_correction: vec2(0., 0.),
-            remaining: MAX_ITERATIONS,
+            elapsed: 0.,
+            restitution,
+            bounced: false,
+            last_normal: None,
+            remaining_slides: config.max_slide_iterations,
+            remaining_bumps: config.max_bump_iterations,
         }
     }
     fn to_movement(&self, original_position: Vector2<f64>) -> Movement {
+        let velocity = if self.bounced {
+            self.velocity
+        } else {
+            self.position - original_position + self.velocity_correction
+        };
         Movement {
             position: self.position,
-            velocity: self.position - original_position + self.velocity_correction,
+            velocity,
         }
     }
     fn step<F>(
@@ -244,12 +792,27 @@ impl MovementStateMachine {
     where
         F: ForEachShapePosition,
     {
-        if self.remaining == 0 {
-            return Some(self.to_movement(env.original.position));
-        }
+        let MovementContext {
+            ref mut closest_collisions,
+            ref mut collision_events,
+            ref config,
+            ref mut candidate_count,
+            record_debug_contacts,
+            ref mut debug_contacts,
+        } = *ctx;
         match self.bump {
             Some(bump) => {
-                let closest = env.closest_collisions(self.position, bump, ctx);
+                if self.remaining_bumps == 0 {
+                    return Some(self.to_movement(env.original.position));
+                }
+                self.remaining_bumps -= 1;
+                let closest = env.closest_collisions(
+                    self.position,
+                    bump,
+                    closest_collisions,
+                    candidate_count,
+                    config.enabled_channels,
+                );
                 match closest.first() {
                     Some(_closest) => {
                         return Some(self.to_movement(env.original.position))
@@ -262,22 +825,102 @@ impl MovementStateMachine {
                 }
             }
             None => {
-                let closest_collisions =
-                    env.closest_collisions(self.position, self.movement, ctx);
+                if self.remaining_slides == 0 {
+                    return Some(self.to_movement(env.original.position));
+                }
+                self.remaining_slides -= 1;
+                let closest_collisions = env.closest_collisions(
+                    self.position,
+                    self.movement,
+                    closest_collisions,
+                    candidate_count,
+                    config.enabled_channels,
+                );
                 match closest_collisions.first() {
                     None => {
                         self.position += self.movement;
                         return Some(self.to_movement(env.original.position));
                     }
                     Some(closest) => {
+                        let multiplier =
+                            closest.left_solid_edge_collision.movement_multiplier();
+                        self.elapsed += (1. - self.elapsed) * multiplier;
+                        let first_event = collision_events.len();
+                        for collision in closest_collisions.iter() {
+                            let recorded = collision_events[first_event..]
+                                .iter_mut()
+                                .find(|event| {
+                                    event.stationary_entity_id
+                                        == collision.stationary_entity_id
+                                });
+                            match recorded {
+                                Some(event) => merge_contact(
+                                    &mut event.contact,
+                                    collision.contact,
+                                    collision.stationary_edge_vector.vector,
+                                ),
+                                None => {
+                                    let impulse =
+                                        -self.velocity.dot(collision.normal());
+                                    collision_events.push(CollisionEvent::new(
+                                        collision,
+                                        config,
+                                        impulse.max(0.),
+                                        self.elapsed,
+                                    ));
+                                }
+                            }
+                            if record_debug_contacts {
+                                let (start, end) = collision.contact;
+                                debug_contacts.push(DebugContact {
+                                    point: (start + end) / 2.,
+                                    normal: collision.normal(),
+                                });
+                            }
+                        }
+                        let normal = closest.normal();
+                        // Slow impacts don't bounce, so that bodies can come
+                        // to rest on the ground.
+                        let restitution =
+                            if -self.velocity.dot(normal) > config.min_bounce_speed {
+                                self.restitution
+                            } else {
+                                0.
+                            };
+                        let bounce = 1. + restitution;
+                        self.velocity -=
+                            normal * self.velocity.dot(normal).min(0.) * bounce;
                         self.position += closest
                             .left_solid_edge_collision
                             .movement_to_collision(self.movement);
                         match max_bump(closest_collisions) {
-                            None => {
-                                self.movement = closest
+                            None if restitution > 0. => {
+                                let remaining = closest
                                     .left_solid_edge_collision
-                                    .slide(self.movement);
+                                    .movement_following_collision(self.movement);
+                                self.movement = remaining
+                                    - normal * remaining.dot(normal).min(0.) * bounce;
+                                self.bounced = true;
+                            }
+                            None => {
+                                let collision = &closest.left_solid_edge_collision;
+                                let corner = self.last_normal.and_then(|last| {
+                                    let is_corner = config.preserve_corner_momentum
+                                        && last.dot(normal) < 1. - CORNER_EPSILON;
+                                    if is_corner {
+                                        Some(last)
+                                    } else {
+                                        None
+                                    }
+                                });
+                                let remaining =
+                                    collision.movement_following_collision(self.movement);
+                                self.movement = match corner {
+                                    Some(last_normal) => {
+                                        corner_slide(remaining, normal, last_normal)
+                                    }
+                                    None => collision.slide(self.movement),
+                                };
                             }
                             Some(max_bump) => {
                                 self.bump = Some(max_bump.vector());
@@ -286,11 +929,276 @@ impl MovementStateMachine {
                                     .movement_following_collision(self.movement);
                             }
                         }
+                        self.last_normal = Some(normal);
                     }
                 }
             }
         };
-        self.remaining -= 1;
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use convex_polygon::ConvexPolygon;
+    use line_segment::LineSegment;
+
+    const NUM_STEPS: usize = 10;
+    const STEP_WIDTH: f64 = 10.;
+    const STEP_HEIGHT: f64 = 1.;
+
+    // Steps low enough to bump up, rising to the right from x = 10.
+    fn staircase(step: &Shape) -> Vec<ShapePosition> {
+        (0..NUM_STEPS)
+            .map(|i| ShapePosition {
+                entity_id: i as EntityId,
+                position: vec2(
+                    10. + i as f64 * STEP_WIDTH,
+                    10. - (i + 1) as f64 * STEP_HEIGHT,
+                ),
+                shape: step,
+            })
+            .collect()
+    }
+
+    // Moves a 10x10 character right over the staircase in a single movement,
+    // returning how many steps it climbed.
+    fn climb_staircase(config: MovementConfig) -> usize {
+        let step = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(STEP_WIDTH, 100.)));
+        let character =
+            Shape::AxisAlignedRect(AxisAlignedRect::new_character(vec2(10., 10.)));
+        let obstacles = staircase(&step);
+        let mut ctx = MovementContext::default();
+        *ctx.config_mut() = config;
+        let movement = ctx.position_after_allowed_movement(
+            ShapePosition {
+                entity_id: RESOLVE_MOVEMENT_ENTITY_ID,
+                position: vec2(0., 0.),
+                shape: &character,
+            },
+            vec2(NUM_STEPS as f64 * STEP_WIDTH + 10., 0.),
+            &Obstacles(&obstacles),
+        );
+        (-movement.position.y / STEP_HEIGHT).round() as usize
+    }
+
+    // Each step takes a slide and a bump, which together are more than either
+    // budget, so climbing the whole staircase needs them kept separate.
+    #[test]
+    fn staircase_resolves_within_separate_budgets() {
+        assert_eq!(climb_staircase(MovementConfig::default()), NUM_STEPS);
+        let config = MovementConfig {
+            max_bump_iterations: 4,
+            ..Default::default()
+        };
+        assert_eq!(climb_staircase(config), 4);
+    }
+
+    #[test]
+    fn character_descends_the_staircase_with_default_budgets() {
+        let step = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(STEP_WIDTH, 100.)));
+        let character =
+            Shape::AxisAlignedRect(AxisAlignedRect::new_character(vec2(10., 10.)));
+        let obstacles = staircase(&step);
+        let mut ctx = MovementContext::default();
+        // Starting on the top step, walk left and fall each frame.
+        let mut position = vec2(100., -10.);
+        for _ in 0..47 {
+            position = ctx.position_after_allowed_movement(
+                ShapePosition {
+                    entity_id: RESOLVE_MOVEMENT_ENTITY_ID,
+                    position,
+                    shape: &character,
+                },
+                vec2(-2., 2.),
+                &Obstacles(&obstacles),
+            ).position;
+        }
+        assert_eq!(position, vec2(6., -1.));
+    }
+
+    // Moves a 10x10 character, with its feet at y = 10, 50 right from the
+    // origin towards an obstacle.
+    fn walk_right_into(position: Vector2<f64>, obstacle: Shape) -> Vector2<f64> {
+        let character =
+            Shape::AxisAlignedRect(AxisAlignedRect::new_character(vec2(10., 10.)));
+        let obstacles = [ShapePosition {
+            entity_id: 0,
+            position,
+            shape: &obstacle,
+        }];
+        resolve_movement(&character, vec2(0., 0.), vec2(50., 0.), &obstacles).position
+    }
+
+    #[test]
+    fn walls_block_without_being_bumped_over() {
+        let rect = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 100.)));
+        let polygon = |vertices| {
+            Shape::ConvexPolygon(ConvexPolygon::from_vertices(vertices).unwrap())
+        };
+        let blocked = vec2(20., 0.);
+        assert_eq!(walk_right_into(vec2(30., -50.), rect.clone()), blocked);
+        // A wall hanging down to 1 above the feet.
+        assert_eq!(walk_right_into(vec2(30., -91.), rect.clone()), blocked);
+        // A step 3 high is too high to bump up.
+        assert_eq!(walk_right_into(vec2(30., 7.), rect.clone()), blocked);
+        let line = LineSegment::new_both_solid(vec2(0., -50.), vec2(0., 50.));
+        let line = walk_right_into(vec2(30., 0.), Shape::LineSegment(line));
+        assert!((line - blocked).magnitude() < 0.2);
+        // A steep wall whose foot is 1 above the feet, leaning away.
+        let lean = vec![vec2(10., 0.), vec2(20., 0.), vec2(20., 50.), vec2(0., 50.)];
+        assert_eq!(walk_right_into(vec2(30., -41.), polygon(lean)), blocked);
+        // A step 1 high, and a ramp whose foot is 1 above the feet, are both
+        // stepped onto.
+        assert_eq!(walk_right_into(vec2(30., 9.), rect).x, 50.);
+        let ramp = vec![vec2(0., 50.), vec2(100., 0.), vec2(100., 50.)];
+        let ramp = walk_right_into(vec2(30., -41.), polygon(ramp));
+        assert!(ramp.x > 40. && ramp.y < -10.);
+    }
+
+    #[test]
+    fn stationary_displacement_keeps_current_velocity() {
+        let displacement = Displacement {
+            movement: vec2(0., 0.),
+            velocity: vec2(0., 0.),
+        };
+        let velocity = displacement.combine_velocity(vec2(1., 2.));
+        assert!(!velocity.x.is_nan() && !velocity.y.is_nan());
+        assert_eq!(velocity, vec2(1., 2.));
+        let displacement = Displacement {
+            movement: vec2(0., -3.),
+            velocity: vec2(0., -3.),
+        };
+        assert_eq!(displacement.combine_velocity(vec2(1., 2.)), vec2(1., -3.));
+    }
+
+    // Moves a 10x10 character diagonally down and right into the inside
+    // corner of a floor and a wall.
+    fn move_into_corner(preserve_corner_momentum: bool) -> Vector2<f64> {
+        let floor = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(500., 20.)));
+        let wall = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 200.)));
+        let character =
+            Shape::AxisAlignedRect(AxisAlignedRect::new_character(vec2(10., 10.)));
+        let obstacles = [
+            ShapePosition {
+                entity_id: 1,
+                position: vec2(0., 100.),
+                shape: &floor,
+            },
+            ShapePosition {
+                entity_id: 2,
+                position: vec2(100., -50.),
+                shape: &wall,
+            },
+        ];
+        let mut ctx = MovementContext::default();
+        ctx.config_mut().preserve_corner_momentum = preserve_corner_momentum;
+        let movement = ctx.position_after_allowed_movement(
+            ShapePosition {
+                entity_id: RESOLVE_MOVEMENT_ENTITY_ID,
+                position: vec2(80., 89.5),
+                shape: &character,
+            },
+            vec2(16., 1.),
+            &Obstacles(&obstacles),
+        );
+        movement.position
+    }
+
+    #[test]
+    fn corner_momentum_continues_up_the_open_wall() {
+        assert_eq!(move_into_corner(false), vec2(90., 90.));
+        // Landing on the floor after moving 8 right leaves 8 to slide along it,
+        // and the 6 that would push into the wall carries on up it instead.
+        assert_eq!(move_into_corner(true), vec2(90., 84.));
+    }
+
+    // Resolves a 10x10 character's movement near the inside corner of a floor
+    // along y = 100 and a wall along x = 100.
+    fn resolve_near_corner(
+        position: Vector2<f64>,
+        movement: Vector2<f64>,
+    ) -> Vector2<f64> {
+        let floor = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(500., 20.)));
+        let wall = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 200.)));
+        let character =
+            Shape::AxisAlignedRect(AxisAlignedRect::new_character(vec2(10., 10.)));
+        let obstacles = [
+            ShapePosition {
+                entity_id: 0,
+                position: vec2(0., 100.),
+                shape: &floor,
+            },
+            ShapePosition {
+                entity_id: 1,
+                position: vec2(100., -100.),
+                shape: &wall,
+            },
+        ];
+        resolve_movement(&character, position, movement, &obstacles).position
+    }
+
+    #[test]
+    fn resolve_movement_stops_slides_and_wedges() {
+        let unobstructed = resolve_near_corner(vec2(0., 0.), vec2(30., 40.));
+        assert_eq!(unobstructed, vec2(30., 40.));
+        let stop = resolve_near_corner(vec2(0., 0.), vec2(200., 0.));
+        assert_eq!(stop, vec2(90., 0.));
+        let slide = resolve_near_corner(vec2(0., 80.), vec2(20., 20.));
+        assert_eq!(slide, vec2(20., 90.));
+        let corner = resolve_near_corner(vec2(60., 60.), vec2(100., 100.));
+        assert_eq!(corner, vec2(90., 90.));
+    }
+
+    #[test]
+    fn raycast_hits_a_rect_corner_and_misses_past_it() {
+        let rect = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(50., 50.)));
+        let obstacles = [
+            ShapePosition {
+                entity_id: 1,
+                position: vec2(100., 100.),
+                shape: &rect,
+            },
+            ShapePosition {
+                entity_id: 2,
+                position: vec2(300., 300.),
+                shape: &rect,
+            },
+        ];
+        let ctx = MovementContext::default();
+        let raycast = |origin, direction| {
+            ctx.raycast(origin, direction, 1000., &Obstacles(&obstacles))
+        };
+        let hit = raycast(vec2(50., 50.), vec2(1., 1.)).unwrap();
+        assert_eq!(hit.entity_id, 1);
+        assert!((hit.distance - 50. * 2_f64.sqrt()).abs() < EPSILON);
+        assert!((hit.point - vec2(100., 100.)).magnitude() < EPSILON);
+        assert!(raycast(vec2(50., 50.), vec2(1., 0.)).is_none());
+        assert!(raycast(vec2(50., 151.), vec2(1., 0.)).is_none());
+        assert!(raycast(vec2(50., 50.), vec2(-1., -1.)).is_none());
+        // Starting inside a shape hits it straight away.
+        let hit = raycast(vec2(125., 125.), vec2(1., 1.)).unwrap();
+        assert_eq!(hit.entity_id, 1);
+        assert_eq!(hit.distance, 0.);
+    }
+
+    #[test]
+    fn sweep_aabb_reports_the_fraction_before_contact() {
+        let wall = Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 100.)));
+        let obstacles = [ShapePosition {
+            entity_id: 1,
+            position: vec2(60., 0.),
+            shape: &wall,
+        }];
+        let mut ctx = MovementContext::default();
+        let aabb = Aabb::new(vec2(0., 40.), vec2(10., 10.));
+        let clear = ctx.sweep_aabb(aabb, vec2(0., 100.), &Obstacles(&obstacles));
+        assert!(clear.is_none());
+        let hit = ctx.sweep_aabb(aabb, vec2(100., 0.), &Obstacles(&obstacles))
+            .unwrap();
+        assert_eq!(hit.entity_id, 1);
+        assert!((hit.fraction - 0.5).abs() < 0.01);
+        assert_eq!(hit.normal, vec2(-1., 0.));
+    }
+}
@@ -0,0 +1,273 @@
+use aabb::Aabb;
+use cgmath::{vec2, InnerSpace, Vector2};
+use std::cmp::Ordering;
+
+fn axis_overlap(a_min: f64, a_max: f64, b_min: f64, b_max: f64) -> f64 {
+    a_max.min(b_max) - a_min.max(b_min)
+}
+
+// Each function returns the smallest translation that moves the first
+// argument out of the second, or `None` if they don't overlap.
+
+pub fn aabb_aabb(a: &Aabb, b: &Aabb) -> Option<Vector2<f64>> {
+    let a_min = a.top_left();
+    let a_max = a.bottom_right_coord();
+    let b_min = b.top_left();
+    let b_max = b.bottom_right_coord();
+    let overlap_x = axis_overlap(a_min.x, a_max.x, b_min.x, b_max.x);
+    let overlap_y = axis_overlap(a_min.y, a_max.y, b_min.y, b_max.y);
+    if overlap_x <= 0. || overlap_y <= 0. {
+        return None;
+    }
+    let a_centre = a.centre();
+    let b_centre = b.centre();
+    if overlap_x < overlap_y {
+        let sign = if a_centre.x < b_centre.x { -1. } else { 1. };
+        Some(vec2(overlap_x * sign, 0.))
+    } else {
+        let sign = if a_centre.y < b_centre.y { -1. } else { 1. };
+        Some(vec2(0., overlap_y * sign))
+    }
+}
+
+pub fn aabb_line_segment(
+    aabb: &Aabb,
+    start: Vector2<f64>,
+    end: Vector2<f64>,
+) -> Option<Vector2<f64>> {
+    let aabb_min = aabb.top_left();
+    let aabb_max = aabb.bottom_right_coord();
+    let centre = aabb.centre();
+    let segment_centre = (start + end) / 2.;
+
+    let overlap_x = axis_overlap(
+        aabb_min.x,
+        aabb_max.x,
+        start.x.min(end.x),
+        start.x.max(end.x),
+    );
+    let overlap_y = axis_overlap(
+        aabb_min.y,
+        aabb_max.y,
+        start.y.min(end.y),
+        start.y.max(end.y),
+    );
+    if overlap_x < 0. || overlap_y < 0. {
+        return None;
+    }
+
+    let vector = end - start;
+    if vector.magnitude2() == 0. {
+        return None;
+    }
+    let normal = vec2(-vector.y, vector.x).normalize();
+    let segment_projection = start.dot(normal);
+    let corners = [
+        aabb_min,
+        vec2(aabb_max.x, aabb_min.y),
+        aabb_max,
+        vec2(aabb_min.x, aabb_max.y),
+    ];
+    let (min_projection, max_projection) = corners.iter().fold(
+        (::std::f64::INFINITY, ::std::f64::NEG_INFINITY),
+        |(min, max), corner| {
+            let projection = corner.dot(normal);
+            (min.min(projection), max.max(projection))
+        },
+    );
+    if min_projection >= segment_projection || max_projection <= segment_projection {
+        return None;
+    }
+    let normal_translation = if centre.dot(normal) < segment_projection {
+        normal * (segment_projection - max_projection)
+    } else {
+        normal * (segment_projection - min_projection)
+    };
+
+    let sign_x = if centre.x < segment_centre.x { -1. } else { 1. };
+    let sign_y = if centre.y < segment_centre.y { -1. } else { 1. };
+    let translations = [
+        Some(normal_translation),
+        if overlap_x > 0. {
+            Some(vec2(overlap_x * sign_x, 0.))
+        } else {
+            None
+        },
+        if overlap_y > 0. {
+            Some(vec2(0., overlap_y * sign_y))
+        } else {
+            None
+        },
+    ];
+    translations
+        .iter()
+        .filter_map(|&translation| translation)
+        .min_by(|a, b| {
+            a.magnitude2()
+                .partial_cmp(&b.magnitude2())
+                .unwrap_or(Ordering::Equal)
+        })
+}
+
+fn closest_point_on_aabb(aabb: &Aabb, point: Vector2<f64>) -> Vector2<f64> {
+    let min = aabb.top_left();
+    let max = aabb.bottom_right_coord();
+    vec2(point.x.max(min.x).min(max.x), point.y.max(min.y).min(max.y))
+}
+
+fn closest_point_on_segment(
+    start: Vector2<f64>,
+    end: Vector2<f64>,
+    point: Vector2<f64>,
+) -> Vector2<f64> {
+    let vector = end - start;
+    let length2 = vector.magnitude2();
+    if length2 == 0. {
+        return start;
+    }
+    let along = ((point - start).dot(vector) / length2).max(0.).min(1.);
+    start + vector * along
+}
+
+fn circle_point(
+    centre: Vector2<f64>,
+    radius: f64,
+    point: Vector2<f64>,
+) -> Option<Vector2<f64>> {
+    let offset = centre - point;
+    let distance = offset.magnitude();
+    if distance >= radius || distance == 0. {
+        return None;
+    }
+    Some(offset * ((radius - distance) / distance))
+}
+
+pub fn circle_aabb(
+    centre: Vector2<f64>,
+    radius: f64,
+    aabb: &Aabb,
+) -> Option<Vector2<f64>> {
+    let closest = closest_point_on_aabb(aabb, centre);
+    if closest == centre {
+        let circle_aabb = Aabb::from_centre_and_half_size(centre, vec2(radius, radius));
+        return aabb_aabb(&circle_aabb, aabb);
+    }
+    circle_point(centre, radius, closest)
+}
+
+pub fn circle_line_segment(
+    centre: Vector2<f64>,
+    radius: f64,
+    start: Vector2<f64>,
+    end: Vector2<f64>,
+) -> Option<Vector2<f64>> {
+    circle_point(centre, radius, closest_point_on_segment(start, end, centre))
+}
+
+pub fn circle_circle(
+    a_centre: Vector2<f64>,
+    a_radius: f64,
+    b_centre: Vector2<f64>,
+    b_radius: f64,
+) -> Option<Vector2<f64>> {
+    circle_point(a_centre, a_radius + b_radius, b_centre)
+}
+
+fn project(vertices: &[Vector2<f64>], axis: Vector2<f64>) -> (f64, f64) {
+    vertices.iter().fold(
+        (::std::f64::INFINITY, ::std::f64::NEG_INFINITY),
+        |(min, max), vertex| {
+            let projection = vertex.dot(axis);
+            (min.min(projection), max.max(projection))
+        },
+    )
+}
+
+fn centroid(vertices: &[Vector2<f64>]) -> Vector2<f64> {
+    vertices
+        .iter()
+        .fold(vec2(0., 0.), |total, &vertex| total + vertex)
+        / vertices.len() as f64
+}
+
+fn edge_normals<'a>(
+    vertices: &'a [Vector2<f64>],
+) -> impl Iterator<Item = Vector2<f64>> + 'a {
+    let num_vertices = vertices.len();
+    (0..num_vertices).filter_map(move |i| {
+        let vector = vertices[(i + 1) % num_vertices] - vertices[i];
+        if vector.magnitude2() == 0. {
+            None
+        } else {
+            Some(vec2(vector.y, -vector.x).normalize())
+        }
+    })
+}
+
+// Separating axis test between two convex vertex lists, either of which may be
+// a single line segment.
+pub fn convex_convex(a: &[Vector2<f64>], b: &[Vector2<f64>]) -> Option<Vector2<f64>> {
+    let a_centre = centroid(a);
+    let b_centre = centroid(b);
+    let mut smallest: Option<Vector2<f64>> = None;
+    for axis in edge_normals(a).chain(edge_normals(b)) {
+        let (a_min, a_max) = project(a, axis);
+        let (b_min, b_max) = project(b, axis);
+        let overlap = axis_overlap(a_min, a_max, b_min, b_max);
+        if overlap <= 0. {
+            return None;
+        }
+        let is_smaller = smallest
+            .map(|smallest| overlap * overlap < smallest.magnitude2())
+            .unwrap_or(true);
+        if is_smaller {
+            let sign = if a_centre.dot(axis) < b_centre.dot(axis) {
+                -1.
+            } else {
+                1.
+            };
+            smallest = Some(axis * overlap * sign);
+        }
+    }
+    smallest
+}
+
+pub fn circle_convex(
+    centre: Vector2<f64>,
+    radius: f64,
+    vertices: &[Vector2<f64>],
+) -> Option<Vector2<f64>> {
+    let closest_vertex = vertices.iter().cloned().min_by(|a, b| {
+        (a - centre)
+            .magnitude2()
+            .partial_cmp(&(b - centre).magnitude2())
+            .unwrap_or(Ordering::Equal)
+    })?;
+    let vertex_axis = if closest_vertex == centre {
+        None
+    } else {
+        Some((centre - closest_vertex).normalize())
+    };
+    let polygon_centre = centroid(vertices);
+    let mut smallest: Option<Vector2<f64>> = None;
+    for axis in edge_normals(vertices).chain(vertex_axis) {
+        let projection = centre.dot(axis);
+        let (min, max) = project(vertices, axis);
+        let overlap = axis_overlap(projection - radius, projection + radius, min, max);
+        if overlap <= 0. {
+            return None;
+        }
+        let is_smaller = smallest
+            .map(|smallest| overlap * overlap < smallest.magnitude2())
+            .unwrap_or(true);
+        if is_smaller {
+            let sign = if projection < polygon_centre.dot(axis) {
+                -1.
+            } else {
+                1.
+            };
+            smallest = Some(axis * overlap * sign);
+        }
+    }
+    smallest
+}
@@ -0,0 +1,40 @@
+use cgmath::Vector2;
+use line_segment::{LineSegment, Solidity};
+
+#[derive(Debug, Clone)]
+pub struct Polyline {
+    points: Vec<Vector2<f64>>,
+    solidity: Solidity,
+}
+
+impl Polyline {
+    pub fn new(mut points: Vec<Vector2<f64>>) -> Self {
+        points.dedup();
+        Self {
+            points,
+            solidity: Solidity::Both,
+        }
+    }
+    pub fn with_solidity(self, solidity: Solidity) -> Self {
+        Self { solidity, ..self }
+    }
+    pub fn points(&self) -> &[Vector2<f64>] {
+        &self.points
+    }
+    pub fn line_segments(&self) -> Vec<LineSegment> {
+        let vectors = self.points
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect::<Vec<_>>();
+        self.points
+            .windows(2)
+            .enumerate()
+            .map(|(i, pair)| {
+                let previous = if i > 0 { Some(vectors[i - 1]) } else { None };
+                let next = vectors.get(i + 1).cloned();
+                LineSegment::with_solidity(pair[0], pair[1], self.solidity)
+                    .with_neighbours(previous, next)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,144 @@
+use aabb::Aabb;
+use cgmath::{vec2, InnerSpace, Vector2};
+use movement::EntityId;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    pub entity_id: EntityId,
+    pub distance: f64,
+    pub point: Vector2<f64>,
+    pub normal: Vector2<f64>,
+}
+
+fn cross(a: Vector2<f64>, b: Vector2<f64>) -> f64 {
+    a.x * b.y - a.y * b.x
+}
+
+// Each function returns the distance along `direction` (a unit vector) at
+// which the ray first touches the shape, and the surface normal at that point.
+
+pub fn aabb(
+    aabb: &Aabb,
+    origin: Vector2<f64>,
+    direction: Vector2<f64>,
+    max_distance: f64,
+) -> Option<(f64, Vector2<f64>)> {
+    let min = aabb.top_left();
+    let max = aabb.bottom_right_coord();
+    let axes = [
+        (origin.x, direction.x, min.x, max.x, vec2(1., 0.)),
+        (origin.y, direction.y, min.y, max.y, vec2(0., 1.)),
+    ];
+    let mut enter = ::std::f64::NEG_INFINITY;
+    let mut exit = ::std::f64::INFINITY;
+    let mut normal = -direction;
+    for &(origin, direction, min, max, axis) in axes.iter() {
+        if direction == 0. {
+            if origin < min || origin > max {
+                return None;
+            }
+            continue;
+        }
+        let to_min = (min - origin) / direction;
+        let to_max = (max - origin) / direction;
+        let (near, far, near_normal) = if to_min < to_max {
+            (to_min, to_max, -axis)
+        } else {
+            (to_max, to_min, axis)
+        };
+        if near > enter {
+            enter = near;
+            normal = near_normal;
+        }
+        exit = exit.min(far);
+    }
+    if enter > exit || exit < 0. || enter > max_distance {
+        None
+    } else if enter < 0. {
+        Some((0., -direction))
+    } else {
+        Some((enter, normal))
+    }
+}
+
+pub fn line_segment(
+    start: Vector2<f64>,
+    end: Vector2<f64>,
+    origin: Vector2<f64>,
+    direction: Vector2<f64>,
+    max_distance: f64,
+) -> Option<(f64, Vector2<f64>)> {
+    let vector = end - start;
+    let denominator = cross(direction, vector);
+    if denominator == 0. {
+        return None;
+    }
+    let to_start = start - origin;
+    let distance = cross(to_start, vector) / denominator;
+    let along_segment = cross(to_start, direction) / denominator;
+    if distance < 0.
+        || distance > max_distance
+        || along_segment < 0.
+        || along_segment > 1.
+    {
+        return None;
+    }
+    let normal = vec2(vector.y, -vector.x).normalize();
+    if normal.dot(direction) > 0. {
+        Some((distance, -normal))
+    } else {
+        Some((distance, normal))
+    }
+}
+
+pub fn circle(
+    centre: Vector2<f64>,
+    radius: f64,
+    origin: Vector2<f64>,
+    direction: Vector2<f64>,
+    max_distance: f64,
+) -> Option<(f64, Vector2<f64>)> {
+    let to_origin = origin - centre;
+    let c = to_origin.magnitude2() - radius * radius;
+    if c <= 0. {
+        return Some((0., -direction));
+    }
+    let b = to_origin.dot(direction);
+    let discriminant = b * b - c;
+    if b > 0. || discriminant < 0. {
+        return None;
+    }
+    let distance = -b - discriminant.sqrt();
+    if distance > max_distance {
+        return None;
+    }
+    let point = origin + direction * distance;
+    Some((distance, (point - centre).normalize()))
+}
+
+pub fn convex(
+    vertices: &[Vector2<f64>],
+    origin: Vector2<f64>,
+    direction: Vector2<f64>,
+    max_distance: f64,
+) -> Option<(f64, Vector2<f64>)> {
+    let num_vertices = vertices.len();
+    let is_inside = (0..num_vertices).all(|i| {
+        cross(vertices[(i + 1) % num_vertices] - vertices[i], origin - vertices[i]) >= 0.
+    });
+    if is_inside {
+        return Some((0., -direction));
+    }
+    (0..num_vertices)
+        .filter_map(|i| {
+            line_segment(
+                vertices[i],
+                vertices[(i + 1) % num_vertices],
+                origin,
+                direction,
+                max_distance,
+            )
+        })
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+}
@@ -0,0 +1,36 @@
+const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+// xorshift64*, which is small and good enough for gameplay randomness while
+// keeping simulations reproducible from a seed.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The all-zero state is a fixed point of xorshift.
+        let state = if seed == 0 { DEFAULT_SEED } else { seed };
+        Self { state }
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+    // Uniform in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next_f64()
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
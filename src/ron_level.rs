@@ -0,0 +1,222 @@
+use axis_aligned_rect::{AxisAlignedRect, EdgeFlags, Metadata};
+use cgmath::{InnerSpace, Vector2};
+use line_segment::{LineSegment, Solidity};
+use ron;
+use shape::Shape;
+use std::io;
+
+const DEFAULT_COLOUR: [f32; 3] = [1., 1., 1.];
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(io::Error),
+    Parse(ron::de::Error),
+    MissingShape { entity: usize },
+    InvalidShape { entity: usize, reason: &'static str },
+    MultiplePlayers { entity: usize },
+}
+
+impl From<io::Error> for LevelError {
+    fn from(error: io::Error) -> Self {
+        LevelError::Io(error)
+    }
+}
+
+impl From<ron::de::Error> for LevelError {
+    fn from(error: ron::de::Error) -> Self {
+        LevelError::Parse(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EntityKind {
+    Static,
+    Dynamic,
+    Kinematic,
+    // The player always has the same shape and colour, so any given are ignored.
+    Player,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RectEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ShapeDescriptor {
+    Rect {
+        dimensions: Vector2<f64>,
+        #[serde(default)]
+        metadata: Option<Metadata>,
+        // Defaults to the edges that are solid for the metadata.
+        #[serde(default)]
+        solid_edges: Option<Vec<RectEdge>>,
+    },
+    LineSegment {
+        start: Vector2<f64>,
+        end: Vector2<f64>,
+        #[serde(default = "default_solidity")]
+        solidity: Solidity,
+    },
+}
+
+fn default_solidity() -> Solidity {
+    Solidity::Both
+}
+
+fn default_colour() -> [f32; 3] {
+    DEFAULT_COLOUR
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityDescriptor {
+    pub kind: EntityKind,
+    pub position: Vector2<f64>,
+    #[serde(default)]
+    pub shape: Option<ShapeDescriptor>,
+    #[serde(default = "default_colour")]
+    pub colour: [f32; 3],
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelDescriptor {
+    pub entities: Vec<EntityDescriptor>,
+}
+
+// Only the player has no shape.
+#[derive(Debug, Clone)]
+pub struct LevelEntity {
+    pub kind: EntityKind,
+    pub position: Vector2<f64>,
+    pub shape: Option<Shape>,
+    pub colour: [f32; 3],
+}
+
+fn edge_flags(edges: &[RectEdge]) -> EdgeFlags {
+    edges.iter().fold(EdgeFlags::NONE, |flags, edge| {
+        flags | match *edge {
+            RectEdge::Top => EdgeFlags::TOP,
+            RectEdge::Bottom => EdgeFlags::BOTTOM,
+            RectEdge::Left => EdgeFlags::LEFT,
+            RectEdge::Right => EdgeFlags::RIGHT,
+        }
+    })
+}
+
+fn shape(descriptor: &ShapeDescriptor, entity: usize) -> Result<Shape, LevelError> {
+    match descriptor {
+        &ShapeDescriptor::Rect {
+            dimensions,
+            metadata,
+            ref solid_edges,
+        } => {
+            if !(dimensions.x > 0. && dimensions.y > 0.) {
+                return Err(LevelError::InvalidShape {
+                    entity,
+                    reason: "rect dimensions must be positive",
+                });
+            }
+            let metadata = metadata.unwrap_or(Metadata::Main);
+            let rect = match solid_edges {
+                &Some(ref edges) => AxisAlignedRect::with_metadata_and_solid_edges(
+                    dimensions,
+                    metadata,
+                    edge_flags(edges),
+                ),
+                &None => AxisAlignedRect::with_metadata(dimensions, metadata),
+            };
+            Ok(Shape::AxisAlignedRect(rect))
+        }
+        &ShapeDescriptor::LineSegment {
+            start,
+            end,
+            solidity,
+        } => {
+            if !((end - start).magnitude2() > 0.) {
+                return Err(LevelError::InvalidShape {
+                    entity,
+                    reason: "line segment must have non-zero length",
+                });
+            }
+            Ok(Shape::LineSegment(LineSegment::with_solidity(
+                start, end, solidity,
+            )))
+        }
+    }
+}
+
+// Checks the whole level before returning any of it, so a malformed level
+// can be rejected without disturbing the current one.
+pub fn parse(ron: &str) -> Result<Vec<LevelEntity>, LevelError> {
+    let descriptor: LevelDescriptor = ron::de::from_str(ron)?;
+    let mut has_player = false;
+    descriptor
+        .entities
+        .iter()
+        .enumerate()
+        .map(|(entity, descriptor)| {
+            let shape = match descriptor.kind {
+                EntityKind::Player => {
+                    if has_player {
+                        return Err(LevelError::MultiplePlayers { entity });
+                    }
+                    has_player = true;
+                    None
+                }
+                _ => match descriptor.shape {
+                    Some(ref shape_descriptor) => Some(shape(shape_descriptor, entity)?),
+                    None => return Err(LevelError::MissingShape { entity }),
+                },
+            };
+            Ok(LevelEntity {
+                kind: descriptor.kind,
+                position: descriptor.position,
+                shape,
+                colour: descriptor.colour,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_level_parses() {
+        let entities = parse(include_str!("../levels/sample.ron")).unwrap();
+        assert_eq!(entities.len(), 8);
+        let players = entities
+            .iter()
+            .filter(|entity| entity.kind == EntityKind::Player)
+            .count();
+        assert_eq!(players, 1);
+    }
+
+    #[test]
+    fn malformed_levels_are_rejected() {
+        match parse("(entities: [(kind: Statc, position: (x: 0., y: 0.))])") {
+            Err(LevelError::Parse(_)) => (),
+            other => panic!("{:?}", other),
+        }
+        match parse("(entities: [(kind: Static, position: (x: 0., y: 0.))])") {
+            Err(LevelError::MissingShape { entity: 0 }) => (),
+            other => panic!("{:?}", other),
+        }
+        let zero_width = "(entities: [(kind: Static, position: (x: 0., y: 0.), \
+                          shape: Some(Rect(dimensions: (x: 0., y: 1.))))])";
+        match parse(zero_width) {
+            Err(LevelError::InvalidShape { entity: 0, .. }) => (),
+            other => panic!("{:?}", other),
+        }
+        let two_players = "(entities: [(kind: Player, position: (x: 0., y: 0.)), \
+                           (kind: Player, position: (x: 0., y: 0.))])";
+        match parse(two_players) {
+            Err(LevelError::MultiplePlayers { entity: 1 }) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -4,6 +4,7 @@ in vec2 a_CornerZeroToOne;
 in vec2 i_Start;
 in vec2 i_End;
 in vec3 i_Colour;
+in vec3 i_EndColour;
 
 uniform WindowProperties {
     vec2 u_WindowSizeInPixels;
@@ -24,7 +25,7 @@ void main() {
         pixel_coord.x / u_WindowSizeInPixels.x * 2 - 1,
         1 - pixel_coord.y / u_WindowSizeInPixels.y * 2);
 
-    v_Colour = i_Colour;
+    v_Colour = mix(i_Colour, i_EndColour, a_CornerZeroToOne.x);
 
     gl_Position = vec4(screen_coord, 0, 1);
 }
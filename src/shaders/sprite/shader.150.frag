@@ -0,0 +1,10 @@
+#version 150 core
+
+uniform sampler2D t_Atlas;
+
+in vec2 v_Uv;
+out vec4 Target;
+
+void main() {
+    Target = texture(t_Atlas, v_Uv);
+}
@@ -1,15 +1,38 @@
 use aabb::Aabb;
 use axis_aligned_rect;
 use best::BestMultiSet;
-use cgmath::Vector2;
-use collide::{Collide, CollidePosition, Collision};
-use line_segment::LineSegment;
+use capsule::Capsule;
+use cgmath::{InnerSpace, Vector2};
+use circle::Circle;
+use collide::{Channels, Collide, CollidePosition, Collision};
+use convex_polygon::ConvexPolygon;
+use line_segment::{LineSegment, Solidity};
 use movement::EntityId;
+use penetration;
+use raycast;
 
-#[derive(Debug, Clone)]
+const OVERLAP_EPSILON: f64 = 0.001;
+
+// Vertices go clockwise on screen, so a point inside is on the solid (left)
+// side of every edge.
+fn is_point_in_convex(vertices: &[Vector2<f64>], point: Vector2<f64>) -> bool {
+    let num_vertices = vertices.len();
+    (0..num_vertices).all(|i| {
+        let start = vertices[i];
+        let edge = vertices[(i + 1) % num_vertices] - start;
+        let to_point = point - start;
+        edge.x * to_point.y - edge.y * to_point.x >= 0.
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum Shape {
     AxisAlignedRect(axis_aligned_rect::AxisAlignedRect),
     LineSegment(LineSegment),
+    Circle(Circle),
+    ConvexPolygon(ConvexPolygon),
+    Capsule(Capsule),
 }
 
 #[derive(Debug)]
@@ -33,6 +56,7 @@ impl<'a> ShapePosition<'a> {
         &self,
         moving: CollidePosition<C>,
         movement: Vector2<f64>,
+        enabled_channels: Channels,
         closest_collisions: &mut BestMultiSet<Collision>,
     ) {
         let position = self.position;
@@ -47,6 +71,7 @@ impl<'a> ShapePosition<'a> {
                 moving.movement_collision_test(
                     collide_position,
                     movement,
+                    enabled_channels,
                     closest_collisions,
                 );
             }
@@ -59,6 +84,46 @@ impl<'a> ShapePosition<'a> {
                 moving.movement_collision_test(
                     collide_position,
                     movement,
+                    enabled_channels,
+                    closest_collisions,
+                );
+            }
+            Shape::Circle(stationary) => {
+                let collide_position = CollidePosition {
+                    collide: stationary,
+                    position,
+                    entity_id,
+                };
+                moving.movement_collision_test(
+                    collide_position,
+                    movement,
+                    enabled_channels,
+                    closest_collisions,
+                );
+            }
+            Shape::ConvexPolygon(stationary) => {
+                let collide_position = CollidePosition {
+                    collide: stationary,
+                    position,
+                    entity_id,
+                };
+                moving.movement_collision_test(
+                    collide_position,
+                    movement,
+                    enabled_channels,
+                    closest_collisions,
+                );
+            }
+            Shape::Capsule(stationary) => {
+                let collide_position = CollidePosition {
+                    collide: stationary,
+                    position,
+                    entity_id,
+                };
+                moving.movement_collision_test(
+                    collide_position,
+                    movement,
+                    enabled_channels,
                     closest_collisions,
                 );
             }
@@ -69,6 +134,7 @@ impl<'a> ShapePosition<'a> {
         &self,
         stationary: ShapePosition,
         movement: Vector2<f64>,
+        enabled_channels: Channels,
         closest_collisions: &mut BestMultiSet<Collision>,
     ) {
         let position = self.position;
@@ -83,6 +149,7 @@ impl<'a> ShapePosition<'a> {
                 stationary.stationary_collision_test(
                     collide_position,
                     movement,
+                    enabled_channels,
                     closest_collisions,
                 )
             }
@@ -95,6 +162,46 @@ impl<'a> ShapePosition<'a> {
                 stationary.stationary_collision_test(
                     collide_position,
                     movement,
+                    enabled_channels,
+                    closest_collisions,
+                )
+            }
+            Shape::Circle(moving) => {
+                let collide_position = CollidePosition {
+                    collide: moving,
+                    position,
+                    entity_id,
+                };
+                stationary.stationary_collision_test(
+                    collide_position,
+                    movement,
+                    enabled_channels,
+                    closest_collisions,
+                )
+            }
+            Shape::ConvexPolygon(moving) => {
+                let collide_position = CollidePosition {
+                    collide: moving,
+                    position,
+                    entity_id,
+                };
+                stationary.stationary_collision_test(
+                    collide_position,
+                    movement,
+                    enabled_channels,
+                    closest_collisions,
+                )
+            }
+            Shape::Capsule(moving) => {
+                let collide_position = CollidePosition {
+                    collide: moving,
+                    position,
+                    entity_id,
+                };
+                stationary.stationary_collision_test(
+                    collide_position,
+                    movement,
+                    enabled_channels,
                     closest_collisions,
                 )
             }
@@ -107,6 +214,284 @@ impl Shape {
         match self {
             &Shape::AxisAlignedRect(ref rect) => rect.aabb(top_left),
             &Shape::LineSegment(ref line_segment) => line_segment.aabb(top_left),
+            &Shape::Circle(ref circle) => circle.aabb(top_left),
+            &Shape::ConvexPolygon(ref polygon) => polygon.aabb(top_left),
+            &Shape::Capsule(ref capsule) => capsule.aabb(top_left),
+        }
+    }
+    fn convex_vertices(&self, top_left: Vector2<f64>) -> Option<Vec<Vector2<f64>>> {
+        match self {
+            &Shape::AxisAlignedRect(ref rect) => {
+                Some(rect.aabb(top_left).corners().to_vec())
+            }
+            &Shape::LineSegment(ref line_segment) => {
+                let line_segment = line_segment.add_vector(top_left);
+                Some(vec![line_segment.start, line_segment.end])
+            }
+            &Shape::Circle(_) => None,
+            &Shape::ConvexPolygon(ref polygon) => Some(polygon.vertices_at(top_left)),
+            &Shape::Capsule(ref capsule) => Some(capsule.vertices_at(top_left)),
+        }
+    }
+    pub fn is_solid(&self) -> bool {
+        match self {
+            &Shape::AxisAlignedRect(ref rect) => !rect.is_one_way() && !rect.is_sensor(),
+            &Shape::LineSegment(ref line_segment) => {
+                line_segment.solidity() == Solidity::Both
+            }
+            &Shape::Circle(_) | &Shape::ConvexPolygon(_) | &Shape::Capsule(_) => true,
+        }
+    }
+    pub fn is_sensor(&self) -> bool {
+        match self {
+            &Shape::AxisAlignedRect(ref rect) => rect.is_sensor(),
+            _ => false,
+        }
+    }
+    pub fn penetration(
+        &self,
+        top_left: Vector2<f64>,
+        other: &Shape,
+        other_top_left: Vector2<f64>,
+    ) -> Option<Vector2<f64>> {
+        match (self, other) {
+            (&Shape::AxisAlignedRect(ref a), &Shape::AxisAlignedRect(ref b)) => {
+                penetration::aabb_aabb(&a.aabb(top_left), &b.aabb(other_top_left))
+            }
+            (&Shape::AxisAlignedRect(ref rect), &Shape::LineSegment(ref segment)) => {
+                let segment = segment.add_vector(other_top_left);
+                penetration::aabb_line_segment(
+                    &rect.aabb(top_left),
+                    segment.start,
+                    segment.end,
+                )
+            }
+            (&Shape::LineSegment(_), &Shape::AxisAlignedRect(_)) => other
+                .penetration(other_top_left, self, top_left)
+                .map(|translation| -translation),
+            (&Shape::LineSegment(_), &Shape::LineSegment(_)) => None,
+            (&Shape::Circle(ref circle), &Shape::AxisAlignedRect(ref rect)) => {
+                penetration::circle_aabb(
+                    circle.centre(top_left),
+                    circle.radius(),
+                    &rect.aabb(other_top_left),
+                )
+            }
+            (&Shape::Circle(ref circle), &Shape::LineSegment(ref segment)) => {
+                let segment = segment.add_vector(other_top_left);
+                penetration::circle_line_segment(
+                    circle.centre(top_left),
+                    circle.radius(),
+                    segment.start,
+                    segment.end,
+                )
+            }
+            (&Shape::Circle(ref a), &Shape::Circle(ref b)) => penetration::circle_circle(
+                a.centre(top_left),
+                a.radius(),
+                b.centre(other_top_left),
+                b.radius(),
+            ),
+            (&Shape::ConvexPolygon(ref polygon), &Shape::Circle(ref circle)) => {
+                penetration::circle_convex(
+                    circle.centre(other_top_left),
+                    circle.radius(),
+                    &polygon.vertices_at(top_left),
+                ).map(|translation| -translation)
+            }
+            (&Shape::ConvexPolygon(ref polygon), _) => penetration::convex_convex(
+                &polygon.vertices_at(top_left),
+                &other.convex_vertices(other_top_left)?,
+            ),
+            (&Shape::Capsule(ref capsule), &Shape::Circle(ref circle)) => {
+                penetration::circle_convex(
+                    circle.centre(other_top_left),
+                    circle.radius(),
+                    &capsule.vertices_at(top_left),
+                ).map(|translation| -translation)
+            }
+            (&Shape::Capsule(ref capsule), _) => penetration::convex_convex(
+                &capsule.vertices_at(top_left),
+                &other.convex_vertices(other_top_left)?,
+            ),
+            (&Shape::Circle(ref circle), &Shape::ConvexPolygon(ref polygon)) => {
+                penetration::circle_convex(
+                    circle.centre(top_left),
+                    circle.radius(),
+                    &polygon.vertices_at(other_top_left),
+                )
+            }
+            (&Shape::Circle(ref circle), &Shape::Capsule(ref capsule)) => {
+                penetration::circle_convex(
+                    circle.centre(top_left),
+                    circle.radius(),
+                    &capsule.vertices_at(other_top_left),
+                )
+            }
+            (_, &Shape::Circle(_))
+            | (_, &Shape::ConvexPolygon(_))
+            | (_, &Shape::Capsule(_)) => other
+                .penetration(other_top_left, self, top_left)
+                .map(|translation| -translation),
+        }
+    }
+    pub fn is_overlapping(
+        &self,
+        top_left: Vector2<f64>,
+        other: &Shape,
+        other_top_left: Vector2<f64>,
+    ) -> bool {
+        match (self, other) {
+            (&Shape::AxisAlignedRect(ref a), &Shape::AxisAlignedRect(ref b)) => {
+                a.aabb(top_left).is_overlapping(&b.aabb(other_top_left))
+            }
+            (&Shape::AxisAlignedRect(ref rect), &Shape::LineSegment(ref segment)) => {
+                segment.is_intersecting_aabb(
+                    other_top_left,
+                    &rect.aabb(top_left).inset(OVERLAP_EPSILON),
+                )
+            }
+            (&Shape::LineSegment(_), &Shape::AxisAlignedRect(_)) => {
+                other.is_overlapping(other_top_left, self, top_left)
+            }
+            (&Shape::LineSegment(ref a), &Shape::LineSegment(ref b)) => {
+                a.is_crossing(top_left, b, other_top_left)
+            }
+            (&Shape::Circle(_), _)
+            | (_, &Shape::Circle(_))
+            | (&Shape::ConvexPolygon(_), _)
+            | (_, &Shape::ConvexPolygon(_))
+            | (&Shape::Capsule(_), _)
+            | (_, &Shape::Capsule(_)) => self.penetration(
+                top_left,
+                other,
+                other_top_left,
+            ).is_some(),
         }
     }
+    pub fn raycast(
+        &self,
+        top_left: Vector2<f64>,
+        origin: Vector2<f64>,
+        direction: Vector2<f64>,
+        max_distance: f64,
+    ) -> Option<(f64, Vector2<f64>)> {
+        match self {
+            &Shape::AxisAlignedRect(ref rect) => {
+                raycast::aabb(&rect.aabb(top_left), origin, direction, max_distance)
+            }
+            &Shape::LineSegment(ref line_segment) => {
+                let line_segment = line_segment.add_vector(top_left);
+                raycast::line_segment(
+                    line_segment.start,
+                    line_segment.end,
+                    origin,
+                    direction,
+                    max_distance,
+                )
+            }
+            &Shape::Circle(ref circle) => raycast::circle(
+                circle.centre(top_left),
+                circle.radius(),
+                origin,
+                direction,
+                max_distance,
+            ),
+            &Shape::ConvexPolygon(ref polygon) => raycast::convex(
+                &polygon.vertices_at(top_left),
+                origin,
+                direction,
+                max_distance,
+            ),
+            &Shape::Capsule(ref capsule) => raycast::convex(
+                &capsule.vertices_at(top_left),
+                origin,
+                direction,
+                max_distance,
+            ),
+        }
+    }
+    // Line segments have no area, so never contain a point.
+    pub fn contains_point(&self, top_left: Vector2<f64>, point: Vector2<f64>) -> bool {
+        match self {
+            &Shape::AxisAlignedRect(ref rect) => {
+                let aabb = rect.aabb(top_left);
+                let top_left = aabb.top_left();
+                let bottom_right = aabb.bottom_right_coord();
+                point.x >= top_left.x && point.x <= bottom_right.x
+                    && point.y >= top_left.y && point.y <= bottom_right.y
+            }
+            &Shape::LineSegment(_) => false,
+            &Shape::Circle(ref circle) => {
+                (point - circle.centre(top_left)).magnitude2()
+                    <= circle.radius() * circle.radius()
+            }
+            &Shape::ConvexPolygon(ref polygon) => {
+                is_point_in_convex(&polygon.vertices_at(top_left), point)
+            }
+            &Shape::Capsule(ref capsule) => {
+                is_point_in_convex(&capsule.vertices_at(top_left), point)
+            }
+        }
+    }
+    pub fn is_intersecting_aabb(&self, top_left: Vector2<f64>, aabb: &Aabb) -> bool {
+        match self {
+            &Shape::AxisAlignedRect(ref rect) => {
+                rect.aabb(top_left).is_intersecting(aabb)
+            }
+            &Shape::LineSegment(ref line_segment) => {
+                line_segment.is_intersecting_aabb(top_left, aabb)
+            }
+            &Shape::Circle(ref circle) => circle.is_intersecting_aabb(top_left, aabb),
+            &Shape::ConvexPolygon(ref polygon) => {
+                polygon.aabb(top_left).is_intersecting(aabb)
+                    && penetration::convex_convex(
+                        &polygon.vertices_at(top_left),
+                        &aabb.corners(),
+                    ).is_some()
+            }
+            &Shape::Capsule(ref capsule) => {
+                capsule.aabb(top_left).is_intersecting(aabb)
+                    && penetration::convex_convex(
+                        &capsule.vertices_at(top_left),
+                        &aabb.corners(),
+                    ).is_some()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec2;
+
+    #[test]
+    fn convex_polygon_contains_points_inside_only() {
+        let ramp = Shape::ConvexPolygon(
+            ConvexPolygon::from_vertices(vec![
+                vec2(0., 100.),
+                vec2(100., 0.),
+                vec2(100., 100.),
+            ]).unwrap(),
+        );
+        let top_left = vec2(50., 50.);
+        assert!(ramp.contains_point(top_left, vec2(140., 140.)));
+        assert!(ramp.contains_point(top_left, vec2(100., 100.)));
+        // Inside the bounding box, but above the slope.
+        assert!(!ramp.contains_point(top_left, vec2(60., 60.)));
+        assert!(!ramp.contains_point(top_left, vec2(200., 100.)));
+    }
+
+    #[test]
+    fn capsule_contains_points_inside_only() {
+        let capsule = Shape::Capsule(Capsule::new(20., 10.));
+        let top_left = vec2(0., 0.);
+        assert!(capsule.contains_point(top_left, vec2(10., 30.)));
+        assert!(capsule.contains_point(top_left, vec2(1., 30.)));
+        assert!(capsule.contains_point(top_left, vec2(10., 1.)));
+        // The corners of the bounding box are outside the rounded caps.
+        assert!(!capsule.contains_point(top_left, vec2(1., 1.)));
+        assert!(!capsule.contains_point(top_left, vec2(19., 59.)));
+    }
 }
@@ -0,0 +1,88 @@
+use aabb::Aabb;
+use cgmath::{vec2, Vector2};
+use game::RenderUpdate;
+use shape::Shape;
+
+fn colour_to_rgb(colour: [f32; 3]) -> String {
+    let channel = |c: f32| (c.max(0.).min(1.) * 255.).round() as u8;
+    format!(
+        "rgb({},{},{})",
+        channel(colour[0]),
+        channel(colour[1]),
+        channel(colour[2])
+    )
+}
+
+fn polygon_element(vertices: &[Vector2<f64>], colour: &str) -> String {
+    let points = vertices
+        .iter()
+        .map(|vertex| format!("{},{}", vertex.x, vertex.y))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("<polygon points=\"{}\" fill=\"{}\"/>\n", points, colour)
+}
+
+pub fn render<'a, I>(render_updates: I) -> String
+where
+    I: IntoIterator<Item = RenderUpdate<'a>>,
+{
+    let render_updates = render_updates.into_iter().collect::<Vec<_>>();
+    let bounds = render_updates
+        .iter()
+        .map(|update| update.shape.aabb(update.position))
+        .fold(None, |bounds: Option<Aabb>, aabb| match bounds {
+            Some(bounds) => Some(bounds.union(&aabb)),
+            None => Some(aabb),
+        })
+        .unwrap_or(Aabb::new(vec2(0., 0.), vec2(0., 0.)));
+    let top_left = bounds.top_left();
+    let size = bounds.size();
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+        top_left.x, top_left.y, size.x, size.y
+    );
+    for update in render_updates.iter() {
+        let colour = colour_to_rgb(update.colour);
+        let element = match update.shape {
+            &Shape::AxisAlignedRect(ref rect) => {
+                let dimensions = rect.dimensions();
+                format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                    update.position.x,
+                    update.position.y,
+                    dimensions.x,
+                    dimensions.y,
+                    colour
+                )
+            }
+            &Shape::LineSegment(ref line_segment) => {
+                let start = update.position + line_segment.start;
+                let end = update.position + line_segment.end;
+                format!(
+                    "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\"/>\n",
+                    start.x, start.y, end.x, end.y, colour
+                )
+            }
+            &Shape::Circle(ref circle) => {
+                let centre = circle.centre(update.position);
+                format!(
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>\n",
+                    centre.x,
+                    centre.y,
+                    circle.radius(),
+                    colour
+                )
+            }
+            &Shape::ConvexPolygon(ref polygon) => {
+                polygon_element(&polygon.vertices_at(update.position), &colour)
+            }
+            &Shape::Capsule(ref capsule) => {
+                polygon_element(&capsule.vertices_at(update.position), &colour)
+            }
+        };
+        svg.push_str(&element);
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
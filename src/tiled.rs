@@ -0,0 +1,218 @@
+use axis_aligned_rect::AxisAlignedRect;
+use binary_level::StaticEntity;
+use cgmath::{vec2, Vector2};
+use line_segment::LineSegment;
+use shape::Shape;
+use xml::attribute::OwnedAttribute;
+use xml::reader::{EventReader, XmlEvent};
+
+const RECT_COLOUR: [f32; 3] = [1., 1., 0.];
+const ONE_WAY_COLOUR: [f32; 3] = [1., 1., 1.];
+const POLYLINE_COLOUR: [f32; 3] = [0., 1., 1.];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Xml(String),
+    MissingAttribute(&'static str),
+    InvalidNumber(String),
+    InvalidPoints(String),
+}
+
+struct Object {
+    position: Vector2<f64>,
+    dimensions: Vector2<f64>,
+    polyline: Option<Vec<Vector2<f64>>>,
+    one_way: bool,
+}
+
+fn attribute<'a>(
+    attributes: &'a [OwnedAttribute],
+    name: &'static str,
+) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|attribute| attribute.name.local_name == name)
+        .map(|attribute| attribute.value.as_str())
+}
+
+fn number(value: &str) -> Result<f64, Error> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| Error::InvalidNumber(value.to_string()))
+}
+
+fn optional_number(
+    attributes: &[OwnedAttribute],
+    name: &'static str,
+) -> Result<f64, Error> {
+    attribute(attributes, name).map(number).unwrap_or(Ok(0.))
+}
+
+fn points(value: &str) -> Result<Vec<Vector2<f64>>, Error> {
+    value
+        .split_whitespace()
+        .map(|point| {
+            let mut coords = point.split(',');
+            match (coords.next(), coords.next(), coords.next()) {
+                (Some(x), Some(y), None) => Ok(vec2(number(x)?, number(y)?)),
+                _ => Err(Error::InvalidPoints(value.to_string())),
+            }
+        })
+        .collect()
+}
+
+fn object_entities(object: Object, entities: &mut Vec<StaticEntity>) {
+    match object.polyline {
+        Some(points) => for pair in points.windows(2) {
+            let line_segment = if object.one_way {
+                LineSegment::new_one_way_from(pair[0], pair[1], vec2(0., -1.))
+            } else {
+                LineSegment::new_both_solid(pair[0], pair[1])
+            };
+            entities.push(StaticEntity {
+                position: object.position,
+                shape: Shape::LineSegment(line_segment),
+                colour: if object.one_way {
+                    ONE_WAY_COLOUR
+                } else {
+                    POLYLINE_COLOUR
+                },
+            });
+        },
+        None => {
+            if object.dimensions.x <= 0. || object.dimensions.y <= 0. {
+                return;
+            }
+            let (rect, colour) = if object.one_way {
+                (AxisAlignedRect::new_floor_only(object.dimensions), ONE_WAY_COLOUR)
+            } else {
+                (AxisAlignedRect::new(object.dimensions), RECT_COLOUR)
+            };
+            entities.push(StaticEntity {
+                position: object.position,
+                shape: Shape::AxisAlignedRect(rect),
+                colour,
+            });
+        }
+    }
+}
+
+pub fn parse(tmx: &str) -> Result<Vec<StaticEntity>, Error> {
+    let mut entities = Vec::new();
+    let mut in_object_group = false;
+    let mut object: Option<Object> = None;
+    for event in EventReader::from_str(tmx) {
+        match event.map_err(|e| Error::Xml(e.to_string()))? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "objectgroup" => in_object_group = true,
+                "object" if in_object_group => {
+                    object = Some(Object {
+                        position: vec2(
+                            optional_number(&attributes, "x")?,
+                            optional_number(&attributes, "y")?,
+                        ),
+                        dimensions: vec2(
+                            optional_number(&attributes, "width")?,
+                            optional_number(&attributes, "height")?,
+                        ),
+                        polyline: None,
+                        one_way: false,
+                    });
+                }
+                "polyline" => if let Some(ref mut object) = object {
+                    let value = attribute(&attributes, "points")
+                        .ok_or(Error::MissingAttribute("points"))?;
+                    object.polyline = Some(points(value)?);
+                },
+                "property" => if let Some(ref mut object) = object {
+                    let name = attribute(&attributes, "name")
+                        .ok_or(Error::MissingAttribute("name"))?;
+                    let value = attribute(&attributes, "value").unwrap_or("");
+                    if name == "oneway" && value == "true" {
+                        object.one_way = true;
+                    }
+                },
+                _ => (),
+            },
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "objectgroup" => in_object_group = false,
+                "object" => if let Some(object) = object.take() {
+                    object_entities(object, &mut entities);
+                },
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+    Ok(entities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.0" orientation="orthogonal" width="30" height="20">
+ <objectgroup name="solids">
+  <object id="1" x="0" y="600" width="960" height="40"/>
+  <object id="2" x="200" y="450" width="100" height="20">
+   <properties>
+    <property name="oneway" type="bool" value="true"/>
+   </properties>
+  </object>
+  <object id="3" x="400" y="300">
+   <polyline points="0,0 50,-20 100,0"/>
+  </object>
+ </objectgroup>
+</map>
+"#;
+
+    #[test]
+    fn parse_object_layer() {
+        let entities = parse(TMX).unwrap();
+        assert_eq!(entities.len(), 4);
+        assert_eq!(entities[0].position, vec2(0., 600.));
+        assert_eq!(
+            entities[0].shape,
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(960., 40.)))
+        );
+        assert_eq!(entities[0].colour, RECT_COLOUR);
+        assert_eq!(entities[1].position, vec2(200., 450.));
+        assert_eq!(
+            entities[1].shape,
+            Shape::AxisAlignedRect(AxisAlignedRect::new_floor_only(vec2(100., 20.)))
+        );
+        assert_eq!(entities[1].colour, ONE_WAY_COLOUR);
+        for entity in &entities[2..] {
+            assert_eq!(entity.position, vec2(400., 300.));
+            assert_eq!(entity.colour, POLYLINE_COLOUR);
+        }
+        assert_eq!(
+            entities[2].shape,
+            Shape::LineSegment(LineSegment::new_both_solid(
+                vec2(0., 0.),
+                vec2(50., -20.),
+            ))
+        );
+        assert_eq!(
+            entities[3].shape,
+            Shape::LineSegment(LineSegment::new_both_solid(
+                vec2(50., -20.),
+                vec2(100., 0.),
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse("<map>").is_err());
+        let bad = r#"<map><objectgroup><object x="a"/></objectgroup></map>"#;
+        assert_eq!(
+            parse(bad).err(),
+            Some(Error::InvalidNumber("a".to_string()))
+        );
+    }
+}